@@ -1,65 +1,173 @@
+mod prompt;
+
 use std::{
     env, fs,
     io::{self, BufRead},
     os::unix::net::UnixStream,
+    path::PathBuf,
 };
 
-use enquote::unquote;
 use getopts::Options;
 use nix::sys::utsname::uname;
 use rpassword::prompt_password_stderr;
 
-use greetd_ipc::{codec::SyncCodec, AuthMessageType, ErrorType, Request, Response};
+use greetd_ipc::{
+    codec::SyncCodec,
+    desktop::{self, DesktopEntry, DEFAULT_SESSION_DIRS},
+    issue::{get_issue, DEFAULT_ISSUE_FILE},
+    AuthMessageType, ErrorType, PowerAction, Request, Response,
+};
 
-fn maybe_unquote(s: &str) -> Result<String, Box<dyn std::error::Error>> {
-    Ok(match s.chars().next() {
-        Some('"') | Some('\'') => unquote(s)?,
-        _ => s.to_string(),
-    })
-}
+use crate::prompt::{prompt_line, Input};
 
-fn prompt_stderr(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+pub(crate) fn prompt_stderr(prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
     let stdin = io::stdin();
     let mut stdin_iter = stdin.lock().lines();
     eprint!("{}", prompt);
     Ok(stdin_iter.next().ok_or("no input")??)
 }
 
-fn get_distro_name() -> Result<String, Box<dyn std::error::Error>> {
-    let os_release = fs::read_to_string("/etc/os-release")?;
-    let parsed = inish::parse(&os_release)?;
-    let general = parsed.get("").ok_or("no general section")?;
-    maybe_unquote(general.get("PRETTY_NAME").ok_or("no pretty name")?)
+fn get_last_user_path() -> Option<PathBuf> {
+    let mut path = match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let mut home = PathBuf::from(env::var_os("HOME")?);
+            home.push(".cache");
+            home
+        }
+    };
+    path.push("agreety");
+    path.push("last-user");
+    Some(path)
+}
+
+fn get_last_user() -> Option<String> {
+    let user = fs::read_to_string(get_last_user_path()?).ok()?;
+    let user = user.trim();
+    if user.is_empty() {
+        None
+    } else {
+        Some(user.to_string())
+    }
 }
 
-fn get_issue() -> Result<String, Box<dyn std::error::Error>> {
-    let vtnr: usize = env::var("XDG_VTNR")
-        .unwrap_or_else(|_| "0".to_string())
-        .parse()
-        .expect("unable to parse VTNR");
-    let uts = uname()?;
-    Ok(fs::read_to_string("/etc/issue")?
-        .replace(
-            "\\S",
-            &get_distro_name().unwrap_or_else(|_| "Linux".to_string()),
-        )
-        .replace("\\l", &format!("tty{}", vtnr))
-        .replace("\\s", uts.sysname().to_str().unwrap())
-        .replace("\\r", uts.release().to_str().unwrap())
-        .replace("\\v", uts.version().to_str().unwrap())
-        .replace("\\n", uts.nodename().to_str().unwrap())
-        .replace("\\m", uts.machine().to_str().unwrap())
-        .replace("\\\\", "\\"))
+fn set_last_user(user: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let path = get_last_user_path().ok_or("no cache directory")?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, format!("{}\n", user))?;
+    Ok(())
 }
 
 enum LoginResult {
-    Success,
-    Failure,
+    Success(String),
+    Failure(&'static str),
+    PowerAction,
+}
+
+fn power_action(action: PowerAction) -> Result<bool, Box<dyn std::error::Error>> {
+    let question = match action {
+        PowerAction::Poweroff => "Power off? [y/N] ",
+        PowerAction::Reboot => "Reboot? [y/N] ",
+    };
+    match prompt_stderr(question)?.trim() {
+        "y" | "Y" => (),
+        _ => return Ok(false),
+    }
+
+    let mut stream = UnixStream::connect(env::var("GREETD_SOCK")?)?;
+    Request::PowerAction { action }.write_to(&mut stream)?;
+    match Response::read_from(&mut stream)? {
+        Response::Error {
+            error_type: ErrorType::Inhibited,
+            description,
+        } => {
+            eprintln!("Blocked by {}", description);
+            Ok(false)
+        }
+        Response::Error { description, .. } => {
+            eprintln!("error: {}", description);
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
+
+fn get_banner(stream: &mut UnixStream) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    Request::GetBanner.write_to(stream)?;
+    match Response::read_from(stream)? {
+        Response::Banner { banner } => Ok(banner),
+        Response::Error { description, .. } => Err(description.into()),
+        resp => Err(format!("unexpected response: {:?}", resp).into()),
+    }
+}
+
+fn choose_session(
+    sessions: &[DesktopEntry],
+    cmd: &Option<String>,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    // The configured command, if any, is the default choice. Otherwise, the
+    // first session is.
+    let offset = if cmd.is_some() { 1 } else { 0 };
+    eprintln!("Sessions:");
+    if let Some(cmd) = cmd {
+        eprintln!("  0) {}", cmd);
+    }
+    for (idx, session) in sessions.iter().enumerate() {
+        eprintln!("  {}) {}", idx + offset, session.name);
+    }
+    let default = if offset == 1 { 0 } else { 1 };
+    loop {
+        let choice = prompt_stderr(&format!("Session [{}]: ", default))?;
+        let choice = match choice.trim() {
+            "" => default,
+            v => match v.parse::<usize>() {
+                Ok(v) => v,
+                Err(_) => {
+                    eprintln!("Invalid choice: {}", v);
+                    continue;
+                }
+            },
+        };
+        match (choice, cmd) {
+            (0, Some(cmd)) => return Ok(Some(cmd.clone())),
+            (idx, _) if idx >= offset && idx - offset < sessions.len() => {
+                return Ok(Some(sessions[idx - offset].command_line()))
+            }
+            (idx, _) => eprintln!("Invalid choice: {}", idx),
+        }
+    }
 }
 
-fn login(node: &str, cmd: &mut Option<String>) -> Result<LoginResult, Box<dyn std::error::Error>> {
+fn login(
+    node: &str,
+    cmd: &mut Option<String>,
+    sessions: &[DesktopEntry],
+    last_user: &Option<String>,
+) -> Result<LoginResult, Box<dyn std::error::Error>> {
     let username = loop {
-        let username = prompt_stderr(&format!("{} login: ", node))?;
+        let prompt = match last_user {
+            Some(last_user) => format!("{} login [{}]: ", node, last_user),
+            None => format!("{} login: ", node),
+        };
+        let username = match prompt_line(&prompt)? {
+            Input::Line(username) => match last_user {
+                Some(last_user) if username.is_empty() => last_user.clone(),
+                _ => username,
+            },
+            Input::FunctionKey(key) => {
+                let action = match key {
+                    2 => PowerAction::Reboot,
+                    3 => PowerAction::Poweroff,
+                    _ => continue,
+                };
+                if power_action(action)? {
+                    return Ok(LoginResult::PowerAction);
+                }
+                continue;
+            }
+        };
         if let Some(u) = username.strip_prefix('!') {
             *cmd = Some(u.to_string());
             eprintln!("Login command changed to: {u}");
@@ -68,9 +176,26 @@ fn login(node: &str, cmd: &mut Option<String>) -> Result<LoginResult, Box<dyn st
         break username;
     };
 
+    let session_cmd = if sessions.is_empty() {
+        cmd.clone()
+    } else {
+        choose_session(sessions, cmd)?
+    };
+
     let mut stream = UnixStream::connect(env::var("GREETD_SOCK")?)?;
 
-    let mut next_request = Request::CreateSession { username };
+    // The banner was displayed at startup, but greetd may require it to be
+    // retrieved on every connection that creates a session. If it cannot be
+    // retrieved, greetd refuses the session if it required the banner.
+    if let Err(e) = get_banner(&mut stream) {
+        eprintln!("unable to get banner: {}", e);
+    }
+
+    let mut next_request = Request::CreateSession {
+        username: username.clone(),
+        concurrent_auth: false,
+        locale: None,
+    };
     let mut starting = false;
     loop {
         next_request.write_to(&mut stream)?;
@@ -95,18 +220,23 @@ fn login(node: &str, cmd: &mut Option<String>) -> Result<LoginResult, Box<dyn st
 
                 next_request = Request::PostAuthMessageResponse { response };
             }
+            Response::PasswordChangeRequired => {
+                eprintln!("Your password has expired and must be changed.");
+                next_request = Request::PostAuthMessageResponse { response: None };
+            }
             Response::Success => {
                 if starting {
-                    return Ok(LoginResult::Success);
+                    return Ok(LoginResult::Success(username));
                 } else {
                     starting = true;
-                    let command = match cmd {
+                    let command = match &session_cmd {
                         Some(cmd) => cmd.clone(),
                         None => prompt_stderr("Command: ")?,
                     };
                     next_request = Request::StartSession {
                         env: vec![],
                         cmd: vec![command.to_string()],
+                        session_type: None,
                     }
                 }
             }
@@ -116,12 +246,30 @@ fn login(node: &str, cmd: &mut Option<String>) -> Result<LoginResult, Box<dyn st
             } => {
                 Request::CancelSession.write_to(&mut stream)?;
                 match error_type {
-                    ErrorType::AuthError => return Ok(LoginResult::Failure),
-                    ErrorType::Error => {
+                    ErrorType::AuthError => return Ok(LoginResult::Failure("Login incorrect")),
+                    ErrorType::AccountExpired => {
+                        return Ok(LoginResult::Failure("Account expired"))
+                    }
+                    ErrorType::AccountLocked => return Ok(LoginResult::Failure("Account locked")),
+                    ErrorType::PasswordExpired => {
+                        return Ok(LoginResult::Failure("Password expired"))
+                    }
+                    ErrorType::CommandNotAllowed => {
+                        return Ok(LoginResult::Failure("Command not allowed"))
+                    }
+                    ErrorType::AlreadyLoggedIn => {
+                        return Ok(LoginResult::Failure("Already logged in elsewhere"))
+                    }
+                    ErrorType::Error
+                    | ErrorType::Inhibited
+                    | ErrorType::PamError
+                    | ErrorType::IoError
+                    | ErrorType::ProtocolError => {
                         return Err(format!("login error: {:?}", description).into())
                     }
                 }
             }
+            resp => return Err(format!("unexpected response: {:?}", resp).into()),
         }
     }
 }
@@ -143,6 +291,23 @@ fn main() {
         "maximum number of accepted failures",
         "FAILURES",
     );
+    opts.optopt(
+        "s",
+        "session-dirs",
+        "colon-separated list of directories with session desktop entries",
+        "DIRS",
+    );
+    opts.optopt(
+        "i",
+        "issue-file",
+        "issue file to show before the login prompt",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "no-remember",
+        "do not remember and suggest the last logged in user",
+    );
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
         Err(f) => {
@@ -165,15 +330,52 @@ fn main() {
         }
     };
 
-    if let Ok(issue) = get_issue() {
+    let session_dirs = matches
+        .opt_str("session-dirs")
+        .unwrap_or_else(|| DEFAULT_SESSION_DIRS.to_string());
+    let sessions: Vec<DesktopEntry> = desktop::entries(&session_dirs)
+        .into_iter()
+        .filter(|entry| !entry.no_display && entry.installed())
+        .collect();
+
+    let issue_file = matches
+        .opt_str("issue-file")
+        .unwrap_or_else(|| DEFAULT_ISSUE_FILE.to_string());
+    let vt = env::var("XDG_VTNR").ok().and_then(|vt| vt.parse().ok());
+    if let Ok(issue) = get_issue(&issue_file, vt) {
         print!("{}", issue);
     }
+    match UnixStream::connect(env::var("GREETD_SOCK").unwrap_or_default())
+        .map_err(|e| e.into())
+        .and_then(|mut stream| get_banner(&mut stream))
+    {
+        Ok(Some(banner)) => println!("{}\n", banner.trim_end()),
+        Ok(None) => (),
+        Err(e) => eprintln!("unable to get banner: {}", e),
+    }
+    eprintln!("F2: reboot, F3: power off\n");
+
+    let remember = !matches.opt_present("no-remember");
+    let last_user = if remember { get_last_user() } else { None };
 
     let uts = uname().unwrap();
     for _ in 0..max_failures {
-        match login(uts.nodename().to_str().unwrap(), &mut cmd) {
-            Ok(LoginResult::Success) => break,
-            Ok(LoginResult::Failure) => eprintln!("Login incorrect\n"),
+        match login(
+            uts.nodename().to_str().unwrap(),
+            &mut cmd,
+            &sessions,
+            &last_user,
+        ) {
+            Ok(LoginResult::Success(username)) => {
+                if remember {
+                    if let Err(e) = set_last_user(&username) {
+                        eprintln!("unable to remember user: {}", e);
+                    }
+                }
+                break;
+            }
+            Ok(LoginResult::PowerAction) => break,
+            Ok(LoginResult::Failure(msg)) => eprintln!("{}\n", msg),
             Err(e) => {
                 eprintln!("error: {}", e);
                 std::process::exit(1);
@@ -0,0 +1,133 @@
+use std::io::{self, Read, Write};
+
+use nix::sys::termios::{
+    tcgetattr, tcsetattr, LocalFlags, SetArg, SpecialCharacterIndices, Termios,
+};
+
+/// A completed line of input, or a function key pressed while typing it.
+pub enum Input {
+    Line(String),
+    FunctionKey(u8),
+}
+
+// Puts stdin into non-canonical mode without echo, restoring the original
+// settings when dropped.
+struct RawMode {
+    orig: Termios,
+}
+
+impl RawMode {
+    fn enable() -> nix::Result<RawMode> {
+        let orig = tcgetattr(io::stdin())?;
+        let mut raw = orig.clone();
+        raw.local_flags
+            .remove(LocalFlags::ICANON | LocalFlags::ECHO);
+        raw.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+        raw.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+        tcsetattr(io::stdin(), SetArg::TCSANOW, &raw)?;
+        Ok(RawMode { orig })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = tcsetattr(io::stdin(), SetArg::TCSANOW, &self.orig);
+    }
+}
+
+fn read_byte(stdin: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    stdin.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+// Decode the remainder of an escape sequence, returning the function key
+// number if it was one. Handles the sequences used by the Linux console
+// (ESC [ [ A), xterm (ESC O P) and vt220 (ESC [ 1 1 ~).
+fn read_escape(stdin: &mut impl Read) -> io::Result<Option<u8>> {
+    match read_byte(stdin)? {
+        b'O' => Ok(match read_byte(stdin)? {
+            c @ b'P'..=b'S' => Some(c - b'P' + 1),
+            _ => None,
+        }),
+        b'[' => match read_byte(stdin)? {
+            b'[' => Ok(match read_byte(stdin)? {
+                c @ b'A'..=b'E' => Some(c - b'A' + 1),
+                _ => None,
+            }),
+            c @ b'0'..=b'9' => {
+                let mut num = (c - b'0') as u32;
+                loop {
+                    match read_byte(stdin)? {
+                        c @ b'0'..=b'9' => num = num * 10 + (c - b'0') as u32,
+                        b'~' => break,
+                        _ => return Ok(None),
+                    }
+                }
+                Ok(match num {
+                    11..=15 => Some((num - 10) as u8),
+                    17..=21 => Some((num - 11) as u8),
+                    23 | 24 => Some((num - 12) as u8),
+                    _ => None,
+                })
+            }
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Prompt for a line of visible input on stderr, returning early if a
+/// function key is pressed. Falls back to plain line reading if stdin is not a
+/// terminal.
+pub fn prompt_line(prompt: &str) -> Result<Input, Box<dyn std::error::Error>> {
+    let _raw = match RawMode::enable() {
+        Ok(raw) => raw,
+        Err(_) => return Ok(Input::Line(crate::prompt_stderr(prompt)?)),
+    };
+
+    let mut stdin = io::stdin().lock();
+    let mut stderr = io::stderr();
+    let mut line: Vec<u8> = Vec::new();
+    write!(stderr, "{}", prompt)?;
+    loop {
+        match read_byte(&mut stdin)? {
+            b'\r' | b'\n' => {
+                writeln!(stderr)?;
+                return Ok(Input::Line(String::from_utf8(line)?));
+            }
+            // Backspace and delete
+            0x7F | 0x08 => {
+                // Remove an entire UTF-8 character.
+                while let Some(c) = line.pop() {
+                    if c & 0xC0 != 0x80 {
+                        write!(stderr, "\x08 \x08")?;
+                        break;
+                    }
+                }
+            }
+            // ^U
+            0x15 => {
+                while let Some(c) = line.pop() {
+                    if c & 0xC0 != 0x80 {
+                        write!(stderr, "\x08 \x08")?;
+                    }
+                }
+            }
+            // ^D
+            0x04 if line.is_empty() => return Err("no input".into()),
+            0x1B => {
+                if let Some(key) = read_escape(&mut stdin)? {
+                    writeln!(stderr)?;
+                    return Ok(Input::FunctionKey(key));
+                }
+            }
+            c if c < 0x20 => (),
+            c => {
+                line.push(c);
+                stderr.write_all(&[c])?;
+            }
+        }
+        stderr.flush()?;
+    }
+}
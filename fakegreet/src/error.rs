@@ -43,6 +43,12 @@ impl From<greetd_ipc::codec::Error> for Error {
     }
 }
 
+impl From<serde_json::error::Error> for Error {
+    fn from(error: serde_json::error::Error) -> Self {
+        Error::ProtocolError(error.to_string())
+    }
+}
+
 impl From<String> for Error {
     fn from(error: String) -> Self {
         Error::Error(error)
@@ -0,0 +1,67 @@
+//! Fault injection, for testing how greeters deal with a misbehaving daemon.
+//!
+//! Faults are applied to the Nth request received on each connection,
+//! counting from 1, so that the same fault is hit again when a greeter
+//! reconnects.
+
+use std::time::Duration;
+
+use tokio::{io::AsyncWriteExt, net::UnixStream, time::sleep};
+
+use crate::error::Error;
+use greetd_ipc::{codec::TokioCodec, ErrorType, Response};
+
+#[derive(Default)]
+pub struct Faults {
+    /// Delay before every response.
+    pub delay: Duration,
+
+    /// Replace the response to this request with an error.
+    pub error_on: Option<usize>,
+
+    /// The type of error to inject.
+    pub error_type: Option<ErrorType>,
+
+    /// Send only half of the response to this request, then disconnect.
+    pub truncate_on: Option<usize>,
+
+    /// Disconnect instead of responding to this request.
+    pub disconnect_on: Option<usize>,
+}
+
+impl Faults {
+    /// Replace the response to the nth request with an error if requested.
+    pub fn apply(&self, n: usize, resp: Response) -> Response {
+        if self.error_on != Some(n) {
+            return resp;
+        }
+        println!("injecting error");
+        Response::Error {
+            error_type: self.error_type.clone().unwrap_or(ErrorType::Error),
+            description: "injected error".to_string(),
+        }
+    }
+
+    /// Send the response to the nth request, applying delay, truncation and
+    /// disconnects. Returns false if the connection should be closed.
+    pub async fn send(&self, n: usize, resp: Response, s: &mut UnixStream) -> Result<bool, Error> {
+        if !self.delay.is_zero() {
+            sleep(self.delay).await;
+        }
+        if self.disconnect_on == Some(n) {
+            println!("injecting disconnect");
+            return Ok(false);
+        }
+        println!("resp: {:?}", resp);
+        if self.truncate_on == Some(n) {
+            println!("injecting truncated response");
+            let body_bytes = serde_json::to_vec(&resp)?;
+            let len_bytes = (body_bytes.len() as u32).to_ne_bytes();
+            s.write_all(&len_bytes).await?;
+            s.write_all(&body_bytes[..body_bytes.len() / 2]).await?;
+            return Ok(false);
+        }
+        resp.write_to(s).await?;
+        Ok(true)
+    }
+}
@@ -1,7 +1,10 @@
 mod error;
+mod faults;
+mod scenario;
 
-use std::{cell::RefCell, env, rc::Rc, time::Duration};
+use std::{cell::RefCell, collections::VecDeque, env, rc::Rc, time::Duration};
 
+use getopts::Options;
 use tokio::{
     net::{UnixListener, UnixStream},
     process::Command,
@@ -9,10 +12,10 @@ use tokio::{
     time::sleep,
 };
 
-use crate::error::Error;
+use crate::{error::Error, faults::Faults, scenario::Step};
 use greetd_ipc::{
     codec::{Error as CodecError, TokioCodec},
-    AuthMessageType, ErrorType, Request, Response,
+    AuthMessageType, ErrorType, HostInfo, Request, Response, UserInfo,
 };
 
 fn wrap_result<T>(res: Result<T, Error>) -> Response {
@@ -33,6 +36,9 @@ struct InnerContext {
     user: Option<String>,
     password: Option<String>,
     ok: bool,
+    // Whether the session was created without a username, like greetd does
+    // when PAM is left to determine the user.
+    user_asked: bool,
 }
 
 struct Context {
@@ -46,15 +52,27 @@ impl Context {
                 user: None,
                 password: None,
                 ok: false,
+                user_asked: false,
             }),
         }
     }
 
     async fn create_session(&self, username: String) -> Result<(), Error> {
-        self.inner.borrow_mut().user = Some(username);
+        let mut s = self.inner.borrow_mut();
+        s.user_asked = username.is_empty();
+        s.user = Some(username).filter(|u| !u.is_empty());
         Ok(())
     }
 
+    fn authenticated_user(&self) -> Option<String> {
+        let s = self.inner.borrow();
+        if s.user_asked {
+            s.user.clone()
+        } else {
+            None
+        }
+    }
+
     async fn get_question(&self) -> Result<Option<(AuthMessageType, String)>, Error> {
         let s = self.inner.borrow();
         if s.ok {
@@ -103,6 +121,7 @@ impl Context {
         s.user = None;
         s.password = None;
         s.ok = false;
+        s.user_asked = false;
         Ok(())
     }
 }
@@ -113,12 +132,16 @@ async fn client_get_question(ctx: &Context) -> Response {
             auth_message_type,
             auth_message,
         },
+        Ok(None) => match ctx.authenticated_user() {
+            Some(username) => Response::Authenticated { username },
+            None => Response::Success,
+        },
         res => wrap_result(res),
     }
 }
 
-async fn client_handler(ctx: &Context, mut s: UnixStream) -> Result<(), Error> {
-    loop {
+async fn client_handler(ctx: &Context, faults: &Faults, mut s: UnixStream) -> Result<(), Error> {
+    for n in 1.. {
         let req = match Request::read_from(&mut s).await {
             Ok(req) => req,
             Err(CodecError::Eof) => return Ok(()),
@@ -127,7 +150,7 @@ async fn client_handler(ctx: &Context, mut s: UnixStream) -> Result<(), Error> {
 
         println!("req: {:?}", req);
         let resp = match req {
-            Request::CreateSession { username } => match ctx.create_session(username).await {
+            Request::CreateSession { username, .. } => match ctx.create_session(username).await {
                 Ok(()) => client_get_question(ctx).await,
                 res => wrap_result(res),
             },
@@ -137,20 +160,55 @@ async fn client_handler(ctx: &Context, mut s: UnixStream) -> Result<(), Error> {
                     res => wrap_result(res),
                 }
             }
-            Request::StartSession { cmd, env: _ } => wrap_result(ctx.start(cmd).await),
+            Request::StartSession { cmd, .. } => wrap_result(ctx.start(cmd).await),
+            Request::StartDesktopSession { id, env: _ } => wrap_result(ctx.start(vec![id]).await),
             Request::CancelSession => wrap_result(ctx.cancel().await),
+            Request::PowerAction { .. } | Request::Upgrade => Response::Success,
+            Request::GetMetrics => Response::Metrics {
+                metrics: Default::default(),
+            },
+            Request::GetSessionHistory => Response::SessionHistory { sessions: vec![] },
+            Request::GetSessions => Response::Sessions { sessions: vec![] },
+            Request::GetBanner => Response::Banner { banner: None },
+            Request::GetDefaultSession => Response::DefaultSession { session: None },
+            Request::GetUserInfo { username } => Response::UserInfo {
+                info: UserInfo {
+                    username,
+                    real_name: None,
+                    icon: None,
+                    session: None,
+                    language: None,
+                },
+            },
+            Request::GetHostInfo => Response::HostInfo {
+                info: HostInfo {
+                    hostname: "fakegreet".to_string(),
+                    os_release: Default::default(),
+                    kernel: "fake".to_string(),
+                    issue: None,
+                },
+            },
+            Request::GetGreeterState { .. } => Response::GreeterState { value: None },
+            Request::SetGreeterState { .. } => Response::Success,
         };
+        let resp = faults.apply(n, resp);
 
         if let Response::Error { .. } = resp {
             ctx.cancel().await?;
         }
 
-        println!("resp: {:?}", resp);
-        resp.write_to(&mut s).await?;
+        if !faults.send(n, resp, &mut s).await? {
+            return Ok(());
+        }
     }
+    Ok(())
 }
 
-pub async fn server() -> Result<(), Error> {
+pub async fn server(
+    cmd: String,
+    scenario: Option<VecDeque<Step>>,
+    faults: Faults,
+) -> Result<(), Error> {
     let mut path = env::current_dir()?;
     let current_dir = format!(
         "{}/greetd.sock",
@@ -164,17 +222,21 @@ pub async fn server() -> Result<(), Error> {
     let listener =
         UnixListener::bind(&path).map_err(|e| format!("unable to open listener: {}", e))?;
 
-    let arg = env::args().nth(1).expect("need argument");
-    let _ = Command::new("sh").arg("-c").arg(arg).spawn()?;
+    let greeter = Command::new("sh").arg("-c").arg(cmd).spawn()?;
+    if let Some(steps) = scenario {
+        return scenario::serve(steps, listener, greeter, &faults).await;
+    }
 
     let ctx = Rc::new(Context::new());
+    let faults = Rc::new(faults);
 
     loop {
         match listener.accept().await {
             Ok((stream, _)) => {
                 let ctx = ctx.clone();
+                let faults = faults.clone();
                 task::spawn_local(async move {
-                    if let Err(e) = client_handler(&ctx, stream).await {
+                    if let Err(e) = client_handler(&ctx, &faults, stream).await {
                         eprintln!("client loop failed: {}", e);
                     }
                 });
@@ -184,12 +246,137 @@ pub async fn server() -> Result<(), Error> {
     }
 }
 
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] [command]", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn get_faults(matches: &getopts::Matches) -> Result<Faults, Error> {
+    let number = |name: &str| -> Result<Option<usize>, Error> {
+        matches
+            .opt_str(name)
+            .map(|n| n.parse())
+            .transpose()
+            .map_err(|e| format!("unable to parse --{}: {}", name, e).into())
+    };
+    let error_type = matches
+        .opt_str("error-type")
+        .map(|t| match t.as_str() {
+            "error" => Ok(ErrorType::Error),
+            "auth_error" => Ok(ErrorType::AuthError),
+            "account_expired" => Ok(ErrorType::AccountExpired),
+            "account_locked" => Ok(ErrorType::AccountLocked),
+            "password_expired" => Ok(ErrorType::PasswordExpired),
+            "command_not_allowed" => Ok(ErrorType::CommandNotAllowed),
+            "already_logged_in" => Ok(ErrorType::AlreadyLoggedIn),
+            "inhibited" => Ok(ErrorType::Inhibited),
+            "pam_error" => Ok(ErrorType::PamError),
+            "io_error" => Ok(ErrorType::IoError),
+            "protocol_error" => Ok(ErrorType::ProtocolError),
+            _ => Err(format!("unknown error type: {}", t)),
+        })
+        .transpose()?;
+    Ok(Faults {
+        delay: Duration::from_millis(number("delay")?.unwrap_or(0) as u64),
+        error_on: number("error-on")?,
+        error_type,
+        truncate_on: number("truncate-on")?,
+        disconnect_on: number("disconnect-on")?,
+    })
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
-    let res = task::LocalSet::new()
-        .run_until(async move { server().await })
-        .await;
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt(
+        "s",
+        "scenario",
+        "check requests against a scenario file and send its responses",
+        "FILE",
+    );
+    opts.optflag(
+        "c",
+        "client",
+        "run the scenario against the daemon at GREETD_SOCK instead",
+    );
+    opts.optopt("", "delay", "delay every response", "MS");
+    opts.optopt(
+        "",
+        "error-on",
+        "respond with an error to the Nth request of a connection",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "error-type",
+        "the type of error to inject, such as error or auth_error",
+        "TYPE",
+    );
+    opts.optopt(
+        "",
+        "truncate-on",
+        "send a truncated response to the Nth request of a connection",
+        "N",
+    );
+    opts.optopt(
+        "",
+        "disconnect-on",
+        "disconnect instead of responding to the Nth request of a connection",
+        "N",
+    );
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => {
+            println!("{}", f);
+            print_usage(&program, opts);
+            std::process::exit(1);
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        std::process::exit(0);
+    }
+
+    let faults = match get_faults(&matches) {
+        Ok(faults) => faults,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let scenario = match matches.opt_str("scenario").map(|p| scenario::load(&p)) {
+        Some(Ok(steps)) => Some(steps),
+        Some(Err(e)) => {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        None => None,
+    };
+
+    let res = if matches.opt_present("client") {
+        match (scenario, env::var("GREETD_SOCK")) {
+            (Some(steps), Ok(path)) => scenario::run_client(steps, &path).await,
+            (None, _) => Err("client mode requires a scenario".into()),
+            (_, Err(_)) => Err("GREETD_SOCK not set".into()),
+        }
+    } else {
+        let cmd = match matches.free.first() {
+            Some(cmd) => cmd.clone(),
+            None => {
+                print_usage(&program, opts);
+                std::process::exit(1);
+            }
+        };
+        task::LocalSet::new()
+            .run_until(async move { server(cmd, scenario, faults).await })
+            .await
+    };
     if let Err(e) = res {
         eprintln!("error: {}", e);
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,185 @@
+//! Scripted protocol scenarios.
+//!
+//! A scenario is a JSON array of steps, each consisting of a request and a
+//! response:
+//!
+//! ```json
+//! [
+//!     {
+//!         "request": { "type": "create_session", "username": "user" },
+//!         "response": { "type": "auth_message", "auth_message_type": "secret", "auth_message": "Password:" }
+//!     },
+//!     {
+//!         "request": { "type": "post_auth_message_response", "response": "password" },
+//!         "response": { "type": "success" }
+//!     }
+//! ]
+//! ```
+//!
+//! When serving a scenario to a greeter, the requests are the expected
+//! requests and the responses are sent back verbatim. When running a scenario
+//! against a daemon, the requests are sent verbatim and the responses are the
+//! expected responses.
+//!
+//! Expectations are matched partially: every field present in the expectation
+//! must be present and equal in the actual message, but fields that are left
+//! out are not checked. This allows e.g. error descriptions to be ignored.
+
+use std::{collections::VecDeque, fs};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{
+    net::{UnixListener, UnixStream},
+    process::Child,
+};
+
+use crate::{error::Error, faults::Faults};
+use greetd_ipc::{
+    codec::{Error as CodecError, TokioCodec},
+    Request, Response,
+};
+
+#[derive(Deserialize)]
+pub struct Step {
+    request: Value,
+    response: Value,
+}
+
+/// Load a scenario from a file.
+pub fn load(path: &str) -> Result<VecDeque<Step>, Error> {
+    let scenario =
+        fs::read_to_string(path).map_err(|e| format!("unable to read scenario {}: {}", path, e))?;
+    serde_json::from_str(&scenario)
+        .map_err(|e| format!("unable to parse scenario {}: {}", path, e).into())
+}
+
+/// Check if the actual value matches the expectation.
+fn matches(expected: &Value, actual: &Value) -> bool {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => expected
+            .iter()
+            .all(|(key, value)| actual.get(key).map(|v| matches(value, v)).unwrap_or(false)),
+        (expected, actual) => expected == actual,
+    }
+}
+
+fn check(step: usize, expected: &Value, actual: &Value) -> Result<(), Error> {
+    if matches(expected, actual) {
+        Ok(())
+    } else {
+        Err(Error::ProtocolError(format!(
+            "step {}: expected {}, got {}",
+            step, expected, actual
+        )))
+    }
+}
+
+// Serve steps on a single connection until the steps run out or the greeter
+// disconnects.
+async fn serve_connection(
+    steps: &mut VecDeque<Step>,
+    step_num: &mut usize,
+    faults: &Faults,
+    mut s: UnixStream,
+) -> Result<(), Error> {
+    let mut n = 0;
+    while let Some(step) = steps.front() {
+        n += 1;
+        let req = match Request::read_from(&mut s).await {
+            Ok(req) => req,
+            Err(CodecError::Eof) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        println!("req: {:?}", req);
+        check(*step_num, &step.request, &serde_json::to_value(&req)?)?;
+
+        let resp: Response = serde_json::from_value(step.response.clone())
+            .map_err(|e| format!("step {}: invalid response: {}", step_num, e))?;
+        let resp = faults.apply(n, resp);
+        let open = faults.send(n, resp, &mut s).await?;
+
+        steps.pop_front();
+        *step_num += 1;
+        if !open {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Serve a scenario to a greeter, checking its requests and sending back the
+/// scripted responses. The scenario may span multiple connections. Fails if
+/// the greeter sends an unexpected request, or exits before the scenario is
+/// complete.
+pub async fn serve(
+    mut steps: VecDeque<Step>,
+    listener: UnixListener,
+    mut greeter: Child,
+    faults: &Faults,
+) -> Result<(), Error> {
+    let mut step_num = 1;
+    while !steps.is_empty() {
+        tokio::select! {
+            status = greeter.wait() => {
+                return Err(Error::ProtocolError(format!(
+                    "greeter exited with {} steps left: {}",
+                    steps.len(),
+                    status?
+                )));
+            }
+            stream = listener.accept() => {
+                let (stream, _) = stream.map_err(|e| format!("accept: {}", e))?;
+                serve_connection(&mut steps, &mut step_num, faults, stream).await?;
+            }
+        }
+    }
+    println!("scenario completed");
+    Ok(())
+}
+
+/// Run a scenario against a daemon, sending the scripted requests and checking
+/// the responses.
+pub async fn run_client(steps: VecDeque<Step>, path: &str) -> Result<(), Error> {
+    let mut s = UnixStream::connect(path)
+        .await
+        .map_err(|e| format!("unable to connect to {}: {}", path, e))?;
+    for (idx, step) in steps.into_iter().enumerate() {
+        let req: Request = serde_json::from_value(step.request)
+            .map_err(|e| format!("step {}: invalid request: {}", idx + 1, e))?;
+        println!("req: {:?}", req);
+        req.write_to(&mut s).await?;
+
+        let resp = Response::read_from(&mut s).await?;
+        println!("resp: {:?}", resp);
+        check(idx + 1, &step.response, &serde_json::to_value(&resp)?)?;
+    }
+    println!("scenario completed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn partial_match() {
+        let actual = json!({"type": "error", "error_type": "auth_error", "description": "nope"});
+        assert!(matches(&json!({"type": "error"}), &actual));
+        assert!(matches(
+            &json!({"type": "error", "error_type": "auth_error"}),
+            &actual
+        ));
+        assert!(!matches(&json!({"type": "success"}), &actual));
+        assert!(!matches(&json!({"type": "error", "foo": "bar"}), &actual));
+        assert!(matches(
+            &json!({"response": null}),
+            &json!({"response": null})
+        ));
+        assert!(!matches(
+            &json!({"response": null}),
+            &json!({"response": "secret"})
+        ));
+    }
+}
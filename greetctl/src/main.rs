@@ -0,0 +1,118 @@
+mod prometheus;
+mod sessions;
+
+use std::{env, os::unix::net::UnixStream};
+
+use getopts::Options;
+
+use greetd_ipc::{codec::SyncCodec, Request, Response};
+
+const DEFAULT_SOCKET: &str = "/run/greetd-admin.sock";
+
+fn request(socket: &str, req: Request) -> Result<Response, Box<dyn std::error::Error>> {
+    let mut stream = UnixStream::connect(socket)
+        .map_err(|e| format!("unable to connect to {}: {}", socket, e))?;
+    req.write_to(&mut stream)?;
+    match Response::read_from(&mut stream)? {
+        Response::Error { description, .. } => Err(description.into()),
+        resp => Ok(resp),
+    }
+}
+
+fn metrics(socket: &str, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let metrics = match request(socket, Request::GetMetrics)? {
+        Response::Metrics { metrics } => metrics,
+        resp => return Err(format!("unexpected response: {:?}", resp).into()),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&metrics)?);
+    } else {
+        print!("{}", prometheus::render(&metrics));
+    }
+    Ok(())
+}
+
+fn sessions(socket: &str, history: bool, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if !history {
+        let sessions = match request(socket, Request::GetSessions)? {
+            Response::Sessions { sessions } => sessions,
+            resp => return Err(format!("unexpected response: {:?}", resp).into()),
+        };
+        if json {
+            println!("{}", serde_json::to_string_pretty(&sessions)?);
+        } else {
+            print!("{}", sessions::render_active(&sessions));
+        }
+        return Ok(());
+    }
+    let sessions = match request(socket, Request::GetSessionHistory)? {
+        Response::SessionHistory { sessions } => sessions,
+        resp => return Err(format!("unexpected response: {:?}", resp).into()),
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&sessions)?);
+    } else {
+        print!("{}", sessions::render(&sessions));
+    }
+    Ok(())
+}
+
+fn upgrade(socket: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match request(socket, Request::Upgrade)? {
+        Response::Success => Ok(()),
+        resp => Err(format!("unexpected response: {:?}", resp).into()),
+    }
+}
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] COMMAND", program);
+    print!("{}", opts.usage(&brief));
+    println!();
+    println!("Commands:");
+    println!("    metrics             print login metrics");
+    println!("    sessions            print the running sessions");
+    println!("    sessions --history  print the most recent user sessions");
+    println!("    upgrade             re-execute greetd, keeping the running session");
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("s", "socket", "admin socket to connect to", "SOCKET");
+    opts.optflag("j", "json", "print output as JSON");
+    opts.optflag("", "history", "print sessions that have ended");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => {
+            println!("{}", f);
+            print_usage(&program, opts);
+            std::process::exit(1);
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        std::process::exit(0);
+    }
+
+    let socket = matches
+        .opt_str("socket")
+        .unwrap_or_else(|| DEFAULT_SOCKET.to_string());
+    let json = matches.opt_present("json");
+
+    let res = match matches.free.first().map(|s| s.as_str()) {
+        Some("metrics") => metrics(&socket, json),
+        Some("sessions") => sessions(&socket, matches.opt_present("history"), json),
+        Some("upgrade") => upgrade(&socket),
+        Some(cmd) => Err(format!("unknown command: {}", cmd).into()),
+        None => {
+            print_usage(&program, opts);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::Write;
+
+use greetd_ipc::{Histogram, Metrics};
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    for bucket in &histogram.buckets {
+        let _ = writeln!(
+            out,
+            "{}_bucket{{le=\"{}\"}} {}",
+            name, bucket.le, bucket.count
+        );
+    }
+    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, histogram.count);
+    let _ = writeln!(out, "{}_sum {}", name, histogram.sum);
+    let _ = writeln!(out, "{}_count {}", name, histogram.count);
+}
+
+/// Render metrics in the Prometheus text exposition format.
+pub fn render(metrics: &Metrics) -> String {
+    let mut out = String::new();
+    counter(
+        &mut out,
+        "greetd_logins_total",
+        "User sessions started after successful authentication.",
+        metrics.logins,
+    );
+    counter(
+        &mut out,
+        "greetd_auth_failures_total",
+        "Failed authentication attempts.",
+        metrics.auth_failures,
+    );
+    counter(
+        &mut out,
+        "greetd_greeter_starts_total",
+        "Times the greeter has been started.",
+        metrics.greeter_starts,
+    );
+    histogram(
+        &mut out,
+        "greetd_session_duration_seconds",
+        "Duration of user sessions that have ended.",
+        &metrics.session_durations,
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use greetd_ipc::Bucket;
+
+    #[test]
+    fn render_metrics() {
+        let metrics = Metrics {
+            logins: 2,
+            auth_failures: 1,
+            greeter_starts: 3,
+            session_durations: Histogram {
+                buckets: vec![
+                    Bucket { le: 60.0, count: 1 },
+                    Bucket {
+                        le: 3600.0,
+                        count: 1,
+                    },
+                ],
+                sum: 7230.0,
+                count: 2,
+            },
+        };
+        let out = render(&metrics);
+        assert!(out.contains("# TYPE greetd_logins_total counter\ngreetd_logins_total 2\n"));
+        assert!(out.contains("greetd_auth_failures_total 1\n"));
+        assert!(out.contains("greetd_greeter_starts_total 3\n"));
+        assert!(out.ends_with(
+            "greetd_session_duration_seconds_bucket{le=\"60\"} 1
+greetd_session_duration_seconds_bucket{le=\"3600\"} 1
+greetd_session_duration_seconds_bucket{le=\"+Inf\"} 2
+greetd_session_duration_seconds_sum 7230
+greetd_session_duration_seconds_count 2
+"
+        ));
+    }
+}
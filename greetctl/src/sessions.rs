@@ -0,0 +1,111 @@
+use std::{fmt::Write, mem};
+
+use greetd_ipc::{SessionClass, SessionInfo, SessionRecord, SessionState};
+
+// Format a time in seconds since the Unix epoch as local time.
+fn format_time(time: u64) -> String {
+    let time = time as libc::time_t;
+    let mut buf = [0u8; 64];
+    let len = unsafe {
+        let mut tm: libc::tm = mem::zeroed();
+        if libc::localtime_r(&time, &mut tm).is_null() {
+            return time.to_string();
+        }
+        libc::strftime(
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+            b"%Y-%m-%d %H:%M:%S\0".as_ptr() as *const libc::c_char,
+            &tm,
+        )
+    };
+    String::from_utf8_lossy(&buf[..len]).to_string()
+}
+
+fn format_duration(secs: u64) -> String {
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn format_vt(vt: Option<usize>) -> String {
+    vt.map(|vt| vt.to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Render running sessions as a table, one session per line.
+pub fn render_active(sessions: &[SessionInfo]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:<6} {:<16} {:<8} {:<4} {:<7} {:<19} STATE",
+        "ID", "USER", "SEAT", "VT", "CLASS", "START"
+    );
+    for s in sessions {
+        let id = match s.id {
+            Some(id) => id.to_string(),
+            None => "-".to_string(),
+        };
+        let class = match s.class {
+            SessionClass::Greeter => "greeter",
+            SessionClass::User => "user",
+        };
+        let state = match s.state {
+            SessionState::Starting => "starting",
+            SessionState::Active => "active",
+            SessionState::Closing => "closing",
+        };
+        let _ = writeln!(
+            out,
+            "{:<6} {:<16} {:<8} {:<4} {:<7} {:<19} {}",
+            id,
+            s.username,
+            s.seat,
+            format_vt(s.vt),
+            class,
+            format_time(s.start),
+            state
+        );
+    }
+    out
+}
+
+/// Render session records as a table, one session per line.
+pub fn render(sessions: &[SessionRecord]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{:<16} {:<8} {:<4} {:<19} {:>10} EXIT",
+        "USER", "SEAT", "VT", "START", "DURATION"
+    );
+    for s in sessions {
+        let duration = match s.end {
+            Some(end) => format_duration(end.saturating_sub(s.start)),
+            None => "running".to_string(),
+        };
+        let exit = match s.exit_code {
+            Some(code) => code.to_string(),
+            None => "-".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{:<16} {:<8} {:<4} {:<19} {:>10} {}",
+            s.username,
+            s.seat,
+            format_vt(s.vt),
+            format_time(s.start),
+            duration,
+            exit
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(0), "0:00:00");
+        assert_eq!(format_duration(3725), "1:02:05");
+        assert_eq!(format_duration(90000), "25:00:00");
+    }
+}
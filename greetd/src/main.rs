@@ -1,26 +1,17 @@
-mod config;
-mod context;
-mod error;
-mod pam;
-mod scrambler;
-mod server;
-mod session;
-mod terminal;
-
 use std::os::unix::{
     io::{FromRawFd, RawFd},
     net::UnixDatagram,
 };
 
+use greetd_core::{config, error::Error, logging, server, session::worker};
 use nix::{
     fcntl::{fcntl, FcntlArg, FdFlag},
     sys::mman::{mlockall, MlockAllFlags},
 };
 use tokio::task;
+use tracing::{debug, error};
 
-use crate::{error::Error, session::worker};
-
-async fn session_worker_main(config: config::Config) -> Result<(), Error> {
+async fn session_worker_main(config: config::Config) -> Result<i32, Error> {
     let raw_fd = config.internal.session_worker as RawFd;
     let mut cur_flags = FdFlag::from_bits_retain(fcntl(raw_fd, FcntlArg::F_GETFD)?);
     cur_flags.insert(FdFlag::FD_CLOEXEC);
@@ -38,20 +29,24 @@ async fn main() {
             std::process::exit(1);
         }
     };
-    if cfg!(feature = "debug") {
-        eprintln!("config: {:?}", config);
+    if let Err(e) = logging::init(&config.log_filter()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
+    debug!(?config, "configuration loaded");
     mlockall(MlockAllFlags::all()).expect("unable to lock pages");
     let res = task::LocalSet::new()
         .run_until(async move {
             if config.internal.session_worker > 0 {
                 session_worker_main(config).await
             } else {
-                server::main(config).await
+                server::main(config).await.map(|()| 0)
             }
         })
         .await;
-    if let Err(e) = res {
-        eprintln!("error: {}", e);
+    match res {
+        Ok(0) => (),
+        Ok(code) => std::process::exit(code),
+        Err(e) => error!("{}", e),
     }
 }
@@ -1,4 +0,0 @@
-pub mod conv;
-pub mod interface;
-mod prctl;
-pub mod worker;
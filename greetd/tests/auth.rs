@@ -0,0 +1,459 @@
+//! End to end tests of the authentication flow, which boot greetd with a
+//! temporary configuration and drive it through its socket like a greeter.
+//!
+//! Authentication is handled by the mock PAM module of pam_greetd_mock, which
+//! accepts the password "password". The tests need root, as greetd only reads
+//! PAM services from /etc/pam.d and runs sessions as their user, and the mock
+//! module must have been built. They are therefore ignored by default, and
+//! fail rather than pass if run without what they need:
+//!
+//! ```sh
+//! cargo build --workspace
+//! sudo cargo test -p greetd --test auth -- --ignored
+//! ```
+
+use std::{
+    fs,
+    os::unix::{fs::PermissionsExt, net::UnixStream},
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use greetd_ipc::{
+    codec::SyncCodec, AuthMessageType, ErrorType, Metrics, PowerAction, Request, Response,
+};
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::{geteuid, Pid},
+};
+
+// The mock PAM module, built next to the greetd binary.
+fn module() -> PathBuf {
+    Path::new(env!("CARGO_BIN_EXE_greetd")).with_file_name("libpam_greetd_mock.so")
+}
+
+// Check that the tests can run at all.
+fn check_preconditions() {
+    assert!(geteuid().is_root(), "the tests must be run as root");
+    assert!(
+        module().exists(),
+        "{} is missing, build the workspace first",
+        module().display()
+    );
+}
+
+// Write a PAM service that uses the mock module with the given arguments.
+fn write_service(path: &Path, args: &str) {
+    let module = module();
+    let pam = ["auth", "account", "password", "session"]
+        .iter()
+        .map(|t| format!("{} required {} {}\n", t, module.display(), args))
+        .collect::<String>();
+    fs::write(path, pam).expect("unable to write PAM service");
+}
+
+/// A running instance of greetd, with a PAM service of its own that uses the
+/// mock module with the given arguments. The greeter is a process that only
+/// waits, as the test acts as the greeter, and has a service without
+/// arguments so that it always starts. The admin socket is in the test
+/// directory, which also comes first in the PATH of greetd, so that tests can
+/// stand in for the tools it runs.
+struct Greetd {
+    child: Child,
+    dir: PathBuf,
+    services: [PathBuf; 2],
+    sock: String,
+}
+
+impl Greetd {
+    fn start(name: &str, args: &str) -> Greetd {
+        Greetd::start_with(name, args, "sleep 3600", "")
+    }
+
+    /// Start greetd with the given greeter command and additional
+    /// configuration sections, in both of which {dir} stands for the test
+    /// directory.
+    fn start_with(name: &str, args: &str, greeter: &str, extra: &str) -> Greetd {
+        check_preconditions();
+        let id = format!("greetd-test-{}-{}", process::id(), name);
+        let dir = std::env::temp_dir().join(&id);
+        fs::create_dir_all(&dir).expect("unable to create test directory");
+
+        let services = [
+            Path::new("/etc/pam.d").join(&id),
+            Path::new("/etc/pam.d").join(format!("{}-greeter", id)),
+        ];
+        write_service(&services[0], args);
+        write_service(&services[1], "");
+
+        let config = dir.join("config.toml");
+        fs::write(
+            &config,
+            format!(
+                "[terminal]
+vt = none
+
+[general]
+service = \"{id}\"
+runfile = \"{dir}/greetd.run\"
+admin_socket = \"{dir}/admin.sock\"
+state_dir = \"\"
+source_profile = false
+plymouth = false
+
+[default_session]
+command = \"{greeter}\"
+user = \"root\"
+service = \"{id}-greeter\"
+
+{extra}",
+                id = id,
+                dir = dir.display(),
+                greeter = greeter.replace("{dir}", &dir.to_string_lossy()),
+                extra = extra.replace("{dir}", &dir.to_string_lossy()),
+            ),
+        )
+        .expect("unable to write config");
+
+        let child = Command::new(env!("CARGO_BIN_EXE_greetd"))
+            .arg("--config")
+            .arg(&config)
+            .env(
+                "PATH",
+                format!(
+                    "{}:{}",
+                    dir.display(),
+                    std::env::var("PATH").unwrap_or_default()
+                ),
+            )
+            .stdin(Stdio::null())
+            .spawn()
+            .expect("unable to start greetd");
+        let sock = format!("/run/greetd-{}.sock", child.id());
+        Greetd {
+            child,
+            dir,
+            services,
+            sock,
+        }
+    }
+
+    fn connect(&self) -> UnixStream {
+        connect(&self.sock)
+    }
+
+    fn connect_admin(&self) -> UnixStream {
+        connect(&self.dir.join("admin.sock").to_string_lossy())
+    }
+}
+
+fn connect(sock: &str) -> UnixStream {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        match UnixStream::connect(sock) {
+            Ok(stream) => {
+                // Fail rather than hang if greetd stops answering.
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(10)))
+                    .expect("unable to set read timeout");
+                return stream;
+            }
+            Err(e) if Instant::now() > deadline => panic!("unable to connect: {}", e),
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+impl Drop for Greetd {
+    fn drop(&mut self) {
+        let _ = kill(Pid::from_raw(self.child.id() as i32), Signal::SIGTERM);
+        let _ = self.child.wait();
+        for service in &self.services {
+            let _ = fs::remove_file(service);
+        }
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn request(stream: &mut UnixStream, req: Request) -> Response {
+    req.write_to(stream).expect("unable to send request");
+    Response::read_from(stream).expect("unable to read response")
+}
+
+fn create_session(stream: &mut UnixStream) -> Response {
+    request(
+        stream,
+        Request::CreateSession {
+            username: "root".to_string(),
+            concurrent_auth: false,
+            locale: None,
+        },
+    )
+}
+
+fn answer(stream: &mut UnixStream, response: Option<&str>) -> Response {
+    request(
+        stream,
+        Request::PostAuthMessageResponse {
+            response: response.map(str::to_string),
+        },
+    )
+}
+
+fn start_session(stream: &mut UnixStream, cmd: &str) -> Response {
+    request(
+        stream,
+        Request::StartSession {
+            cmd: vec![cmd.to_string()],
+            env: vec![],
+            session_type: None,
+        },
+    )
+}
+
+// Wait for a file to be created, returning whether it was before the deadline.
+fn wait_for(path: &Path, deadline: Instant) -> bool {
+    while !path.exists() {
+        if Instant::now() > deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    true
+}
+
+fn metrics(stream: &mut UnixStream) -> Metrics {
+    match request(stream, Request::GetMetrics) {
+        Response::Metrics { metrics } => metrics,
+        resp => panic!("expected metrics, got {:?}", resp),
+    }
+}
+
+fn assert_question(resp: Response, style: AuthMessageType, msg: &str) {
+    match resp {
+        Response::AuthMessage {
+            auth_message_type,
+            auth_message,
+        } => {
+            assert_eq!(auth_message_type, style);
+            assert_eq!(auth_message, msg);
+        }
+        resp => panic!("expected {:?} message {:?}, got {:?}", style, msg, resp),
+    }
+}
+
+fn assert_success(resp: Response) {
+    assert!(matches!(resp, Response::Success), "got {:?}", resp);
+}
+
+#[test]
+#[ignore = "requires root and the mock PAM module"]
+fn success() {
+    let greetd = Greetd::start("success", "");
+    let mut s = greetd.connect();
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    assert_success(answer(&mut s, Some("password")));
+    assert_success(request(&mut s, Request::CancelSession));
+}
+
+#[test]
+#[ignore = "requires root and the mock PAM module"]
+fn failure() {
+    let greetd = Greetd::start("failure", "");
+    let mut s = greetd.connect();
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    match answer(&mut s, Some("wrong")) {
+        Response::Error {
+            error_type: ErrorType::AuthError,
+            ..
+        } => (),
+        resp => panic!("expected an auth error, got {:?}", resp),
+    }
+    // Greeters cancel the failed session before trying again, as agreety
+    // does, whether or not its worker is still around.
+    request(&mut s, Request::CancelSession);
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+}
+
+#[test]
+#[ignore = "requires root and the mock PAM module"]
+fn multiple_prompts() {
+    let greetd = Greetd::start("multi", "code");
+    let mut s = greetd.connect();
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    assert_question(
+        answer(&mut s, Some("password")),
+        AuthMessageType::Info,
+        "Check your authenticator",
+    );
+    assert_question(answer(&mut s, None), AuthMessageType::Visible, "Code:");
+    assert_success(answer(&mut s, Some("1234")));
+    assert_success(request(&mut s, Request::CancelSession));
+}
+
+#[test]
+#[ignore = "requires root and the mock PAM module"]
+fn cancel() {
+    let greetd = Greetd::start("cancel", "");
+    let mut s = greetd.connect();
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    assert_success(request(&mut s, Request::CancelSession));
+    match answer(&mut s, Some("password")) {
+        Response::Error { .. } => (),
+        resp => panic!("expected an error, got {:?}", resp),
+    }
+    // A cancelled session starts over.
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    assert_success(answer(&mut s, Some("password")));
+    assert_success(request(&mut s, Request::CancelSession));
+}
+
+#[test]
+#[ignore = "requires root and the mock PAM module"]
+fn password_change() {
+    let greetd = Greetd::start("expired", "expired");
+    let mut s = greetd.connect();
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    match answer(&mut s, Some("password")) {
+        Response::PasswordChangeRequired => (),
+        resp => panic!("expected a password change, got {:?}", resp),
+    }
+    assert_question(
+        answer(&mut s, None),
+        AuthMessageType::Secret,
+        "New password:",
+    );
+    assert_success(answer(&mut s, Some("hunter2")));
+    assert_success(request(&mut s, Request::CancelSession));
+}
+
+#[test]
+#[ignore = "requires root and the mock PAM module"]
+fn handover_timeout() {
+    // The greeter notes when it is terminated, and the session never reports
+    // that it is ready.
+    let greetd = Greetd::start_with(
+        "handover",
+        "",
+        "trap 'touch {dir}/terminated; exit 0' TERM; while :; do sleep 1; done",
+        "[handover]\ntimeout = 2\n",
+    );
+    let terminated = greetd.dir.join("terminated");
+    let mut s = greetd.connect();
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    assert_success(answer(&mut s, Some("password")));
+    let start = Instant::now();
+    assert_success(start_session(&mut s, "sleep 60"));
+
+    // The greeter is kept until the timeout, and then terminated regardless.
+    assert!(
+        wait_for(&terminated, start + Duration::from_secs(10)),
+        "greeter was not terminated"
+    );
+    assert!(
+        start.elapsed() >= Duration::from_secs(2),
+        "greeter was terminated after {:?}, before the timeout",
+        start.elapsed()
+    );
+}
+
+#[test]
+#[ignore = "requires root and the mock PAM module"]
+fn power_inhibited() {
+    let greetd = Greetd::start_with(
+        "inhibited",
+        "",
+        "sleep 3600",
+        "[power]
+poweroff_command = \"touch {dir}/poweroff\"
+reboot_command = \"touch {dir}/reboot\"
+",
+    );
+    // busctl replies with whatever inhibitors the test has written.
+    let busctl = greetd.dir.join("busctl");
+    let inhibitors = greetd.dir.join("inhibitors");
+    fs::write(
+        &busctl,
+        format!("#!/bin/sh\ncat {}\n", inhibitors.display()),
+    )
+    .expect("unable to write busctl");
+    fs::set_permissions(&busctl, fs::Permissions::from_mode(0o755))
+        .expect("unable to make busctl executable");
+    let poweroff = greetd.dir.join("poweroff");
+    let mut s = greetd.connect();
+    let power = |s: &mut UnixStream| {
+        request(
+            s,
+            Request::PowerAction {
+                action: PowerAction::Poweroff,
+            },
+        )
+    };
+
+    fs::write(
+        &inhibitors,
+        r#"{"type":"a(ssssuu)","data":[[["shutdown:sleep","PackageKit","Upgrading packages","block",0,812]]]}"#,
+    )
+    .expect("unable to write inhibitors");
+    match power(&mut s) {
+        Response::Error {
+            error_type: ErrorType::Inhibited,
+            description,
+        } => assert_eq!(description, "PackageKit (Upgrading packages)"),
+        resp => panic!("expected the action to be inhibited, got {:?}", resp),
+    }
+    thread::sleep(Duration::from_millis(500));
+    assert!(!poweroff.exists(), "inhibited power command was run");
+
+    // Once the inhibitor is gone, the command runs.
+    fs::write(&inhibitors, r#"{"type":"a(ssssuu)","data":[[]]}"#)
+        .expect("unable to write inhibitors");
+    assert_success(power(&mut s));
+    assert!(
+        wait_for(&poweroff, Instant::now() + Duration::from_secs(10)),
+        "power command was not run"
+    );
+}
+
+#[test]
+#[ignore = "requires root and the mock PAM module"]
+fn login_metrics() {
+    let greetd = Greetd::start("metrics", "");
+    let mut s = greetd.connect();
+    let mut admin = greetd.connect_admin();
+    let m = metrics(&mut admin);
+    assert_eq!((m.logins, m.auth_failures, m.greeter_starts), (0, 0, 1));
+
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    match answer(&mut s, Some("wrong")) {
+        Response::Error {
+            error_type: ErrorType::AuthError,
+            ..
+        } => (),
+        resp => panic!("expected an auth error, got {:?}", resp),
+    }
+    let m = metrics(&mut admin);
+    assert_eq!((m.logins, m.auth_failures), (0, 1));
+
+    // A successful authentication is not a login until the session starts,
+    // which is once the greeter has exited.
+    request(&mut s, Request::CancelSession);
+    assert_question(create_session(&mut s), AuthMessageType::Secret, "Password:");
+    assert_success(answer(&mut s, Some("password")));
+    assert_eq!(metrics(&mut admin).logins, 0);
+    assert_success(start_session(&mut s, "sleep 60"));
+    drop(s);
+
+    let deadline = Instant::now() + Duration::from_secs(20);
+    loop {
+        let m = metrics(&mut admin);
+        if m.logins == 1 {
+            assert_eq!(m.auth_failures, 1);
+            break;
+        }
+        assert!(Instant::now() < deadline, "login was not counted: {:?}", m);
+        thread::sleep(Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,157 @@
+//! User metadata recorded by AccountsService, read from its files directly so
+//! that neither greetd nor its greeters need D-Bus access.
+
+use std::{fs, path::Path};
+
+use greetd_ipc::UserInfo;
+use nix::unistd::User;
+
+const ACCOUNTS_DIR: &str = "/var/lib/AccountsService";
+
+/// The metadata AccountsService keeps for a user.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Record {
+    pub session: Option<String>,
+    pub language: Option<String>,
+    pub icon: Option<String>,
+}
+
+fn non_empty(value: Option<&&str>) -> Option<String> {
+    value.filter(|v| !v.is_empty()).map(|v| v.to_string())
+}
+
+// Parse an AccountsService user file, preferring the Wayland-or-any "Session"
+// key over the older "XSession".
+fn parse(contents: &str) -> Record {
+    let file = match inish::parse(contents) {
+        Ok(file) => file,
+        Err(_) => return Default::default(),
+    };
+    let user = match file.get("User") {
+        Some(user) => user,
+        None => return Default::default(),
+    };
+    Record {
+        session: non_empty(user.get("Session")).or_else(|| non_empty(user.get("XSession"))),
+        language: non_empty(user.get("Language")),
+        icon: non_empty(user.get("Icon")),
+    }
+}
+
+/// Read the AccountsService record of a user. Missing records are empty.
+pub fn read(name: &str) -> Record {
+    read_in(Path::new(ACCOUNTS_DIR), name)
+}
+
+// Read the record of a user from the given AccountsService directory, falling
+// back to the icon AccountsService keeps for the user if the record names none.
+fn read_in(dir: &Path, name: &str) -> Record {
+    let mut record = fs::read_to_string(dir.join("users").join(name))
+        .map(|contents| parse(&contents))
+        .unwrap_or_default();
+    if record.icon.is_none() {
+        let icon = dir.join("icons").join(name);
+        if icon.is_file() {
+            record.icon = icon.to_str().map(str::to_string);
+        }
+    }
+    record
+}
+
+/// Return the display metadata of a user.
+pub fn user_info(user: &User) -> UserInfo {
+    let record = read(&user.name);
+    // The real name is the first field of the GECOS field.
+    let real_name = user
+        .gecos
+        .to_str()
+        .ok()
+        .and_then(|gecos| gecos.split(',').next())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string);
+    UserInfo {
+        username: user.name.clone(),
+        real_name,
+        icon: record.icon,
+        session: record.session,
+        language: record.language,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        assert_eq!(
+            parse("[User]\nLanguage=de_DE.UTF-8\nSession=sway\nXSession=i3\nIcon=/home/x/.face\nSystemAccount=false\n"),
+            Record {
+                session: Some("sway".to_string()),
+                language: Some("de_DE.UTF-8".to_string()),
+                icon: Some("/home/x/.face".to_string()),
+            }
+        );
+        assert_eq!(
+            parse("[User]\nLanguage=\nSession=\nXSession=i3\n"),
+            Record {
+                session: Some("i3".to_string()),
+                ..Default::default()
+            }
+        );
+        assert_eq!(parse("[InputSource0]\nxkb=us\n"), Default::default());
+        assert_eq!(parse("garbage"), Default::default());
+    }
+
+    #[test]
+    fn lookup() {
+        let dir = std::env::temp_dir().join(format!("greetd-test-{}-accounts", std::process::id()));
+        fs::create_dir_all(dir.join("users")).unwrap();
+        fs::create_dir_all(dir.join("icons")).unwrap();
+        fs::write(dir.join("users/alice"), "[User]\nSession=sway\n").unwrap();
+        fs::write(dir.join("icons/alice"), "").unwrap();
+        fs::write(dir.join("users/bob"), "[User]\nIcon=/home/bob/.face\n").unwrap();
+        fs::write(dir.join("icons/bob"), "").unwrap();
+        fs::write(dir.join("icons/carol"), "").unwrap();
+
+        let icon = |name: &str| Some(dir.join("icons").join(name).to_str().unwrap().to_string());
+        assert_eq!(
+            read_in(&dir, "alice"),
+            Record {
+                session: Some("sway".to_string()),
+                icon: icon("alice"),
+                ..Default::default()
+            }
+        );
+        // An icon in the record takes precedence.
+        assert_eq!(
+            read_in(&dir, "bob").icon,
+            Some("/home/bob/.face".to_string())
+        );
+        // Users without a record may still have an icon.
+        assert_eq!(
+            read_in(&dir, "carol"),
+            Record {
+                icon: icon("carol"),
+                ..Default::default()
+            }
+        );
+        assert_eq!(read_in(&dir, "dave"), Default::default());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn info() {
+        let mut user = User::from_name("root").unwrap().unwrap();
+        user.name = format!("greetd-test-{}", std::process::id());
+        user.gecos = std::ffi::CString::new("Alice Liddell,Room 1,,").unwrap();
+        let info = user_info(&user);
+        assert_eq!(info.username, user.name);
+        assert_eq!(info.real_name, Some("Alice Liddell".to_string()));
+        assert_eq!(info.session, None);
+
+        user.gecos = std::ffi::CString::new(",Room 1").unwrap();
+        assert_eq!(user_info(&user).real_name, None);
+    }
+}
@@ -0,0 +1,152 @@
+//! The initial session given on the kernel command line, such as
+//! "greetd.autologin=kiosk greetd.session=\"cage -- firefox --kiosk\"", which
+//! overrides the one in the configuration file. This allows images to be
+//! booted into a session of choice without changing their configuration.
+//!
+//! Whoever can edit the kernel command line can log in as any user this way,
+//! so it is only honored if enabled with general.kernel_cmdline.
+
+use super::{ConfigFile, ConfigSession};
+use crate::error::Error;
+
+pub const CMDLINE: &str = "/proc/cmdline";
+
+const AUTOLOGIN: &str = "greetd.autologin";
+const SESSION: &str = "greetd.session";
+
+// Split the kernel command line into parameters the way the kernel does, at
+// whitespace outside of double quotes, which are removed. Parameters after
+// "--" are for init, and not returned.
+fn split(cmdline: &str) -> Vec<String> {
+    let mut params = Vec::new();
+    let mut param = String::new();
+    let mut quoted = false;
+    for c in cmdline.trim().chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if param == "--" {
+                    return params;
+                }
+                if !param.is_empty() {
+                    params.push(std::mem::take(&mut param));
+                }
+            }
+            c => param.push(c),
+        }
+    }
+    if !param.is_empty() && param != "--" {
+        params.push(param);
+    }
+    params
+}
+
+/// Apply the initial session given on the kernel command line, if any, to the
+/// configuration. greetd.autologin sets the user of the initial session, and
+/// greetd.session its command. Either may be left out if the configuration has
+/// an initial session to take the other from.
+pub fn apply(file: &mut ConfigFile, cmdline: &str) -> Result<(), Error> {
+    let (mut user, mut command) = (None, None);
+    for param in split(cmdline) {
+        match param.split_once('=') {
+            Some((AUTOLOGIN, v)) => user = Some(v.to_string()),
+            Some((SESSION, v)) => command = Some(v.to_string()),
+            _ => (),
+        }
+    }
+    if user.is_none() && command.is_none() {
+        return Ok(());
+    }
+
+    let configured = file.initial_session.take();
+    let missing = |set, unset| {
+        Error::ConfigError(format!(
+            "{} is set on the kernel command line without {}",
+            set, unset
+        ))
+    };
+    let user = user
+        .or_else(|| configured.as_ref().map(|s| s.user.clone()))
+        .ok_or_else(|| missing(SESSION, AUTOLOGIN))?;
+    let command = command
+        .or_else(|| configured.as_ref().map(|s| s.command.clone()))
+        .ok_or_else(|| missing(AUTOLOGIN, SESSION))?;
+    if user.is_empty() || command.is_empty() {
+        return Err(Error::ConfigError(format!(
+            "{} and {} must not be empty",
+            AUTOLOGIN, SESSION
+        )));
+    }
+    let service = match configured {
+        Some(s) => s.service,
+        None => file.general.service.clone(),
+    };
+    file.initial_session = Some(ConfigSession {
+        command,
+        user,
+        service,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params() {
+        assert_eq!(
+            split("quiet greetd.session=\"cage -- app\" \"a=b c\"  root=/dev/sda1\n"),
+            vec![
+                "quiet",
+                "greetd.session=cage -- app",
+                "a=b c",
+                "root=/dev/sda1"
+            ]
+        );
+        assert_eq!(split("quiet -- greetd.autologin=alice"), vec!["quiet"]);
+        assert_eq!(split(""), Vec::<String>::new());
+    }
+
+    #[test]
+    fn autologin() {
+        let mut file = ConfigFile::default();
+        apply(&mut file, "quiet splash").unwrap();
+        assert_eq!(file.initial_session, None);
+
+        apply(
+            &mut file,
+            "greetd.autologin=kiosk greetd.session=\"cage app\"",
+        )
+        .unwrap();
+        assert_eq!(
+            file.initial_session,
+            Some(ConfigSession {
+                command: "cage app".to_string(),
+                user: "kiosk".to_string(),
+                service: "greetd".to_string(),
+            })
+        );
+
+        // The configured initial session fills in what is missing.
+        file.initial_session = Some(ConfigSession {
+            command: "sway".to_string(),
+            user: "alice".to_string(),
+            service: "greetd-autologin".to_string(),
+        });
+        apply(&mut file, "greetd.autologin=bob").unwrap();
+        assert_eq!(
+            file.initial_session,
+            Some(ConfigSession {
+                command: "sway".to_string(),
+                user: "bob".to_string(),
+                service: "greetd-autologin".to_string(),
+            })
+        );
+
+        let mut file = ConfigFile::default();
+        assert!(apply(&mut file, "greetd.autologin=alice").is_err());
+        assert!(apply(&mut file, "greetd.session=sway").is_err());
+        assert!(apply(&mut file, "greetd.autologin= greetd.session=sway").is_err());
+    }
+}
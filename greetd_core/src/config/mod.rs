@@ -0,0 +1,1860 @@
+mod cmdline;
+
+use std::{collections::HashMap, default::Default, env, fs::read_to_string, str::FromStr};
+
+use enquote::unquote;
+use getopts::Options;
+use serde::{Deserialize, Serialize};
+
+use super::error::Error;
+use greetd_ipc::desktop::DEFAULT_SESSION_DIRS;
+
+const RUNFILE: &str = "/run/greetd.run";
+const ADMIN_SOCKET: &str = "/run/greetd-admin.sock";
+const STATE_DIR: &str = "/var/lib/greetd";
+const GENERAL_SERVICE: &str = "greetd";
+const GREETER_SERVICE: &str = "greetd-greeter";
+const POWEROFF_COMMAND: &str = "systemctl poweroff";
+const REBOOT_COMMAND: &str = "systemctl reboot";
+const X11_SERVER_COMMAND: &str = "/usr/bin/Xorg -nolisten tcp -noreset -keeptty";
+const SMARTCARD_LOCK_COMMAND: &str = "loginctl lock-session $XDG_SESSION_ID";
+const IPC_TIMEOUT: u64 = 10;
+const DEVICES_TIMEOUT: u64 = 10;
+const HANDOVER_TIMEOUT: u64 = 10;
+const X11_TIMEOUT: u64 = 30;
+const GREETD_OOM_SCORE_ADJ: i32 = -1000;
+const GREETER_OOM_SCORE_ADJ: i32 = -900;
+const IPC_MAX_CONNECTIONS: usize = 16;
+const IPC_CONNECTION_RATE: u32 = 10;
+const LOG_LEVEL: &str = if cfg!(feature = "debug") {
+    "debug"
+} else {
+    "info"
+};
+
+#[derive(Debug, Eq, PartialEq, Default)]
+pub enum VtSelection {
+    Next,
+    Current,
+    #[default]
+    None,
+    Specific(usize),
+}
+
+impl FromStr for VtSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" | "\"none\"" => Ok(VtSelection::None),
+            "current" | "\"current\"" => Ok(VtSelection::Current),
+            "next" | "\"next\"" => Ok(VtSelection::Next),
+            v => v
+                .parse()
+                .map(VtSelection::Specific)
+                .map_err(|e| format!("could not parse vt number: {}", e)),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum SingleSession {
+    #[default]
+    Off,
+    Reject,
+    Switch,
+}
+
+impl FromStr for SingleSession {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" | "\"off\"" => Ok(SingleSession::Off),
+            "reject" | "\"reject\"" => Ok(SingleSession::Reject),
+            "switch" | "\"switch\"" => Ok(SingleSession::Switch),
+            v => Err(format!("unknown policy '{}'", v)),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Default)]
+pub struct ConfigSession {
+    pub command: String,
+    pub user: String,
+    pub service: String,
+}
+
+#[derive(Debug, Eq, PartialEq, Default)]
+pub struct ConfigInternal {
+    pub session_worker: usize,
+    pub log_filter: Option<String>,
+    pub debug_ipc: bool,
+    pub ready_fd: Option<i32>,
+    pub pidfile: Option<String>,
+}
+
+#[derive(Debug, Eq, PartialEq, Default)]
+pub struct ConfigTerminal {
+    pub vt: VtSelection,
+    pub switch: bool,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ConfigGeneral {
+    pub source_profile: bool,
+    pub runfile: String,
+    pub service: String,
+    pub seat: String,
+    pub log_level: String,
+    pub admin_socket: String,
+    pub home_unlock_command: String,
+    pub session_dirs: String,
+    pub state_dir: String,
+    pub worker_cgroup: String,
+    pub plymouth: bool,
+    pub oom_score_adj: i32,
+    pub single_session: SingleSession,
+    pub fail_delay_ms: u32,
+    pub kernel_cmdline: bool,
+}
+
+impl Default for ConfigGeneral {
+    fn default() -> Self {
+        ConfigGeneral {
+            source_profile: true,
+            runfile: RUNFILE.to_string(),
+            service: GENERAL_SERVICE.to_string(),
+            seat: "seat0".to_string(),
+            log_level: LOG_LEVEL.to_string(),
+            admin_socket: ADMIN_SOCKET.to_string(),
+            home_unlock_command: String::new(),
+            session_dirs: DEFAULT_SESSION_DIRS.to_string(),
+            state_dir: STATE_DIR.to_string(),
+            worker_cgroup: String::new(),
+            plymouth: true,
+            oom_score_adj: GREETD_OOM_SCORE_ADJ,
+            single_session: Default::default(),
+            fail_delay_ms: 0,
+            kernel_cmdline: false,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Default)]
+pub enum LastSessionAction {
+    #[default]
+    Greeter,
+    Poweroff,
+    Reboot,
+}
+
+impl FromStr for LastSessionAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "greeter" | "\"greeter\"" => Ok(LastSessionAction::Greeter),
+            "poweroff" | "\"poweroff\"" => Ok(LastSessionAction::Poweroff),
+            "reboot" | "\"reboot\"" => Ok(LastSessionAction::Reboot),
+            action => Err(format!("unknown action '{}'", action)),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ConfigPower {
+    pub poweroff_command: String,
+    pub reboot_command: String,
+    pub on_last_session_exit: LastSessionAction,
+    pub respect_inhibitors: bool,
+}
+
+impl Default for ConfigPower {
+    fn default() -> Self {
+        ConfigPower {
+            poweroff_command: POWEROFF_COMMAND.to_string(),
+            reboot_command: REBOOT_COMMAND.to_string(),
+            on_last_session_exit: Default::default(),
+            respect_inhibitors: true,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ConfigIpc {
+    pub timeout: u64,
+    pub idle_timeout: u64,
+    pub max_connections: usize,
+    pub connection_rate: u32,
+}
+
+impl Default for ConfigIpc {
+    fn default() -> Self {
+        ConfigIpc {
+            timeout: IPC_TIMEOUT,
+            idle_timeout: 0,
+            max_connections: IPC_MAX_CONNECTIONS,
+            connection_rate: IPC_CONNECTION_RATE,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Default, Clone)]
+pub struct ConfigBanner {
+    pub text: String,
+    pub file: String,
+    pub required: bool,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct ConfigCommandPolicy {
+    pub commands: Vec<String>,
+    pub prefixes: Vec<String>,
+    pub desktop_sessions: bool,
+}
+
+#[derive(Debug, Eq, PartialEq, Default, Clone, Serialize, Deserialize)]
+pub struct ConfigCleanup {
+    pub wayland_sockets: bool,
+    pub x_locks: bool,
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ConfigX11 {
+    pub server_command: String,
+    pub timeout: u64,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SmartcardAction {
+    Lock,
+    Terminate,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ConfigSmartcard {
+    pub action: SmartcardAction,
+    pub lock_command: String,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ConfigVsock {
+    pub port: u32,
+    pub allowed_cids: Vec<u32>,
+    pub allow_power: bool,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum IoClass {
+    Realtime,
+    BestEffort,
+    Idle,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct ConfigIoPriority {
+    pub class: IoClass,
+    pub level: u8,
+}
+
+impl FromStr for ConfigIoPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (class, level) = match s.split_once(':') {
+            Some((class, level)) => (class, Some(level)),
+            None => (s, None),
+        };
+        let class = match class {
+            "realtime" => IoClass::Realtime,
+            "best-effort" => IoClass::BestEffort,
+            "idle" => IoClass::Idle,
+            class => return Err(format!("unknown I/O scheduling class '{}'", class)),
+        };
+        let level = match (class, level) {
+            (IoClass::Idle, Some(_)) => return Err("the idle class has no level".to_string()),
+            (_, Some(level)) => level
+                .parse()
+                .ok()
+                .filter(|level| *level <= 7)
+                .ok_or_else(|| format!("invalid level '{}', expected 0-7", level))?,
+            (_, None) => 4,
+        };
+        Ok(ConfigIoPriority { class, level })
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct ConfigRlimit {
+    pub soft: u64,
+    pub hard: u64,
+}
+
+impl FromStr for ConfigRlimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let value = |v: &str| match v {
+            "infinity" => Ok(u64::MAX),
+            v => v
+                .parse()
+                .map_err(|e| format!("invalid limit '{}': {}", v, e)),
+        };
+        let (soft, hard) = match s.split_once(':') {
+            Some((soft, hard)) => (value(soft)?, value(hard)?),
+            None => (value(s)?, value(s)?),
+        };
+        if soft > hard {
+            return Err("soft limit exceeds hard limit".to_string());
+        }
+        Ok(ConfigRlimit { soft, hard })
+    }
+}
+
+/// Hardening applied to greeter sessions.
+#[derive(Debug, Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ConfigSandbox {
+    pub no_new_privs: bool,
+    pub private_tmp: bool,
+    pub private_network: bool,
+    pub groups: Option<Vec<String>>,
+}
+
+/// Process attributes applied to sessions of a class before they are started.
+#[derive(Debug, Eq, PartialEq, Default, Clone, Serialize, Deserialize)]
+pub struct ConfigLimits {
+    pub umask: Option<u32>,
+    pub nice: Option<i32>,
+    pub ionice: Option<ConfigIoPriority>,
+    pub nofile: Option<ConfigRlimit>,
+    pub core: Option<ConfigRlimit>,
+    pub memlock: Option<ConfigRlimit>,
+    pub oom_score_adj: i32,
+    pub sandbox: Option<ConfigSandbox>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub struct ConfigHandover {
+    pub timeout: u64,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ConfigDevices {
+    pub wait: Vec<String>,
+    pub timeout: u64,
+}
+
+#[derive(Debug, Eq, PartialEq, Default)]
+pub struct ConfigFile {
+    pub terminal: ConfigTerminal,
+    pub general: ConfigGeneral,
+    pub default_session: ConfigSession,
+    pub initial_session: Option<ConfigSession>,
+    pub power: ConfigPower,
+    pub ipc: ConfigIpc,
+    pub banner: ConfigBanner,
+    pub command_policy: Option<ConfigCommandPolicy>,
+    pub cleanup: ConfigCleanup,
+    pub x11: Option<ConfigX11>,
+    pub vsock: Option<ConfigVsock>,
+    pub smartcard: Option<ConfigSmartcard>,
+    pub devices: Option<ConfigDevices>,
+    pub greeter_limits: ConfigLimits,
+    pub user_limits: ConfigLimits,
+    pub handover: Option<ConfigHandover>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct Config {
+    pub file: ConfigFile,
+    pub internal: ConfigInternal,
+}
+
+impl Config {
+    /// The log filter to use, in env-filter syntax. Directives from the command
+    /// line take precedence over the configured log level.
+    pub fn log_filter(&self) -> String {
+        let mut filter = self.file.general.log_level.clone();
+        if self.internal.debug_ipc {
+            filter.push_str(",greetd_core::ipc=debug");
+        }
+        if let Some(extra) = &self.internal.log_filter {
+            filter.push(',');
+            filter.push_str(extra);
+        }
+        filter
+    }
+}
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options]", program);
+    println!("{}", opts.usage(&brief));
+    println!("For more details, see greetd(1).");
+}
+
+fn maybe_unquote(s: &str) -> Result<String, Error> {
+    Ok(match s.chars().next() {
+        Some('"') | Some('\'') => unquote(s).map_err(|e| Error::ConfigError(format!("{}", e)))?,
+        _ => s.to_string(),
+    })
+}
+
+fn parse_oom_score_adj(value: Option<&&str>, name: &str, default: i32) -> Result<i32, Error> {
+    let value = value
+        .map(|s| s.parse())
+        .unwrap_or(Ok(default))
+        .map_err(|e| format!("could not parse {}.oom_score_adj: {}", name, e))?;
+    match value {
+        -1000..=1000 => Ok(value),
+        _ => Err(format!("could not parse {}.oom_score_adj: out of range", name).into()),
+    }
+}
+
+fn parse_limits(
+    section: Option<&HashMap<&str, &str>>,
+    name: &str,
+    oom_score_adj: i32,
+) -> Result<ConfigLimits, Error> {
+    let section = match section {
+        Some(section) => section,
+        None => {
+            return Ok(ConfigLimits {
+                oom_score_adj,
+                ..Default::default()
+            })
+        }
+    };
+    let rlimit = |key: &str| {
+        section
+            .get(key)
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|e| format!("could not parse {}.{}: {}", name, key, e))
+    };
+    Ok(ConfigLimits {
+        umask: section
+            .get("umask")
+            .map(|s| u32::from_str_radix(s, 8))
+            .transpose()
+            .map_err(|e| format!("could not parse {}.umask: {}", name, e))?
+            .map(|umask| match umask {
+                0..=0o777 => Ok(umask),
+                _ => Err(format!("could not parse {}.umask: out of range", name)),
+            })
+            .transpose()?,
+        nice: section
+            .get("nice")
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|e| format!("could not parse {}.nice: {}", name, e))?
+            .map(|nice| match nice {
+                -20..=19 => Ok(nice),
+                _ => Err(format!("could not parse {}.nice: out of range", name)),
+            })
+            .transpose()?,
+        ionice: section
+            .get("ionice")
+            .map(|s| s.parse())
+            .transpose()
+            .map_err(|e| format!("could not parse {}.ionice: {}", name, e))?,
+        nofile: rlimit("nofile")?,
+        core: rlimit("core")?,
+        memlock: rlimit("memlock")?,
+        oom_score_adj: parse_oom_score_adj(section.get("oom_score_adj"), name, oom_score_adj)?,
+        sandbox: None,
+    })
+}
+
+fn parse_sandbox(section: Option<&HashMap<&str, &str>>) -> Result<Option<ConfigSandbox>, Error> {
+    let section = match section {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let flag = |key: &str, default: &str| {
+        section
+            .get(key)
+            .unwrap_or(&default)
+            .parse()
+            .map_err(|e| format!("could not parse greeter_sandbox.{}: {}", key, e))
+    };
+    let groups = match section.get("groups") {
+        Some(groupsstr) => {
+            let groups = maybe_unquote(groupsstr)
+                .map_err(|e| format!("unable to read greeter_sandbox.groups: {}", e))?;
+            Some(
+                groups
+                    .split(':')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+    Ok(Some(ConfigSandbox {
+        no_new_privs: flag("no_new_privs", "true")?,
+        private_tmp: flag("private_tmp", "true")?,
+        private_network: flag("private_network", "false")?,
+        groups,
+    }))
+}
+
+fn parse_config(config_str: &str) -> Result<ConfigFile, Error> {
+    let config = inish::parse(config_str)?;
+    let general = match config.get("general") {
+        Some(section) => {
+            let runfilestr = section.get("runfile").unwrap_or(&RUNFILE);
+            let runfile = maybe_unquote(runfilestr)
+                .map_err(|e| format!("unable to read general.runfile: {}", e))?;
+
+            let servicestr = section.get("service").unwrap_or(&GENERAL_SERVICE);
+            let service = maybe_unquote(servicestr)
+                .map_err(|e| format!("unable to read general.service: {}", e))?;
+
+            let seatstr = section.get("seat").unwrap_or(&"seat0");
+            let seat = maybe_unquote(seatstr)
+                .map_err(|e| format!("unable to read default_session.seat: {}", e))?;
+
+            let log_levelstr = section.get("log_level").unwrap_or(&LOG_LEVEL);
+            let log_level = maybe_unquote(log_levelstr)
+                .map_err(|e| format!("unable to read general.log_level: {}", e))?;
+
+            let admin_socketstr = section.get("admin_socket").unwrap_or(&ADMIN_SOCKET);
+            let admin_socket = maybe_unquote(admin_socketstr)
+                .map_err(|e| format!("unable to read general.admin_socket: {}", e))?;
+
+            let home_unlock_commandstr = section.get("home_unlock_command").unwrap_or(&"");
+            let home_unlock_command = maybe_unquote(home_unlock_commandstr)
+                .map_err(|e| format!("unable to read general.home_unlock_command: {}", e))?;
+
+            let session_dirsstr = section.get("session_dirs").unwrap_or(&DEFAULT_SESSION_DIRS);
+            let session_dirs = maybe_unquote(session_dirsstr)
+                .map_err(|e| format!("unable to read general.session_dirs: {}", e))?;
+
+            let state_dirstr = section.get("state_dir").unwrap_or(&STATE_DIR);
+            let state_dir = maybe_unquote(state_dirstr)
+                .map_err(|e| format!("unable to read general.state_dir: {}", e))?;
+
+            let worker_cgroupstr = section.get("worker_cgroup").unwrap_or(&"");
+            let worker_cgroup = maybe_unquote(worker_cgroupstr)
+                .map_err(|e| format!("unable to read general.worker_cgroup: {}", e))?;
+
+            ConfigGeneral {
+                source_profile: section
+                    .get("source_profile")
+                    .unwrap_or(&"true")
+                    .parse()
+                    .map_err(|e| format!("could not parse source_profile: {}", e))?,
+                runfile,
+                service,
+                seat,
+                log_level,
+                admin_socket,
+                home_unlock_command,
+                session_dirs,
+                state_dir,
+                worker_cgroup,
+                plymouth: section
+                    .get("plymouth")
+                    .unwrap_or(&"true")
+                    .parse()
+                    .map_err(|e| format!("could not parse general.plymouth: {}", e))?,
+                oom_score_adj: parse_oom_score_adj(
+                    section.get("oom_score_adj"),
+                    "general",
+                    GREETD_OOM_SCORE_ADJ,
+                )?,
+                single_session: section
+                    .get("single_session")
+                    .unwrap_or(&"off")
+                    .parse()
+                    .map_err(|e| format!("could not parse general.single_session: {}", e))?,
+                fail_delay_ms: section
+                    .get("fail_delay_ms")
+                    .unwrap_or(&"0")
+                    .parse()
+                    .map_err(|e| format!("could not parse general.fail_delay_ms: {}", e))?,
+                kernel_cmdline: section
+                    .get("kernel_cmdline")
+                    .unwrap_or(&"false")
+                    .parse()
+                    .map_err(|e| format!("could not parse general.kernel_cmdline: {}", e))?,
+            }
+        }
+
+        None => Default::default(),
+    };
+
+    let default_session = match config.get("default_session") {
+        Some(section) => {
+            let commandstr = section
+                .get("command")
+                .ok_or("default_session contains no command")?;
+            let command = maybe_unquote(commandstr)
+                .map_err(|e| format!("unable to read default_session.command: {}", e))?;
+
+            let userstr = section.get("user").unwrap_or(&"greeter");
+            let user = maybe_unquote(userstr)
+                .map_err(|e| format!("unable to read default_session.user: {}", e))?;
+
+            let servicestr = section.get("service").unwrap_or(&GREETER_SERVICE);
+            let service = maybe_unquote(servicestr)
+                .map_err(|e| format!("unable to read default_session.service: {}", e))?;
+
+            Ok(ConfigSession {
+                command,
+                user,
+                service,
+            })
+        }
+        None => Err("no default_session specified"),
+    }?;
+
+    let initial_session = match config.get("initial_session") {
+        Some(section) => {
+            let commandstr = section
+                .get("command")
+                .ok_or("initial_session contains no command")?;
+            let command = maybe_unquote(commandstr)
+                .map_err(|e| format!("unable to read initial_session.command: {}", e))?;
+
+            let userstr = section
+                .get("user")
+                .ok_or("initial_session contains no user")?;
+            let user = maybe_unquote(userstr)
+                .map_err(|e| format!("unable to read initial_session.user: {}", e))?;
+
+            let generalservicestr = general.service.as_str();
+            let servicestr = section.get("service").unwrap_or(&generalservicestr);
+            let service = maybe_unquote(servicestr)
+                .map_err(|e| format!("unable to read initial_session.service: {}", e))?;
+
+            Some(ConfigSession {
+                command,
+                user,
+                service,
+            })
+        }
+        None => None,
+    };
+
+    let terminal = match config.get("terminal") {
+        Some(section) => Ok(ConfigTerminal {
+            vt: maybe_unquote(section.get("vt").ok_or("VT not specified")?)
+                .map_err(|e| format!("unable to read terminal.vt: {}", e))?
+                .as_str()
+                .parse()?,
+            switch: section
+                .get("switch")
+                .unwrap_or(&"true")
+                .parse()
+                .map_err(|e| format!("could not parse switch: {}", e))?,
+        }),
+        None => Err("no terminal specified"),
+    }?;
+
+    let power = match config.get("power") {
+        Some(section) => {
+            let poweroffstr = section.get("poweroff_command").unwrap_or(&POWEROFF_COMMAND);
+            let poweroff_command = maybe_unquote(poweroffstr)
+                .map_err(|e| format!("unable to read power.poweroff_command: {}", e))?;
+
+            let rebootstr = section.get("reboot_command").unwrap_or(&REBOOT_COMMAND);
+            let reboot_command = maybe_unquote(rebootstr)
+                .map_err(|e| format!("unable to read power.reboot_command: {}", e))?;
+
+            ConfigPower {
+                poweroff_command,
+                reboot_command,
+                on_last_session_exit: section
+                    .get("on_last_session_exit")
+                    .unwrap_or(&"greeter")
+                    .parse()
+                    .map_err(|e| format!("could not parse power.on_last_session_exit: {}", e))?,
+                respect_inhibitors: section
+                    .get("respect_inhibitors")
+                    .unwrap_or(&"true")
+                    .parse()
+                    .map_err(|e| format!("could not parse power.respect_inhibitors: {}", e))?,
+            }
+        }
+        None => Default::default(),
+    };
+
+    let ipc = match config.get("ipc") {
+        Some(section) => ConfigIpc {
+            timeout: section
+                .get("timeout")
+                .map(|s| s.parse())
+                .unwrap_or(Ok(IPC_TIMEOUT))
+                .map_err(|e| format!("could not parse ipc.timeout: {}", e))?,
+            idle_timeout: section
+                .get("idle_timeout")
+                .unwrap_or(&"0")
+                .parse()
+                .map_err(|e| format!("could not parse ipc.idle_timeout: {}", e))?,
+            max_connections: section
+                .get("max_connections")
+                .map(|s| s.parse())
+                .unwrap_or(Ok(IPC_MAX_CONNECTIONS))
+                .map_err(|e| format!("could not parse ipc.max_connections: {}", e))?,
+            connection_rate: section
+                .get("connection_rate")
+                .map(|s| s.parse())
+                .unwrap_or(Ok(IPC_CONNECTION_RATE))
+                .map_err(|e| format!("could not parse ipc.connection_rate: {}", e))?,
+        },
+        None => Default::default(),
+    };
+
+    let banner = match config.get("banner") {
+        Some(section) => ConfigBanner {
+            text: maybe_unquote(section.get("text").unwrap_or(&""))
+                .map_err(|e| format!("unable to read banner.text: {}", e))?,
+            file: maybe_unquote(section.get("file").unwrap_or(&""))
+                .map_err(|e| format!("unable to read banner.file: {}", e))?,
+            required: section
+                .get("required")
+                .unwrap_or(&"false")
+                .parse()
+                .map_err(|e| format!("could not parse banner.required: {}", e))?,
+        },
+        None => Default::default(),
+    };
+
+    let command_policy = match config.get("command_policy") {
+        Some(section) => {
+            let commandsstr = section.get("commands").unwrap_or(&"");
+            let commands = maybe_unquote(commandsstr)
+                .map_err(|e| format!("unable to read command_policy.commands: {}", e))?;
+
+            let prefixesstr = section.get("prefixes").unwrap_or(&"");
+            let prefixes = maybe_unquote(prefixesstr)
+                .map_err(|e| format!("unable to read command_policy.prefixes: {}", e))?;
+
+            Some(ConfigCommandPolicy {
+                commands: commands
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                prefixes: prefixes
+                    .split(':')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                desktop_sessions: section
+                    .get("desktop_sessions")
+                    .unwrap_or(&"false")
+                    .parse()
+                    .map_err(|e| {
+                        format!("could not parse command_policy.desktop_sessions: {}", e)
+                    })?,
+            })
+        }
+        None => None,
+    };
+
+    let cleanup = match config.get("cleanup") {
+        Some(section) => {
+            let pathsstr = section.get("paths").unwrap_or(&"");
+            let paths = maybe_unquote(pathsstr)
+                .map_err(|e| format!("unable to read cleanup.paths: {}", e))?;
+
+            ConfigCleanup {
+                wayland_sockets: section
+                    .get("wayland_sockets")
+                    .unwrap_or(&"false")
+                    .parse()
+                    .map_err(|e| format!("could not parse cleanup.wayland_sockets: {}", e))?,
+                x_locks: section
+                    .get("x_locks")
+                    .unwrap_or(&"false")
+                    .parse()
+                    .map_err(|e| format!("could not parse cleanup.x_locks: {}", e))?,
+                paths: paths
+                    .split(':')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            }
+        }
+        None => Default::default(),
+    };
+
+    let x11 = match config.get("x11") {
+        Some(section) => {
+            let server_commandstr = section.get("server_command").unwrap_or(&X11_SERVER_COMMAND);
+            let server_command = maybe_unquote(server_commandstr)
+                .map_err(|e| format!("unable to read x11.server_command: {}", e))?;
+
+            Some(ConfigX11 {
+                server_command,
+                timeout: section
+                    .get("timeout")
+                    .map(|s| s.parse())
+                    .unwrap_or(Ok(X11_TIMEOUT))
+                    .map_err(|e| format!("could not parse x11.timeout: {}", e))?,
+            })
+        }
+        None => None,
+    };
+
+    let vsock = match config.get("vsock") {
+        Some(section) => {
+            let port = section
+                .get("port")
+                .ok_or("vsock.port is required")?
+                .parse()
+                .map_err(|e| format!("could not parse vsock.port: {}", e))?;
+            let allowed_cids = section
+                .get("allowed_cids")
+                .unwrap_or(&"")
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse())
+                .collect::<Result<Vec<u32>, _>>()
+                .map_err(|e| format!("could not parse vsock.allowed_cids: {}", e))?;
+            let allow_power = section
+                .get("allow_power")
+                .unwrap_or(&"false")
+                .parse()
+                .map_err(|e| format!("could not parse vsock.allow_power: {}", e))?;
+
+            Some(ConfigVsock {
+                port,
+                allowed_cids,
+                allow_power,
+            })
+        }
+        None => None,
+    };
+
+    let smartcard = match config.get("smartcard") {
+        Some(section) => {
+            let action = match section.get("action").unwrap_or(&"lock").trim() {
+                "lock" => SmartcardAction::Lock,
+                "terminate" => SmartcardAction::Terminate,
+                action => {
+                    return Err(format!(
+                        "could not parse smartcard.action: unknown action '{}'",
+                        action
+                    )
+                    .into())
+                }
+            };
+            let lock_commandstr = section
+                .get("lock_command")
+                .unwrap_or(&SMARTCARD_LOCK_COMMAND);
+            let lock_command = maybe_unquote(lock_commandstr)
+                .map_err(|e| format!("unable to read smartcard.lock_command: {}", e))?;
+
+            Some(ConfigSmartcard {
+                action,
+                lock_command,
+            })
+        }
+        None => None,
+    };
+
+    let devices = match config.get("devices") {
+        Some(section) => {
+            let waitstr = section.get("wait").unwrap_or(&"");
+            let wait = maybe_unquote(waitstr)
+                .map_err(|e| format!("unable to read devices.wait: {}", e))?;
+
+            Some(ConfigDevices {
+                wait: wait
+                    .split(':')
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                timeout: section
+                    .get("timeout")
+                    .map(|s| s.parse())
+                    .unwrap_or(Ok(DEVICES_TIMEOUT))
+                    .map_err(|e| format!("could not parse devices.timeout: {}", e))?,
+            })
+        }
+        None => None,
+    };
+
+    let greeter_limits = ConfigLimits {
+        sandbox: parse_sandbox(config.get("greeter_sandbox"))?,
+        ..parse_limits(
+            config.get("greeter_limits"),
+            "greeter_limits",
+            GREETER_OOM_SCORE_ADJ,
+        )?
+    };
+    // Sessions are not protected, even though they inherit the protection of
+    // greetd itself.
+    let user_limits = parse_limits(config.get("user_limits"), "user_limits", 0)?;
+
+    let handover = match config.get("handover") {
+        Some(section) => Some(ConfigHandover {
+            timeout: section
+                .get("timeout")
+                .map(|s| s.parse())
+                .unwrap_or(Ok(HANDOVER_TIMEOUT))
+                .map_err(|e| format!("could not parse handover.timeout: {}", e))?,
+        }),
+        None => None,
+    };
+
+    Ok(ConfigFile {
+        initial_session,
+        default_session,
+        general,
+        terminal,
+        power,
+        ipc,
+        banner,
+        command_policy,
+        cleanup,
+        x11,
+        vsock,
+        smartcard,
+        devices,
+        greeter_limits,
+        user_limits,
+        handover,
+    })
+}
+
+pub fn read_config() -> Result<Config, Error> {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("s", "socket-path", "socket path to use", "SOCKET_PATH");
+    opts.optopt("c", "config", "config file to use", "CONFIG_FILE");
+    opts.optopt("", "vt", "use the specified vt", "VT");
+    opts.optopt(
+        "",
+        "log-filter",
+        "log filter directives, overriding the configured log level",
+        "FILTER",
+    );
+    opts.optflag(
+        "",
+        "debug-ipc",
+        "log all protocol messages, with secrets redacted",
+    );
+    opts.optopt(
+        "",
+        "ready-fd",
+        "notify readiness by writing a newline to the given fd",
+        "FD",
+    );
+    opts.optopt("", "pidfile", "write our PID to the given file", "PIDFILE");
+    opts.optopt(
+        "w",
+        "session-worker",
+        "start a session worker (internal)",
+        "FD",
+    );
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => return Err(format!("could not parse arguments: {}", f).into()),
+    };
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        std::process::exit(0);
+    }
+
+    let internal = ConfigInternal {
+        session_worker: matches
+            .opt_get("session-worker")
+            .expect("unable to parse session-worker")
+            .unwrap_or(0),
+        log_filter: matches.opt_str("log-filter"),
+        debug_ipc: matches.opt_present("debug-ipc"),
+        ready_fd: matches
+            .opt_get("ready-fd")
+            .map_err(|e| format!("could not parse --ready-fd: {}", e))?,
+        pidfile: matches.opt_str("pidfile"),
+    };
+
+    if internal.session_worker > 0 {
+        return Ok(Config {
+            file: Default::default(),
+            internal,
+        });
+    }
+
+    let config_str = match matches.opt_str("config") {
+        Some(v) => read_to_string(v),
+        None => read_to_string("/etc/greetd/greetd.conf")
+            .or_else(|_| read_to_string("/etc/greetd/config.toml")),
+    }?;
+    let mut file = parse_config(&config_str).map_err(|e| Error::ConfigError(e.to_string()))?;
+
+    if let Some(vt) = matches.opt_str("vt") {
+        file.terminal.vt = vt.parse()?
+    };
+
+    if file.general.kernel_cmdline {
+        // Without /proc, there is no command line to honor.
+        if let Ok(cmdline) = read_to_string(cmdline::CMDLINE) {
+            cmdline::apply(&mut file, &cmdline)?;
+        }
+    }
+
+    if file.default_session.command.is_empty() {
+        return Err(Error::ConfigError(
+            "no default session user specified".to_string(),
+        ));
+    }
+    if file.default_session.user.is_empty() {
+        return Err(Error::ConfigError(
+            "no default session user specified".to_string(),
+        ));
+    }
+    if let Some(s) = &file.initial_session {
+        if s.user.is_empty() {
+            return Err(Error::ConfigError(
+                "initial session enabled but contained no user".to_string(),
+            ));
+        }
+        if s.command.is_empty() {
+            return Err(Error::ConfigError(
+                "initial session enabled but contained no command".to_string(),
+            ));
+        }
+    }
+
+    Ok(Config { file, internal })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimal_config() {
+        let config = parse_config(
+            "
+[terminal]
+vt = 1
+[default_session]
+command = \"agreety\"
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config,
+            ConfigFile {
+                terminal: ConfigTerminal {
+                    vt: VtSelection::Specific(1),
+                    switch: true,
+                },
+                default_session: ConfigSession {
+                    command: "agreety".to_string(),
+                    user: "greeter".to_string(),
+                    service: "greetd-greeter".to_string(),
+                },
+                general: Default::default(),
+                initial_session: None,
+                power: Default::default(),
+                ipc: Default::default(),
+                banner: Default::default(),
+                command_policy: None,
+                cleanup: Default::default(),
+                x11: None,
+                vsock: None,
+                smartcard: None,
+                devices: None,
+                greeter_limits: ConfigLimits {
+                    oom_score_adj: GREETER_OOM_SCORE_ADJ,
+                    ..Default::default()
+                },
+                user_limits: Default::default(),
+                handover: None,
+            }
+        );
+    }
+
+    #[test]
+    fn initial_session() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[initial_session]
+command = \"sway\"
+user = \"john\"
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config,
+            ConfigFile {
+                terminal: ConfigTerminal {
+                    vt: VtSelection::Specific(1),
+                    switch: true,
+                },
+                default_session: ConfigSession {
+                    command: "agreety".to_string(),
+                    user: "greeter".to_string(),
+                    service: "greetd-greeter".to_string(),
+                },
+                general: Default::default(),
+                initial_session: Some(ConfigSession {
+                    command: "sway".to_string(),
+                    user: "john".to_string(),
+                    service: "greetd".to_string(),
+                }),
+                power: Default::default(),
+                ipc: Default::default(),
+                banner: Default::default(),
+                command_policy: None,
+                cleanup: Default::default(),
+                x11: None,
+                vsock: None,
+                smartcard: None,
+                devices: None,
+                greeter_limits: ConfigLimits {
+                    oom_score_adj: GREETER_OOM_SCORE_ADJ,
+                    ..Default::default()
+                },
+                user_limits: Default::default(),
+                handover: None,
+            }
+        );
+    }
+
+    #[test]
+    fn general() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[general]
+source_profile = false
+runfile = \"/path/to/greetd.state\"
+admin_socket = \"\"
+home_unlock_command = \"fscrypt unlock --quiet $HOME\"
+state_dir = \"/var/lib/greetd-seat1\"
+worker_cgroup = \"/system.slice/greetd.service/workers\"
+plymouth = false
+single_session = reject
+fail_delay_ms = 2000
+kernel_cmdline = true
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config,
+            ConfigFile {
+                terminal: ConfigTerminal {
+                    vt: VtSelection::Specific(1),
+                    switch: true,
+                },
+                default_session: ConfigSession {
+                    command: "agreety".to_string(),
+                    user: "greeter".to_string(),
+                    service: "greetd-greeter".to_string(),
+                },
+                general: ConfigGeneral {
+                    source_profile: false,
+                    runfile: "/path/to/greetd.state".to_string(),
+                    service: "greetd".to_string(),
+                    seat: "seat0".to_string(),
+                    log_level: LOG_LEVEL.to_string(),
+                    admin_socket: "".to_string(),
+                    home_unlock_command: "fscrypt unlock --quiet $HOME".to_string(),
+                    session_dirs: "/usr/share/wayland-sessions:/usr/share/xsessions".to_string(),
+                    state_dir: "/var/lib/greetd-seat1".to_string(),
+                    worker_cgroup: "/system.slice/greetd.service/workers".to_string(),
+                    plymouth: false,
+                    oom_score_adj: GREETD_OOM_SCORE_ADJ,
+                    single_session: SingleSession::Reject,
+                    fail_delay_ms: 2000,
+                    kernel_cmdline: true,
+                },
+                initial_session: None,
+                power: Default::default(),
+                ipc: Default::default(),
+                banner: Default::default(),
+                command_policy: None,
+                cleanup: Default::default(),
+                x11: None,
+                vsock: None,
+                smartcard: None,
+                devices: None,
+                greeter_limits: ConfigLimits {
+                    oom_score_adj: GREETER_OOM_SCORE_ADJ,
+                    ..Default::default()
+                },
+                user_limits: Default::default(),
+                handover: None,
+            }
+        );
+    }
+
+    #[test]
+    fn log_level() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[general]
+log_level = \"warn,greetd_core::session=debug\"
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(config.general.log_level, "warn,greetd_core::session=debug");
+
+        let mut config = Config {
+            file: config,
+            internal: Default::default(),
+        };
+        assert_eq!(config.log_filter(), "warn,greetd_core::session=debug");
+        config.internal.log_filter = Some("greetd_core::pam=trace".to_string());
+        assert_eq!(
+            config.log_filter(),
+            "warn,greetd_core::session=debug,greetd_core::pam=trace"
+        );
+        config.internal.debug_ipc = true;
+        assert_eq!(
+            config.log_filter(),
+            "warn,greetd_core::session=debug,greetd_core::ipc=debug,greetd_core::pam=trace"
+        );
+    }
+
+    #[test]
+    fn invalid_general() {
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[general]
+source_profile = fals
+",
+        )
+        .is_err())
+    }
+
+    #[test]
+    fn terminal() {
+        let config = parse_config(
+            "
+[default_session]\ncommand = \"agreety\"
+[terminal]
+vt = 1
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config,
+            ConfigFile {
+                terminal: ConfigTerminal {
+                    vt: VtSelection::Specific(1),
+                    switch: true,
+                },
+                default_session: ConfigSession {
+                    command: "agreety".to_string(),
+                    user: "greeter".to_string(),
+                    service: "greetd-greeter".to_string(),
+                },
+                general: Default::default(),
+                initial_session: None,
+                power: Default::default(),
+                ipc: Default::default(),
+                banner: Default::default(),
+                command_policy: None,
+                cleanup: Default::default(),
+                x11: None,
+                vsock: None,
+                smartcard: None,
+                devices: None,
+                greeter_limits: ConfigLimits {
+                    oom_score_adj: GREETER_OOM_SCORE_ADJ,
+                    ..Default::default()
+                },
+                user_limits: Default::default(),
+                handover: None,
+            }
+        );
+        let config = parse_config(
+            "
+[default_session]\ncommand = \"agreety\"
+[terminal]
+vt = next
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config,
+            ConfigFile {
+                terminal: ConfigTerminal {
+                    vt: VtSelection::Next,
+                    switch: true,
+                },
+                default_session: ConfigSession {
+                    command: "agreety".to_string(),
+                    user: "greeter".to_string(),
+                    service: "greetd-greeter".to_string(),
+                },
+                general: Default::default(),
+                initial_session: None,
+                power: Default::default(),
+                ipc: Default::default(),
+                banner: Default::default(),
+                command_policy: None,
+                cleanup: Default::default(),
+                x11: None,
+                vsock: None,
+                smartcard: None,
+                devices: None,
+                greeter_limits: ConfigLimits {
+                    oom_score_adj: GREETER_OOM_SCORE_ADJ,
+                    ..Default::default()
+                },
+                user_limits: Default::default(),
+                handover: None,
+            }
+        );
+        let config = parse_config(
+            "
+[default_session]\ncommand = \"agreety\"
+[terminal]
+vt = current
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config,
+            ConfigFile {
+                terminal: ConfigTerminal {
+                    vt: VtSelection::Current,
+                    switch: true,
+                },
+                default_session: ConfigSession {
+                    command: "agreety".to_string(),
+                    user: "greeter".to_string(),
+                    service: "greetd-greeter".to_string(),
+                },
+                general: Default::default(),
+                initial_session: None,
+                power: Default::default(),
+                ipc: Default::default(),
+                banner: Default::default(),
+                command_policy: None,
+                cleanup: Default::default(),
+                x11: None,
+                vsock: None,
+                smartcard: None,
+                devices: None,
+                greeter_limits: ConfigLimits {
+                    oom_score_adj: GREETER_OOM_SCORE_ADJ,
+                    ..Default::default()
+                },
+                user_limits: Default::default(),
+                handover: None,
+            }
+        );
+    }
+
+    #[test]
+    fn power() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[power]
+poweroff_command = \"loginctl poweroff\"
+on_last_session_exit = \"poweroff\"
+respect_inhibitors = false
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.power,
+            ConfigPower {
+                poweroff_command: "loginctl poweroff".to_string(),
+                reboot_command: "systemctl reboot".to_string(),
+                on_last_session_exit: LastSessionAction::Poweroff,
+                respect_inhibitors: false,
+            }
+        );
+
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[power]
+on_last_session_exit = halt
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn ipc() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[ipc]
+idle_timeout = 300
+max_connections = 0
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.ipc,
+            ConfigIpc {
+                timeout: 10,
+                idle_timeout: 300,
+                max_connections: 0,
+                connection_rate: 10,
+            }
+        );
+
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[ipc]
+timeout = soon
+",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn banner() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[banner]
+text = \"Authorized use only.\\nActivity may be monitored.\"
+required = true
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.banner,
+            ConfigBanner {
+                text: "Authorized use only.\nActivity may be monitored.".to_string(),
+                file: "".to_string(),
+                required: true,
+            }
+        );
+
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[banner]
+required = maybe
+",
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn command_policy() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[command_policy]
+commands = \"sway --unsupported-gpu; startplasma-wayland\"
+prefixes = /usr/bin/:/usr/local/bin/
+desktop_sessions = true
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.command_policy,
+            Some(ConfigCommandPolicy {
+                commands: vec![
+                    "sway --unsupported-gpu".to_string(),
+                    "startplasma-wayland".to_string()
+                ],
+                prefixes: vec!["/usr/bin/".to_string(), "/usr/local/bin/".to_string()],
+                desktop_sessions: true,
+            })
+        );
+    }
+
+    #[test]
+    fn cleanup() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[cleanup]
+wayland_sockets = true
+paths = \"$XDG_RUNTIME_DIR/sway-ipc.*:/tmp/$USER-*\"
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.cleanup,
+            ConfigCleanup {
+                wayland_sockets: true,
+                x_locks: false,
+                paths: vec![
+                    "$XDG_RUNTIME_DIR/sway-ipc.*".to_string(),
+                    "/tmp/$USER-*".to_string()
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn x11() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[x11]
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.x11,
+            Some(ConfigX11 {
+                server_command: "/usr/bin/Xorg -nolisten tcp -noreset -keeptty".to_string(),
+                timeout: 30,
+            })
+        );
+
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[x11]
+timeout = 5
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(config.x11.map(|x| x.timeout), Some(5));
+    }
+
+    #[test]
+    fn vsock() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[vsock]
+port = 1024
+allowed_cids = 3, 42
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.vsock,
+            Some(ConfigVsock {
+                port: 1024,
+                allowed_cids: vec![3, 42],
+                allow_power: false,
+            })
+        );
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[vsock]
+port = 1024
+allow_power = true
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(config.vsock.map(|v| v.allow_power), Some(true));
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[vsock]
+allowed_cids = 3
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn smartcard() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[smartcard]
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.smartcard,
+            Some(ConfigSmartcard {
+                action: SmartcardAction::Lock,
+                lock_command: "loginctl lock-session $XDG_SESSION_ID".to_string(),
+            })
+        );
+
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[smartcard]
+action = terminate
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.smartcard.map(|s| s.action),
+            Some(SmartcardAction::Terminate)
+        );
+
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[smartcard]
+action = reboot
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn devices() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[devices]
+wait = \"/dev/dri/card0:/dev/input/event*\"
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.devices,
+            Some(ConfigDevices {
+                wait: vec![
+                    "/dev/dri/card0".to_string(),
+                    "/dev/input/event*".to_string()
+                ],
+                timeout: 10,
+            })
+        );
+
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[devices]
+wait = /dev/dri/card1
+timeout = 30
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(config.devices.map(|d| d.timeout), Some(30));
+
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[devices]
+timeout = soon
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn limits() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[greeter_limits]
+nice = 5
+ionice = idle
+[user_limits]
+umask = 027
+ionice = best-effort:2
+nofile = 1024:524288
+core = 0
+memlock = infinity
+oom_score_adj = 100
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.greeter_limits,
+            ConfigLimits {
+                nice: Some(5),
+                ionice: Some(ConfigIoPriority {
+                    class: IoClass::Idle,
+                    level: 4,
+                }),
+                oom_score_adj: GREETER_OOM_SCORE_ADJ,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            config.user_limits,
+            ConfigLimits {
+                umask: Some(0o027),
+                nice: None,
+                ionice: Some(ConfigIoPriority {
+                    class: IoClass::BestEffort,
+                    level: 2,
+                }),
+                nofile: Some(ConfigRlimit {
+                    soft: 1024,
+                    hard: 524288,
+                }),
+                core: Some(ConfigRlimit { soft: 0, hard: 0 }),
+                memlock: Some(ConfigRlimit {
+                    soft: u64::MAX,
+                    hard: u64::MAX,
+                }),
+                oom_score_adj: 100,
+                sandbox: None,
+            }
+        );
+
+        for invalid in [
+            "umask = 0800",
+            "umask = 1777",
+            "nice = 20",
+            "ionice = fast",
+            "ionice = idle:3",
+            "ionice = realtime:8",
+            "nofile = 2048:1024",
+            "core = unlimited",
+            "oom_score_adj = -1001",
+        ] {
+            assert!(
+                parse_config(&format!(
+                    "[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"\n[user_limits]\n{}",
+                    invalid
+                ))
+                .is_err(),
+                "{} should not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn sandbox() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[greeter_sandbox]
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.greeter_limits.sandbox,
+            Some(ConfigSandbox {
+                no_new_privs: true,
+                private_tmp: true,
+                private_network: false,
+                groups: None,
+            })
+        );
+        assert_eq!(config.user_limits.sandbox, None);
+
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[greeter_limits]
+nice = 5
+[greeter_sandbox]
+private_tmp = false
+private_network = true
+groups = video:input
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(config.greeter_limits.nice, Some(5));
+        assert_eq!(
+            config.greeter_limits.sandbox,
+            Some(ConfigSandbox {
+                no_new_privs: true,
+                private_tmp: false,
+                private_network: true,
+                groups: Some(vec!["video".to_string(), "input".to_string()]),
+            })
+        );
+
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[greeter_sandbox]
+groups =
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(
+            config.greeter_limits.sandbox.and_then(|s| s.groups),
+            Some(vec![])
+        );
+
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[greeter_sandbox]
+no_new_privs = maybe
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn handover() {
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[handover]
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(config.handover, Some(ConfigHandover { timeout: 10 }));
+
+        let config = parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[handover]
+timeout = 3
+",
+        )
+        .expect("config didn't parse");
+        assert_eq!(config.handover, Some(ConfigHandover { timeout: 3 }));
+    }
+
+    #[test]
+    fn invalid_initial_session() {
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[initial_session]
+"
+        )
+        .is_err());
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[initial_session]
+command = \"sway\"
+"
+        )
+        .is_err());
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1\n[default_session]\ncommand = \"agreety\"
+[initial_session]
+user = \"user\"
+"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn invalid_default_session() {
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1
+[default_session]
+"
+        )
+        .is_err());
+        assert!(parse_config(
+            "
+[terminal]\nvt = 1
+[default_session]
+user = \"john\"
+"
+        )
+        .is_err());
+    }
+}
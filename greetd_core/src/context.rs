@@ -0,0 +1,1272 @@
+use std::{
+    fs::{self, File},
+    path::Path,
+    process::Command,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use nix::{
+    sys::{utsname::uname, wait::WaitStatus},
+    unistd::{alarm, Pid, User},
+};
+use tokio::{
+    sync::{Mutex, RwLock},
+    time::sleep,
+};
+use tracing::{error, info, info_span, warn};
+
+use crate::{
+    accounts,
+    config::{
+        ConfigBanner, ConfigCommandPolicy, ConfigFile, ConfigHandover, ConfigPower,
+        LastSessionAction, SingleSession,
+    },
+    error::Error,
+    greeter_state::GreeterState,
+    handover::ReadySocket,
+    history::{History, Sessions},
+    inhibit,
+    logins::{self, Login},
+    metrics::Recorder,
+    policy,
+    reaper::{Exit, Registry, Tag},
+    scrambler::Scrambler,
+    session::{
+        interface::{Session, SessionChild, SessionSettings, SessionState},
+        worker::{AuthMessageType as SessAuthMessageType, SessionClass, TerminalMode},
+    },
+    terminal::Terminal,
+    upgrade::SavedSession,
+    user_session,
+};
+use greetd_ipc::{
+    desktop, issue, AuthMessageType, HostInfo, Metrics, PowerAction,
+    SessionClass as IpcSessionClass, SessionInfo, SessionRecord, SessionState as IpcSessionState,
+    SessionType, UserInfo, UserSession,
+};
+
+// When something that happened the given instant occurred, in seconds since
+// the Unix epoch.
+fn epoch_secs(time: Instant) -> u64 {
+    SystemTime::now()
+        .checked_sub(time.elapsed())
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+struct SessionChildSet {
+    child: SessionChild,
+    time: Instant,
+    is_greeter: bool,
+}
+
+struct SessionSet {
+    session: Session,
+    time: Instant,
+    // When the current authentication attempt began, which is when the
+    // session was created or last answered a question.
+    attempt: Instant,
+}
+
+/// A greeter that is kept running until the session that replaced it is ready.
+struct Retiring {
+    greeter: SessionChildSet,
+    ready: Option<ReadySocket>,
+    deadline: Instant,
+    terminated: Option<Instant>,
+}
+
+impl Retiring {
+    fn terminate(&mut self) {
+        if self.terminated.is_none() {
+            self.greeter.child.term();
+            self.terminated = Some(Instant::now());
+        }
+    }
+}
+
+// The session under configuration has its own lock, so that waiting for its
+// session worker, such as during a slow PAM conversation, does not hold up
+// other users of the context.
+type ConfiguringSession = Rc<Mutex<SessionSet>>;
+
+/// A step in the authentication of the session under configuration that the
+/// greeter must be told about.
+pub enum Question {
+    /// A message from PAM, which must be answered unless it is informational.
+    Message(AuthMessageType, String),
+    /// The password has expired, and the messages that follow are for
+    /// changing it. Needs no answer.
+    PasswordChange,
+}
+
+struct ContextInner {
+    current: Option<SessionChildSet>,
+    scheduled: Option<SessionSet>,
+    configuring: Option<ConfiguringSession>,
+    retiring: Option<Retiring>,
+}
+
+// A session worker that did not follow the protocol is in an unknown state, and
+// cannot be trusted to honor a cancellation. Kill it instead, leaving any other
+// sessions alone.
+fn check_protocol_error(session: &Session, err: &Error) {
+    if let Error::ProtocolError(_) = err {
+        warn!(parent: session.span(), "killing misbehaving session worker: {}", err);
+        session.kill();
+    }
+}
+
+/// Context keeps track of running sessions and start new ones.
+pub struct Context {
+    inner: RwLock<ContextInner>,
+    greeter_bin: String,
+    greeter_user: String,
+    greeter_service: String,
+    pam_service: String,
+    settings: SessionSettings,
+    runfile: String,
+    session_dirs: String,
+    power: ConfigPower,
+    banner: ConfigBanner,
+    command_policy: Option<ConfigCommandPolicy>,
+    handover: Option<ConfigHandover>,
+    single_session: SingleSession,
+    metrics: Recorder,
+    history: History,
+    greeter_state: GreeterState,
+    registry: Registry,
+}
+
+impl Context {
+    /// Create a context for the given configuration. The PAM services have
+    /// been checked to exist, and the terminal and greeter socket may have
+    /// been carried over from before a re-exec.
+    pub fn new(
+        file: &ConfigFile,
+        greeter_service: String,
+        pam_service: String,
+        term_mode: TerminalMode,
+        listener_path: String,
+    ) -> Context {
+        let general = &file.general;
+        let greeter_state = GreeterState::load(&general.state_dir, &general.seat);
+        Context {
+            inner: RwLock::new(ContextInner {
+                current: None,
+                scheduled: None,
+                configuring: None,
+                retiring: None,
+            }),
+            greeter_bin: file.default_session.command.clone(),
+            greeter_user: file.default_session.user.clone(),
+            greeter_service,
+            pam_service,
+            settings: SessionSettings {
+                seat: general.seat.clone(),
+                term_mode,
+                source_profile: general.source_profile,
+                listener_path,
+                home_unlock_command: general.home_unlock_command.clone(),
+                cleanup: file.cleanup.clone(),
+                x11: file.x11.clone(),
+                smartcard: file.smartcard.clone(),
+                greeter_limits: file.greeter_limits.clone(),
+                user_limits: file.user_limits.clone(),
+                fail_delay_ms: general.fail_delay_ms,
+            },
+            runfile: general.runfile.clone(),
+            session_dirs: general.session_dirs.clone(),
+            power: file.power.clone(),
+            banner: file.banner.clone(),
+            command_policy: file.command_policy.clone(),
+            handover: file.handover,
+            single_session: general.single_session,
+            metrics: Recorder::new(),
+            history: History::default(),
+            greeter_state,
+            registry: Registry::default(),
+        }
+    }
+
+    /// The registry that session workers are registered in.
+    pub fn registry(&self) -> Registry {
+        self.registry.clone()
+    }
+
+    /// Create a session, registering its worker with the reaper.
+    fn new_session(&self) -> Result<Session, Error> {
+        let session = Session::new_external()?;
+        self.registry
+            .register(session.pid(), Tag::Session(session.id()));
+        Ok(session)
+    }
+
+    /// Directly start an unauthenticated session, bypassing the normal
+    /// scheduling. This function does not take the inner lock, and can thus
+    /// be used while it is held.
+    async fn start_unauthenticated_session(
+        &self,
+        class: SessionClass,
+        user: &str,
+        service: &str,
+        cmd: Vec<String>,
+    ) -> Result<SessionChild, Error> {
+        let mut scheduled_session = self.new_session()?;
+        let res = async {
+            scheduled_session
+                .initiate(service, class, user, false, &self.settings, None)
+                .await?;
+            loop {
+                match scheduled_session.get_state().await {
+                    Ok(SessionState::Ready) => break,
+                    Ok(SessionState::Question(_, _) | SessionState::PasswordChange) => {
+                        scheduled_session.post_response(None).await?
+                    }
+                    Err(err @ Error::ProtocolError(_)) => return Err(err),
+                    Err(err) => return Err(format!("session start failed: {}", err).into()),
+                }
+            }
+
+            scheduled_session.send_args(cmd, vec![]).await?;
+            scheduled_session.start(false).await
+        }
+        .await;
+
+        match res {
+            Ok(child) => {
+                info!(parent: &child.span, user, "unauthenticated session started");
+                Ok(child)
+            }
+            Err(err) => {
+                check_protocol_error(&scheduled_session, &err);
+                Err(err)
+            }
+        }
+    }
+
+    /// Directly start a greeter session, bypassing the normal scheduling. This
+    /// function does not take the inner lock, and can thus be used while it is
+    /// held.
+    async fn start_greeter(&self) -> Result<SessionChild, Error> {
+        self.metrics.greeter_start();
+        self.start_unauthenticated_session(
+            SessionClass::Greeter,
+            &self.greeter_user,
+            &self.greeter_service,
+            vec![self.greeter_bin.to_string()],
+        )
+        .await
+    }
+
+    /// Directly start a greeter session, bypassing the normal scheduling.
+    pub async fn greet(&self) -> Result<(), Error> {
+        {
+            let inner = self.inner.read().await;
+            if inner.current.is_some() {
+                return Err("session already active".into());
+            }
+        }
+
+        let mut inner = self.inner.write().await;
+        inner.current = Some(SessionChildSet {
+            child: self.start_greeter().await?,
+            time: Instant::now(),
+            is_greeter: true,
+        });
+        Ok(())
+    }
+
+    /// Describe the running session for a re-exec of greetd. This fails while a
+    /// session is being set up, as its worker would be lost.
+    pub async fn suspend(&self) -> Result<Option<SavedSession>, Error> {
+        let inner = self.inner.read().await;
+        if inner.configuring.is_some() || inner.scheduled.is_some() || inner.retiring.is_some() {
+            return Err("a session is being started".into());
+        }
+        Ok(inner.current.as_ref().map(|s| SavedSession {
+            id: s.child.id,
+            task: s.child.task.as_raw(),
+            sub_task: s.child.sub_task.as_raw(),
+            is_greeter: s.is_greeter,
+            age: s.time.elapsed(),
+        }))
+    }
+
+    /// Take over a session that was running before greetd re-executed, along
+    /// with the metrics and history recorded until then. Must be called before
+    /// the reaper is started.
+    pub async fn resume(&self, session: Option<SavedSession>, metrics: Metrics, history: Sessions) {
+        self.metrics.restore(metrics);
+        self.history.restore(history);
+        let session = match session {
+            Some(session) => session,
+            None => return,
+        };
+        let task = Pid::from_raw(session.task);
+        self.registry.register(task, Tag::Session(session.id));
+        info!(id = session.id, "resuming session");
+        self.inner.write().await.current = Some(SessionChildSet {
+            child: SessionChild {
+                id: session.id,
+                task,
+                sub_task: Pid::from_raw(session.sub_task),
+                span: info_span!("session", id = session.id, worker = session.task),
+            },
+            time: Instant::now()
+                .checked_sub(session.age)
+                .unwrap_or_else(Instant::now),
+            is_greeter: session.is_greeter,
+        });
+    }
+
+    /// Check if this is the first time greetd starts since boot, or if it restarted for any reason
+    pub fn is_first_run(&self) -> bool {
+        !Path::new(&self.runfile).exists()
+    }
+
+    /// Create runfile used to check if greetd was already started since boot
+    pub fn create_runfile(&self) {
+        if let Err(err) = File::create(&self.runfile) {
+            warn!("could not create runfile: {}", err);
+        }
+    }
+
+    /// Directly start an initial session, bypassing the normal scheduling.
+    pub async fn start_user_session(&self, user: &str, cmd: Vec<String>) -> Result<(), Error> {
+        {
+            let inner = self.inner.read().await;
+            if inner.current.is_some() {
+                return Err("session already active".into());
+            }
+        }
+
+        let mut inner = self.inner.write().await;
+        let child = self
+            .start_unauthenticated_session(SessionClass::User, user, &self.pam_service, cmd)
+            .await?;
+        self.record_login(child.id, user);
+        inner.current = Some(SessionChildSet {
+            child,
+            time: Instant::now(),
+            is_greeter: false,
+        });
+        Ok(())
+    }
+
+    // The VT that greeters and sessions run on, if any.
+    fn vt(&self) -> Option<usize> {
+        match self.settings.term_mode {
+            TerminalMode::Terminal { vt, .. } => Some(vt),
+            TerminalMode::Stdin => None,
+        }
+    }
+
+    // Record the user session in the history, and for other instances of
+    // greetd to find.
+    fn record_login(&self, id: u64, user: &str) {
+        self.history.start(id, user, &self.settings.seat, self.vt());
+        let login = Login {
+            user: user.to_string(),
+            seat: self.settings.seat.clone(),
+            vt: self.vt(),
+        };
+        if let Err(e) = logins::record(&login) {
+            warn!("{}", e);
+        }
+    }
+
+    // Refuse to start a session for a user that already has one elsewhere, if
+    // so configured, switching to the existing session if possible.
+    fn check_single_session(&self, user: &str) -> Result<(), Error> {
+        let login = match self.single_session {
+            SingleSession::Off => return Ok(()),
+            _ => match logins::find(user) {
+                Some(login) => login,
+                None => return Ok(()),
+            },
+        };
+        info!(user, location = %login.location(), "user already logged in");
+        if let (SingleSession::Switch, TerminalMode::Terminal { path, .. }, Some(vt)) =
+            (self.single_session, &self.settings.term_mode, login.vt)
+        {
+            if login.seat == self.settings.seat {
+                if let Err(e) = Terminal::open(path).and_then(|term| term.vt_setactivate(vt)) {
+                    warn!("unable to switch to existing session: {}", e);
+                }
+            }
+        }
+        Err(Error::AlreadyLoggedIn(login.location()))
+    }
+
+    /// Create a new session for configuration, returning its ID.
+    pub async fn create_session(
+        &self,
+        username: String,
+        locale: Option<String>,
+    ) -> Result<u64, Error> {
+        if let Some(locale) = &locale {
+            // Locale names are passed to setlocale, which treats names with a
+            // slash as paths.
+            let valid = |c: char| c.is_ascii_alphanumeric() || "_.-@".contains(c);
+            if locale.is_empty() || !locale.chars().all(valid) {
+                return Err(format!("invalid locale '{}'", locale).into());
+            }
+        }
+        {
+            let inner = self.inner.read().await;
+            if inner.current.is_none() {
+                return Err("session not active".into());
+            }
+            if inner.configuring.is_some() {
+                return Err("a session is already being configured".into());
+            }
+            if inner.scheduled.is_some() {
+                return Err("a session is already scheduled".into());
+            }
+            if inner.retiring.is_some() {
+                return Err("a session is being handed over".into());
+            }
+        }
+
+        let mut session_set = SessionSet {
+            session: self.new_session()?,
+            time: Instant::now(),
+            attempt: Instant::now(),
+        };
+        let id = session_set.session.id();
+        info!(parent: session_set.session.span(), user = %username, "session created");
+        session_set
+            .session
+            .initiate(
+                &self.pam_service,
+                SessionClass::User,
+                &username,
+                true,
+                &self.settings,
+                locale.as_deref(),
+            )
+            .await?;
+
+        let session = self
+            .inner
+            .write()
+            .await
+            .configuring
+            .replace(Rc::new(Mutex::new(session_set)));
+
+        // If there was a session under configuration, cancel it.
+        if let Some(s) = session {
+            s.lock().await.session.cancel().await?;
+        }
+
+        Ok(id)
+    }
+
+    /// Cancel the session being configured.
+    pub async fn cancel(&self) -> Result<(), Error> {
+        let session = self.inner.write().await.configuring.take();
+        if let Some(s) = session {
+            let mut s = s.lock().await;
+            info!(parent: s.session.span(), "session cancelled");
+            s.session.cancel().await?;
+        }
+        Ok(())
+    }
+
+    /// Cancel the session being configured if it is the one with the given ID,
+    /// such as when the connection that created it has failed. Sessions
+    /// created by other connections are left alone.
+    pub async fn cancel_session(&self, id: u64) -> Result<(), Error> {
+        let session = match self.configuring().await {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        if session.lock().await.session.id() != id {
+            return Ok(());
+        }
+        {
+            let mut inner = self.inner.write().await;
+            match &inner.configuring {
+                Some(s) if Rc::ptr_eq(s, &session) => inner.configuring = None,
+                _ => return Ok(()),
+            }
+        }
+        let mut s = session.lock().await;
+        info!(parent: s.session.span(), "session cancelled");
+        s.session.cancel().await
+    }
+
+    /// Return the session under configuration. The context lock is not held
+    /// while the session is in use.
+    async fn configuring(&self) -> Option<ConfiguringSession> {
+        self.inner.read().await.configuring.clone()
+    }
+
+    /// Discard the session under configuration after its worker misbehaved,
+    /// unless it has already been replaced.
+    async fn discard_configuring(&self, session: &ConfiguringSession, err: &Error) {
+        let mut inner = self.inner.write().await;
+        if let Some(s) = &inner.configuring {
+            if Rc::ptr_eq(s, session) {
+                inner.configuring = None;
+            }
+        }
+        drop(inner);
+        check_protocol_error(&session.lock().await.session, err);
+    }
+
+    /// Retrieve a question from the session under configuration.
+    pub async fn get_question(&self) -> Result<Option<Question>, Error> {
+        let session = match self.configuring().await {
+            Some(s) => s,
+            None => return Err("no session under configuration".into()),
+        };
+        let state = session.lock().await.session.get_state().await;
+        self.question(&session, state).await
+    }
+
+    /// Move on from the last message of the session under configuration, and
+    /// retrieve the next one without waiting for the answer to a pending
+    /// question. Used for concurrent authentication, where the session may
+    /// send further messages or complete while a question is pending. This
+    /// is cancellation safe.
+    pub async fn next_question(&self) -> Result<Option<Question>, Error> {
+        let session = match self.configuring().await {
+            Some(s) => s,
+            None => return Err("no session under configuration".into()),
+        };
+        // The session may not send anything until the question is answered,
+        // so wait for it without holding the session lock.
+        let readable = session.lock().await.session.readable();
+        readable.await?;
+        let state = session.lock().await.session.next_state().await;
+        self.question(&session, state).await
+    }
+
+    // Make a failed authentication attempt take at least the configured
+    // time, so that the time it takes does not reveal why it failed. PAM
+    // usually waits for most of it already, see pam_fail_delay(3).
+    async fn fail_delay(&self, session: &ConfiguringSession) {
+        let attempt = session.lock().await.attempt;
+        let delay = Duration::from_millis(self.settings.fail_delay_ms.into());
+        if let Some(remaining) = delay.checked_sub(attempt.elapsed()) {
+            sleep(remaining).await;
+        }
+    }
+
+    async fn question(
+        &self,
+        session: &ConfiguringSession,
+        state: Result<SessionState, Error>,
+    ) -> Result<Option<Question>, Error> {
+        match state {
+            Err(
+                e @ Error::AuthError(_)
+                | e @ Error::AccountExpired(_)
+                | e @ Error::AccountLocked(_)
+                | e @ Error::PasswordExpired(_),
+            ) => {
+                self.metrics.auth_failure();
+                self.fail_delay(session).await;
+                Err(e)
+            }
+            Err(e @ Error::ProtocolError(_)) => {
+                self.discard_configuring(session, &e).await;
+                Err(e)
+            }
+            Err(e) => Err(e),
+            Ok(SessionState::Ready) => Ok(None),
+            Ok(SessionState::PasswordChange) => Ok(Some(Question::PasswordChange)),
+            Ok(SessionState::Question(style, string)) => Ok(Some(Question::Message(
+                match style {
+                    SessAuthMessageType::Visible => AuthMessageType::Visible,
+                    SessAuthMessageType::Secret => AuthMessageType::Secret,
+                    SessAuthMessageType::Info => AuthMessageType::Info,
+                    SessAuthMessageType::Error => AuthMessageType::Error,
+                },
+                string,
+            ))),
+        }
+    }
+
+    /// The user of the session under configuration as determined by PAM, if
+    /// it was created without one and has been authenticated.
+    pub async fn authenticated_user(&self) -> Option<String> {
+        let session = self.configuring().await?;
+        let s = session.lock().await;
+        s.session.user().map(str::to_string)
+    }
+
+    /// The default session chosen by the user of the session under
+    /// configuration, if any.
+    pub async fn default_session(&self) -> Result<Option<UserSession>, Error> {
+        let session = match self.configuring().await {
+            Some(s) => s,
+            None => return Err("no session under configuration".into()),
+        };
+        let username = session.lock().await.session.username().to_string();
+        if username.is_empty() {
+            return Err("the user is not yet known".into());
+        }
+        let user =
+            User::from_name(&username)?.ok_or_else(|| format!("user '{}' not found", username))?;
+        Ok(user_session::find(&user, &self.session_dirs))
+    }
+
+    /// The display metadata of the given user.
+    pub fn user_info(&self, username: &str) -> Result<UserInfo, Error> {
+        let user =
+            User::from_name(username)?.ok_or_else(|| format!("user '{}' not found", username))?;
+        Ok(accounts::user_info(&user))
+    }
+
+    /// The identity of the machine, with the issue file rendered for the VT
+    /// that the greeter runs on.
+    pub fn host_info(&self) -> Result<HostInfo, Error> {
+        let uts = uname()?;
+        let issue = match issue::get_issue(issue::DEFAULT_ISSUE_FILE, self.vt()) {
+            Ok(issue) => Some(issue),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("unable to read issue file: {}", e).into()),
+        };
+        Ok(HostInfo {
+            hostname: uts.nodename().to_string_lossy().to_string(),
+            os_release: issue::os_release(),
+            kernel: uts.release().to_string_lossy().to_string(),
+            issue,
+        })
+    }
+
+    /// Retrieve a value stored by the greeter.
+    pub fn greeter_state(&self, key: &str) -> Result<Option<String>, Error> {
+        self.greeter_state.get(key)
+    }
+
+    /// Store a value for the greeter, or remove it if the value is None.
+    pub fn set_greeter_state(&self, key: String, value: Option<String>) -> Result<(), Error> {
+        self.greeter_state.set(key, value)
+    }
+
+    /// Answer a question to the session under configuration.
+    pub async fn post_response(&self, answer: Option<String>) -> Result<(), Error> {
+        let session = match self.configuring().await {
+            Some(s) => s,
+            None => {
+                if let Some(mut answer) = answer {
+                    answer.scramble();
+                }
+                return Err("no session under configuration".into());
+            }
+        };
+        let res = {
+            let mut s = session.lock().await;
+            s.attempt = Instant::now();
+            match s.session.get_state().await {
+                Ok(SessionState::Ready) => Err("session has no pending questions".into()),
+                Ok(_) => s.session.post_response(answer).await,
+                Err(e) => Err(e),
+            }
+        };
+        if let Err(e @ Error::ProtocolError(_)) = &res {
+            self.discard_configuring(&session, e).await;
+        }
+        res
+    }
+
+    /// Answer the pending question of the session under configuration. Used for
+    /// concurrent authentication, where the session may have moved on without
+    /// the answer, in which case it is discarded.
+    pub async fn post_answer(&self, mut answer: Option<String>) -> Result<(), Error> {
+        let session = match self.configuring().await {
+            Some(s) => s,
+            None => {
+                if let Some(mut answer) = answer {
+                    answer.scramble();
+                }
+                return Err("no session under configuration".into());
+            }
+        };
+        let res = {
+            let mut s = session.lock().await;
+            if s.session.awaiting_answer() {
+                s.attempt = Instant::now();
+                s.session.post_response(answer).await
+            } else {
+                if let Some(answer) = &mut answer {
+                    answer.scramble();
+                }
+                Ok(())
+            }
+        };
+        if let Err(e @ Error::ProtocolError(_)) = &res {
+            self.discard_configuring(&session, e).await;
+        }
+        res
+    }
+
+    /// Schedule the session under configuration to be started with the
+    /// session desktop entry of the given ID, such as "sway". The command and
+    /// session type are taken from the desktop entry rather than the greeter.
+    pub async fn start_desktop(&self, id: &str, mut env: Vec<String>) -> Result<(), Error> {
+        let entry = desktop::find(&self.session_dirs, id)
+            .ok_or_else(|| format!("no session desktop entry named '{}'", id))?;
+        if !entry.installed() {
+            return Err(format!("session '{}' is not installed", id).into());
+        }
+        // The variables describing the session come last, so that they take
+        // precedence over those given by the greeter.
+        env.extend(entry.env());
+        self.schedule(vec![entry.command_line()], env).await
+    }
+
+    /// Schedule the session under configuration with the provided arguments.
+    /// If the command is that of a session desktop entry, or a session type is
+    /// given, the variables describing the session are added to those given by
+    /// the greeter.
+    pub async fn start(
+        &self,
+        mut cmd: Vec<String>,
+        mut env: Vec<String>,
+        session_type: Option<SessionType>,
+    ) -> Result<(), Error> {
+        if cmd.is_empty() {
+            match self.default_session().await? {
+                Some(UserSession {
+                    desktop_id: Some(id),
+                    ..
+                }) => return self.start_desktop(&id, env).await,
+                Some(session) => cmd = session.cmd,
+                None => return Err("no command given, and the user has no default session".into()),
+            }
+        }
+        let mut session_env = Vec::new();
+        if let Some(session_type) = session_type {
+            session_env.push(format!("XDG_SESSION_TYPE={}", session_type.as_str()));
+        }
+        let command = cmd.join(" ");
+        if let Some(entry) = desktop::entries(&self.session_dirs)
+            .into_iter()
+            .find(|e| e.command_line() == command)
+        {
+            session_env.extend(entry.env());
+        }
+        for var in session_env {
+            let name = var.split('=').next().unwrap_or_default();
+            if !env.iter().any(|e| e.split('=').next() == Some(name)) {
+                env.push(var);
+            }
+        }
+        self.schedule(cmd, env).await
+    }
+
+    // Schedule the session under configuration with the provided arguments.
+    async fn schedule(&self, cmd: Vec<String>, mut env: Vec<String>) -> Result<(), Error> {
+        // Checked before the session is taken, so that the greeter may try
+        // again with another command.
+        if let Some(policy) = &self.command_policy {
+            let res = policy::check(policy, &self.session_dirs, &cmd)
+                .and_then(|()| policy::check_env(&env));
+            if let Err(e) = res {
+                warn!("rejecting session command: {}", e);
+                return Err(e);
+            }
+        }
+
+        // Checked once authenticated, as it would otherwise reveal who is
+        // logged in, and before the session is taken, so that it can still be
+        // started once the existing session has ended.
+        if let Some(session) = self.configuring().await {
+            let mut s = session.lock().await;
+            if let Ok(SessionState::Ready) = s.session.get_state().await {
+                self.check_single_session(s.session.username())?;
+            }
+        }
+
+        let session = match self.inner.write().await.configuring.take() {
+            Some(s) => s,
+            None => return Err("no session active".into()),
+        };
+        let mut ready = None;
+
+        {
+            let mut s = session.lock().await;
+            match s.session.get_state().await {
+                Err(e) => {
+                    check_protocol_error(&s.session, &e);
+                    return Err(e);
+                }
+                Ok(SessionState::Question(..) | SessionState::PasswordChange) => {
+                    return Err("session is not ready".into())
+                }
+                Ok(SessionState::Ready) => (),
+            }
+
+            if self.handover.is_some() {
+                ready = match self.ready_socket(&s.session) {
+                    Ok(ready) => {
+                        env.push(ready.env());
+                        Some(ready)
+                    }
+                    Err(e) => {
+                        warn!(parent: s.session.span(), "session cannot report readiness: {}", e);
+                        None
+                    }
+                };
+            }
+
+            // Send our arguments to the session.
+            if let Err(e) = s.session.send_args(cmd, env).await {
+                check_protocol_error(&s.session, &e);
+                return Err(e);
+            }
+            info!(parent: s.session.span(), "session scheduled");
+        }
+
+        // The session is no longer under configuration, so it can only still
+        // be shared with a request that was already waiting for it.
+        let session = match Rc::try_unwrap(session) {
+            Ok(s) => s.into_inner(),
+            Err(_) => return Err("session is busy".into()),
+        };
+        if let Some(handover) = self.handover {
+            return self.hand_over(session, ready, handover).await;
+        }
+        let previous = self.inner.write().await.scheduled.replace(session);
+
+        // If there was a scheduled session, cancel it.
+        if let Some(mut p) = previous {
+            p.session.cancel().await?;
+        }
+
+        // We give the greeter 5 seconds to prove itself well-behaved before
+        // we lose patience and shoot it in the back repeatedly. This is all
+        // handled by our alarm handler.
+        alarm::set(5);
+
+        Ok(())
+    }
+
+    // Create the socket that a session reports its readiness on.
+    fn ready_socket(&self, session: &Session) -> Result<ReadySocket, Error> {
+        let user = User::from_name(session.username())?.ok_or("unable to get user info")?;
+        ReadySocket::create(session.id(), user.uid)
+    }
+
+    // Start a session while its greeter keeps running, to be terminated by the
+    // alarm handler once the session is ready.
+    async fn hand_over(
+        &self,
+        mut scheduled: SessionSet,
+        ready: Option<ReadySocket>,
+        handover: ConfigHandover,
+    ) -> Result<(), Error> {
+        // The greeter is still there to deal with a failure.
+        let s = match scheduled.session.start(true).await {
+            Ok(s) => s,
+            Err(e) => {
+                check_protocol_error(&scheduled.session, &e);
+                return Err(format!("session start failed: {}", e).into());
+            }
+        };
+        info!(parent: &s.span, "session started");
+        self.metrics.login();
+        self.record_login(s.id, scheduled.session.username());
+        let mut inner = self.inner.write().await;
+        let greeter = inner.current.replace(SessionChildSet {
+            child: s,
+            time: Instant::now(),
+            is_greeter: false,
+        });
+        if let Some(greeter) = greeter {
+            inner.retiring = Some(Retiring {
+                greeter,
+                ready,
+                deadline: Instant::now() + Duration::from_secs(handover.timeout),
+                terminated: None,
+            });
+            alarm::set(1);
+        }
+        Ok(())
+    }
+
+    /// Perform a power action by running the configured command. The command
+    /// is not waited for, and will be reaped like any other child.
+    pub async fn power(&self, action: PowerAction) -> Result<(), Error> {
+        self.run_power_command(action).map(|_| ())
+    }
+
+    // Run the power command for the given action, unless it is inhibited,
+    // returning its PID.
+    fn run_power_command(&self, action: PowerAction) -> Result<Pid, Error> {
+        let cmd = match action {
+            PowerAction::Poweroff => &self.power.poweroff_command,
+            PowerAction::Reboot => &self.power.reboot_command,
+        };
+        if cmd.is_empty() {
+            return Err(format!("no command configured for {:?}", action).into());
+        }
+        if self.power.respect_inhibitors {
+            match inhibit::shutdown_blockers() {
+                Ok(blockers) if !blockers.is_empty() => {
+                    let blockers = blockers
+                        .iter()
+                        .map(|i| format!("{} ({})", i.who, i.why))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    warn!(?action, %blockers, "power action inhibited");
+                    return Err(Error::Inhibited(blockers));
+                }
+                Ok(_) => (),
+                // Without logind, there is nothing to respect.
+                Err(e) => warn!("unable to check for inhibitors: {}", e),
+            }
+        }
+        info!(?action, "running power command");
+        let child = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(cmd)
+            .spawn()
+            .map_err(|e| format!("unable to run power command: {}", e))?;
+        Ok(Pid::from_raw(child.id() as i32))
+    }
+
+    /// Return the banner to display before authentication, if any. The banner
+    /// file is read on every call so that it can be changed without restarting
+    /// greetd, and takes precedence over the configured text.
+    pub fn banner(&self) -> Result<Option<String>, Error> {
+        let banner = if !self.banner.file.is_empty() {
+            fs::read_to_string(&self.banner.file)
+                .map_err(|e| format!("unable to read banner file: {}", e))?
+        } else {
+            self.banner.text.clone()
+        };
+        Ok(if banner.is_empty() {
+            None
+        } else {
+            Some(banner)
+        })
+    }
+
+    /// Whether greeters must retrieve the banner before creating a session.
+    pub fn banner_required(&self) -> bool {
+        self.banner.required
+    }
+
+    /// Return the login metrics.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.get()
+    }
+
+    /// Return the most recent user sessions that have ended.
+    pub fn session_history(&self) -> Vec<SessionRecord> {
+        self.history.get()
+    }
+
+    /// Return the sessions of this instance that are running or about to
+    /// start, followed by the user sessions of other instances of greetd.
+    /// Sessions under configuration are left out, as their user has not
+    /// authenticated yet.
+    pub async fn sessions(&self) -> Vec<SessionInfo> {
+        let info = |id: u64, username: &str, class, start, state| SessionInfo {
+            id: Some(id),
+            username: username.to_string(),
+            seat: self.settings.seat.clone(),
+            vt: self.vt(),
+            class,
+            start,
+            state,
+        };
+        let child_info = |s: &SessionChildSet, state| {
+            let start = epoch_secs(s.time);
+            if s.is_greeter {
+                return info(
+                    s.child.id,
+                    &self.greeter_user,
+                    IpcSessionClass::Greeter,
+                    start,
+                    state,
+                );
+            }
+            match self.history.running(s.child.id) {
+                Some(record) => info(
+                    s.child.id,
+                    &record.username,
+                    IpcSessionClass::User,
+                    record.start,
+                    state,
+                ),
+                None => info(s.child.id, "", IpcSessionClass::User, start, state),
+            }
+        };
+
+        let inner = self.inner.read().await;
+        let mut sessions = Vec::new();
+        if let Some(retiring) = &inner.retiring {
+            sessions.push(child_info(&retiring.greeter, IpcSessionState::Closing));
+        }
+        if let Some(current) = &inner.current {
+            sessions.push(child_info(current, IpcSessionState::Active));
+        }
+        if let Some(scheduled) = &inner.scheduled {
+            sessions.push(info(
+                scheduled.session.id(),
+                scheduled.session.username(),
+                IpcSessionClass::User,
+                epoch_secs(scheduled.time),
+                IpcSessionState::Starting,
+            ));
+        }
+        drop(inner);
+
+        sessions.extend(
+            logins::list()
+                .into_iter()
+                .map(|(login, start)| SessionInfo {
+                    id: None,
+                    username: login.user,
+                    seat: login.seat,
+                    vt: login.vt,
+                    class: IpcSessionClass::User,
+                    start,
+                    state: IpcSessionState::Active,
+                }),
+        );
+        sessions
+    }
+
+    /// Return the complete session history, to be carried across a re-exec.
+    pub fn save_history(&self) -> Sessions {
+        self.history.save()
+    }
+
+    /// Discard a scheduled session whose worker misbehaved while starting,
+    /// and start the greeter again in its place.
+    async fn discard_scheduled(&self, scheduled: SessionSet, err: Error) -> Result<(), Error> {
+        check_protocol_error(&scheduled.session, &err);
+        let mut inner = self.inner.write().await;
+        inner.current = Some(SessionChildSet {
+            child: self.start_greeter().await?,
+            time: Instant::now(),
+            is_greeter: true,
+        });
+        Ok(())
+    }
+
+    /// Notify the Context of an alarm.
+    pub async fn alarm(&self) -> Result<(), Error> {
+        // Keep trying to terminate the greeter until it gives up.
+        let mut inner = self.inner.write().await;
+
+        if let Some(r) = &mut inner.retiring {
+            match r.terminated {
+                None => {
+                    if r.ready.as_mut().is_some_and(ReadySocket::ready) {
+                        info!(parent: &r.greeter.child.span, "session ready, terminating greeter");
+                        r.terminate();
+                    } else if Instant::now() >= r.deadline {
+                        info!(parent: &r.greeter.child.span, "handover timed out, terminating greeter");
+                        r.terminate();
+                    }
+                }
+                Some(time) if time.elapsed() > Duration::from_secs(10) => {
+                    warn!(parent: &r.greeter.child.span, "greeter did not exit, killing it");
+                    r.greeter.child.kill();
+                }
+                Some(_) => (),
+            }
+            alarm::set(1);
+        }
+
+        if let Some(mut p) = inner.scheduled.take() {
+            if let Some(g) = inner.current.take() {
+                if p.time.elapsed() > Duration::from_secs(10) {
+                    // We're out of patience.
+                    warn!(parent: &g.child.span, "greeter did not exit, killing it");
+                    g.child.kill();
+                } else {
+                    // Let's try to give it a gentle nudge.
+                    g.child.term();
+                }
+                inner.current = Some(g);
+                inner.scheduled = Some(p);
+                alarm::set(1);
+                return Ok(());
+            }
+            drop(inner);
+            let s = match p.session.start(false).await {
+                Ok(s) => s,
+                Err(e @ Error::ProtocolError(_)) => return self.discard_scheduled(p, e).await,
+                Err(e) => return Err(format!("session start failed: {}", e).into()),
+            };
+            info!(parent: &s.span, "session started");
+            self.metrics.login();
+            self.record_login(s.id, p.session.username());
+            let mut inner = self.inner.write().await;
+            inner.current = Some(SessionChildSet {
+                child: s,
+                time: Instant::now(),
+                is_greeter: false,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Notify the Context that a child process exited.
+    pub async fn child_exited(&self, exit: Exit) -> Result<(), Error> {
+        let id = match exit.tag {
+            Tag::Session(id) => id,
+            Tag::PowerCommand => return self.power_command_exited(exit.status).await,
+        };
+        let mut inner = self.inner.write().await;
+        let (was_greeter, sesion_length) = match &inner.current {
+            Some(s) if s.child.id == id => {
+                info!(parent: &s.child.span, greeter = s.is_greeter, "session exited");
+                if !s.is_greeter {
+                    self.metrics.session_end(s.time.elapsed());
+                    logins::clear();
+                    if let Some(r) = self.history.end(id, exit.status) {
+                        info!(
+                            parent: &s.child.span,
+                            user = %r.username,
+                            seat = %r.seat,
+                            vt = ?r.vt,
+                            duration = s.time.elapsed().as_secs(),
+                            exit_code = ?r.exit_code,
+                            "session ended"
+                        );
+                    }
+                }
+                let res = (s.is_greeter, s.time.elapsed());
+                inner.current = None;
+                // The greeter being handed over from has nothing left to wait for.
+                if let Some(r) = &mut inner.retiring {
+                    r.terminate();
+                }
+                res
+            }
+            _ if matches!(&inner.retiring, Some(r) if r.greeter.child.id == id) => {
+                info!("greeter exited after handover");
+                inner.retiring = None;
+                return Ok(());
+            }
+            _ => {
+                // A worker of a session that has not been started yet is
+                // gone, so the session can no longer be used.
+                if matches!(&inner.scheduled, Some(s) if s.session.id() == id) {
+                    if let Some(s) = inner.scheduled.take() {
+                        warn!(parent: s.session.span(), status = ?exit.status, "session worker exited before session start");
+                    }
+                }
+                // A session that is busy is left for its user to fail on.
+                let configuring = match &inner.configuring {
+                    Some(s) => matches!(s.try_lock(), Ok(s) if s.session.id() == id),
+                    None => false,
+                };
+                if configuring {
+                    warn!(status = ?exit.status, session = id, "session worker exited during configuration");
+                    inner.configuring = None;
+                }
+                return Ok(());
+            }
+        };
+
+        match inner.scheduled.take() {
+            Some(mut scheduled) => {
+                // Our greeter finally bit the dust so we can
+                // start our scheduled session.
+                drop(inner);
+                let s = match scheduled.session.start(false).await {
+                    Ok(s) => s,
+                    Err(e @ Error::ProtocolError(_)) => {
+                        return self.discard_scheduled(scheduled, e).await;
+                    }
+                    Err(e) => return Err(format!("session start failed: {}", e).into()),
+                };
+                info!(parent: &s.span, "session started");
+                self.metrics.login();
+                self.record_login(s.id, scheduled.session.username());
+                let mut inner = self.inner.write().await;
+                inner.current = Some(SessionChildSet {
+                    child: s,
+                    time: Instant::now(),
+                    is_greeter: false,
+                });
+            }
+            None => {
+                if was_greeter {
+                    return Err("greeter exited without creating a session".into());
+                }
+                let action = match self.power.on_last_session_exit {
+                    LastSessionAction::Greeter => None,
+                    LastSessionAction::Poweroff => Some(PowerAction::Poweroff),
+                    LastSessionAction::Reboot => Some(PowerAction::Reboot),
+                };
+                if let Some(action) = action {
+                    // Nobody is going to use a greeter on this machine, so we
+                    // are done here. Should the power action fail, we still
+                    // need to offer a way in, which for a failing command is
+                    // done once it exits.
+                    match self.run_power_command(action) {
+                        Ok(pid) => {
+                            self.registry.register(pid, Tag::PowerCommand);
+                            return Ok(());
+                        }
+                        Err(e) => error!("unable to perform power action: {}", e),
+                    }
+                }
+                if sesion_length < Duration::from_secs(1) {
+                    sleep(Duration::from_secs(1)).await;
+                }
+                inner.current = Some(SessionChildSet {
+                    child: self.start_greeter().await?,
+                    time: Instant::now(),
+                    is_greeter: true,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    // The power command run after the last session exited is done. Unless it
+    // succeeded, the machine is not going down, so a greeter is started after
+    // all.
+    async fn power_command_exited(&self, status: WaitStatus) -> Result<(), Error> {
+        if let WaitStatus::Exited(_, 0) = status {
+            return Ok(());
+        }
+        error!(?status, "power command failed, starting greeter");
+        let mut inner = self.inner.write().await;
+        if inner.current.is_none() {
+            inner.current = Some(SessionChildSet {
+                child: self.start_greeter().await?,
+                time: Instant::now(),
+                is_greeter: true,
+            });
+        }
+        Ok(())
+    }
+
+    /// Notify the Context that we want to terminate. This should be called on
+    /// SIGTERM.
+    pub async fn terminate(&self) -> Result<(), Error> {
+        let mut inner = self.inner.write().await;
+        if let Some(sess) = inner.configuring.take() {
+            let _ = sess.lock().await.session.cancel().await;
+        }
+        if let Some(mut sess) = inner.scheduled.take() {
+            let _ = sess.session.cancel().await;
+        }
+        if let Some(sess) = inner.current.take() {
+            sess.child.term();
+        }
+        if let Some(mut r) = inner.retiring.take() {
+            r.terminate();
+        }
+        logins::clear();
+        Ok(())
+    }
+}
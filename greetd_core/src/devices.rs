@@ -0,0 +1,162 @@
+//! Waiting for the devices the greeter needs, such as the DRM card of a GPU
+//! that is slow to probe, so that it is not started before they exist.
+//!
+//! The configured paths are checked again whenever a uevent arrives, from the
+//! kernel as well as from udev, as udev creates device symlinks only once it
+//! has processed the kernel uevent.
+
+use std::{
+    fs,
+    os::unix::{
+        ffi::OsStrExt,
+        io::{AsRawFd, OwnedFd},
+    },
+    path::Path,
+    time::Duration,
+};
+
+use nix::{
+    errno::Errno,
+    sys::socket::{recv, MsgFlags, SockFlag},
+};
+use tokio::{
+    io::unix::{AsyncFd, AsyncFdReadyGuard},
+    time::{sleep, timeout_at, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{session::cleanup::matches, uevent};
+
+// How often to check for devices if uevents are unavailable.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Return whether a device exists. The last component of the path may contain
+// wildcards, in which case any matching device will do.
+fn present(pattern: &str) -> bool {
+    let path = Path::new(pattern);
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(dir), Some(name)) => (dir, name.as_bytes()),
+        _ => return false,
+    };
+    if !name.contains(&b'*') && !name.contains(&b'?') {
+        return path.exists();
+    }
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .any(|entry| matches(name, entry.file_name().as_bytes())),
+        Err(_) => false,
+    }
+}
+
+fn missing(patterns: &[String]) -> Vec<&str> {
+    patterns
+        .iter()
+        .filter(|p| !present(p))
+        .map(String::as_str)
+        .collect()
+}
+
+// Receive all pending uevents, as we only care that something happened.
+fn drain(guard: &mut AsyncFdReadyGuard<OwnedFd>, buf: &mut [u8]) -> Result<(), Errno> {
+    loop {
+        match recv(guard.get_inner().as_raw_fd(), buf, MsgFlags::empty()) {
+            Ok(_) | Err(Errno::EINTR) | Err(Errno::ENOBUFS) => continue,
+            Err(Errno::EAGAIN) => {
+                guard.clear_ready();
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Wait until all the given devices exist, or until the timeout expires, in
+/// which case the missing devices are logged.
+pub async fn wait(patterns: &[String], timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+
+    // Listen before checking, so that no device can appear unnoticed.
+    let mut sock = match uevent::open(
+        uevent::GROUP_KERNEL | uevent::GROUP_UDEV,
+        SockFlag::SOCK_NONBLOCK,
+    )
+    .and_then(|fd| Ok(AsyncFd::new(fd)?))
+    {
+        Ok(sock) => Some(sock),
+        Err(e) => {
+            warn!("unable to listen for uevents, polling for devices: {}", e);
+            None
+        }
+    };
+
+    let mut buf = [0; 8192];
+    let mut waiting = false;
+    loop {
+        let missing = missing(patterns);
+        if missing.is_empty() {
+            if waiting {
+                info!("devices present");
+            }
+            return;
+        }
+        if Instant::now() >= deadline {
+            warn!(devices = ?missing, "timed out waiting for devices");
+            return;
+        }
+        if !waiting {
+            info!(devices = ?missing, "waiting for devices");
+            waiting = true;
+        }
+
+        let res = match &sock {
+            Some(sock) => match timeout_at(deadline, sock.readable()).await {
+                Ok(Ok(mut guard)) => drain(&mut guard, &mut buf).map_err(|e| e.to_string()),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Ok(()),
+            },
+            None => {
+                let _ = timeout_at(deadline, sleep(POLL_INTERVAL)).await;
+                Ok(())
+            }
+        };
+        if let Err(e) = res {
+            warn!("unable to receive uevents, polling for devices: {}", e);
+            sock = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn present_devices() {
+        assert!(present("/dev/null"));
+        assert!(present("/dev/nul?"));
+        assert!(present("/dev/n*"));
+        assert!(!present("/dev/greetd-missing"));
+        assert!(!present("/dev/greetd-missing*"));
+        assert!(!present("/greetd-missing/*"));
+        assert!(!present("/"));
+    }
+
+    #[tokio::test]
+    async fn wait_present() {
+        let start = Instant::now();
+        wait(&["/dev/null".to_string()], Duration::from_secs(10)).await;
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn wait_timeout() {
+        // Waiting ends at the deadline even if no uevent ever arrives.
+        let start = Instant::now();
+        let patterns = ["/dev/null".to_string(), "/dev/greetd-missing".to_string()];
+        wait(&patterns, Duration::from_millis(300)).await;
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(300), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(5), "{:?}", elapsed);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{convert::From, fmt, io};
+
+use greetd_ipc::{ErrorType, Response};
+use serde::{Deserialize, Serialize};
+use thiserror::Error as ThisError;
+
+/// A failed PAM call.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PamFailure {
+    /// The PAM function that failed, such as "pam_authenticate".
+    pub function: String,
+    /// The PAM return code.
+    pub code: i32,
+    /// The name of the return code, such as "AUTH_ERR".
+    pub name: String,
+}
+
+impl fmt::Display for PamFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.function, self.name)
+    }
+}
+
+#[derive(Debug, ThisError, Clone, Deserialize, Serialize)]
+pub enum Error {
+    #[error("{0}")]
+    Error(String),
+
+    #[error("authentication error: {0}")]
+    AuthError(PamFailure),
+
+    #[error("account expired: {0}")]
+    AccountExpired(PamFailure),
+
+    #[error("account locked: {0}")]
+    AccountLocked(PamFailure),
+
+    #[error("password expired: {0}")]
+    PasswordExpired(PamFailure),
+
+    /// A PAM call failed for a reason other than the credentials or the state
+    /// of the account.
+    #[error("PAM error: {0}")]
+    Pam(PamFailure),
+
+    #[error("command not allowed: {0}")]
+    CommandNotAllowed(String),
+
+    #[error("already logged in on {0}")]
+    AlreadyLoggedIn(String),
+
+    #[error("inhibited by {0}")]
+    Inhibited(String),
+
+    #[error("protocol error: {0}")]
+    ProtocolError(String),
+
+    /// An I/O or other system call error, with the errno if there is one.
+    #[error("i/o error: {message}")]
+    Io { errno: Option<i32>, message: String },
+
+    #[error("configuration error: {0}")]
+    ConfigError(String),
+}
+
+impl Error {
+    /// An I/O error, described by what was being done when it occurred, such
+    /// as "unable to receive message".
+    pub fn io(what: &str, error: io::Error) -> Error {
+        Error::Io {
+            errno: error.raw_os_error(),
+            message: format!("{}: {}", what, error),
+        }
+    }
+
+    /// The type of the error as reported to greeters.
+    pub fn error_type(&self) -> ErrorType {
+        match self {
+            Error::AuthError(_) => ErrorType::AuthError,
+            Error::AccountExpired(_) => ErrorType::AccountExpired,
+            Error::AccountLocked(_) => ErrorType::AccountLocked,
+            Error::PasswordExpired(_) => ErrorType::PasswordExpired,
+            Error::Pam(_) => ErrorType::PamError,
+            Error::CommandNotAllowed(_) => ErrorType::CommandNotAllowed,
+            Error::AlreadyLoggedIn(_) => ErrorType::AlreadyLoggedIn,
+            Error::Inhibited(_) => ErrorType::Inhibited,
+            Error::ProtocolError(_) => ErrorType::ProtocolError,
+            Error::Io { .. } => ErrorType::IoError,
+            Error::Error(_) | Error::ConfigError(_) => ErrorType::Error,
+        }
+    }
+}
+
+impl From<Error> for Response {
+    fn from(error: Error) -> Self {
+        let error_type = error.error_type();
+        let description = match error {
+            Error::AuthError(failure)
+            | Error::AccountExpired(failure)
+            | Error::AccountLocked(failure)
+            | Error::PasswordExpired(failure) => failure.to_string(),
+            Error::AlreadyLoggedIn(location) => location,
+            Error::Inhibited(inhibitors) => inhibitors,
+            e => e.to_string(),
+        };
+        Response::Error {
+            error_type,
+            description,
+        }
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for Error {
+    fn from(error: Box<dyn std::error::Error>) -> Self {
+        Error::Error(format!("{}", error))
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io {
+            errno: error.raw_os_error(),
+            message: format!("{}", error),
+        }
+    }
+}
+
+impl From<crate::pam::PamError> for Error {
+    fn from(error: crate::pam::PamError) -> Self {
+        use crate::pam::PamError;
+        match error {
+            PamError::Error(s) => Error::Error(s),
+            PamError::Failed(f) | PamError::AbortError(f) => Error::Pam(f),
+            PamError::AuthError(f) => Error::AuthError(f),
+            PamError::AccountExpired(f) => Error::AccountExpired(f),
+            PamError::AccountLocked(f) => Error::AccountLocked(f),
+            PamError::PasswordExpired(f) => Error::PasswordExpired(f),
+        }
+    }
+}
+
+impl From<greetd_ipc::codec::Error> for Error {
+    fn from(error: greetd_ipc::codec::Error) -> Self {
+        match error {
+            greetd_ipc::codec::Error::Serialization(s) => Error::ProtocolError(s),
+            greetd_ipc::codec::Error::Io(message) => Error::Io {
+                errno: None,
+                message,
+            },
+            greetd_ipc::codec::Error::Eof => Error::Io {
+                errno: None,
+                message: "EOF".to_string(),
+            },
+        }
+    }
+}
+
+impl From<String> for Error {
+    fn from(error: String) -> Self {
+        Error::Error(error)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(error: &str) -> Self {
+        Error::Error(error.to_string())
+    }
+}
+
+impl From<serde_json::error::Error> for Error {
+    fn from(error: serde_json::error::Error) -> Self {
+        Error::ProtocolError(error.to_string())
+    }
+}
+
+impl From<nix::Error> for Error {
+    fn from(error: nix::Error) -> Self {
+        Error::Io {
+            errno: Some(error as i32),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<std::ffi::NulError> for Error {
+    fn from(error: std::ffi::NulError) -> Self {
+        Error::Error(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses() {
+        let failure = PamFailure {
+            function: "pam_authenticate".to_string(),
+            code: 7,
+            name: "AUTH_ERR".to_string(),
+        };
+        match Response::from(Error::AuthError(failure.clone())) {
+            Response::Error {
+                error_type: ErrorType::AuthError,
+                description,
+            } => assert_eq!(description, "pam_authenticate: AUTH_ERR"),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        match Response::from(Error::Pam(failure)) {
+            Response::Error {
+                error_type: ErrorType::PamError,
+                description,
+            } => assert_eq!(description, "PAM error: pam_authenticate: AUTH_ERR"),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        match Response::from(Error::from(nix::Error::EACCES)) {
+            Response::Error {
+                error_type: ErrorType::IoError,
+                ..
+            } => (),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+        match Response::from(Error::AlreadyLoggedIn("seat0, VT 2".to_string())) {
+            Response::Error {
+                error_type: ErrorType::AlreadyLoggedIn,
+                description,
+            } => assert_eq!(description, "seat0, VT 2"),
+            resp => panic!("unexpected response: {:?}", resp),
+        }
+    }
+}
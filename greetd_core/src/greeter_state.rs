@@ -0,0 +1,168 @@
+//! A small key/value store for the greeter, so that it can remember its state,
+//! such as the last selected user or session, across its own restarts without
+//! being able to write to disk itself.
+//!
+//! Each seat has its own store, as each seat has its own greeter. The store is
+//! kept in memory, and written to a file in the state directory of greetd on
+//! every change.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs::{self, DirBuilder, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+};
+
+use tracing::warn;
+
+use crate::error::Error;
+
+// Limits that keep a greeter from filling the disk.
+const MAX_KEYS: usize = 64;
+const MAX_KEY_LEN: usize = 64;
+const MAX_VALUE_LEN: usize = 4096;
+
+fn check_key(key: &str) -> Result<(), Error> {
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(format!("key must be 1 to {} characters long", MAX_KEY_LEN).into());
+    }
+    if !key
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+    {
+        return Err(format!("invalid key '{}'", key).into());
+    }
+    Ok(())
+}
+
+/// GreeterState holds the values stored by the greeter of a seat.
+pub struct GreeterState {
+    path: Option<PathBuf>,
+    values: RefCell<BTreeMap<String, String>>,
+}
+
+impl GreeterState {
+    /// Load the store of the given seat from the state directory. An empty
+    /// directory keeps the store in memory only. A missing or unreadable file
+    /// results in an empty store.
+    pub fn load(state_dir: &str, seat: &str) -> GreeterState {
+        if state_dir.is_empty() {
+            return GreeterState {
+                path: None,
+                values: Default::default(),
+            };
+        }
+        let path = Path::new(state_dir).join(format!("greeter-{}.json", seat));
+        let values = match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|e| {
+                warn!("ignoring invalid greeter state {}: {}", path.display(), e);
+                Default::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => Default::default(),
+            Err(e) => {
+                warn!("unable to read greeter state {}: {}", path.display(), e);
+                Default::default()
+            }
+        };
+        GreeterState {
+            path: Some(path),
+            values: RefCell::new(values),
+        }
+    }
+
+    /// Return the value stored under the given key, if any.
+    pub fn get(&self, key: &str) -> Result<Option<String>, Error> {
+        check_key(key)?;
+        Ok(self.values.borrow().get(key).cloned())
+    }
+
+    /// Store a value under the given key, or remove the key if the value is
+    /// None, and save the store.
+    pub fn set(&self, key: String, value: Option<String>) -> Result<(), Error> {
+        check_key(&key)?;
+        let mut values = self.values.borrow_mut();
+        match value {
+            Some(value) => {
+                if value.len() > MAX_VALUE_LEN {
+                    return Err(
+                        format!("value must be at most {} bytes long", MAX_VALUE_LEN).into(),
+                    );
+                }
+                if !values.contains_key(&key) && values.len() >= MAX_KEYS {
+                    return Err(format!("at most {} keys can be stored", MAX_KEYS).into());
+                }
+                values.insert(key, value);
+            }
+            None => {
+                if values.remove(&key).is_none() {
+                    return Ok(());
+                }
+            }
+        }
+        self.save(&values)
+    }
+
+    // Write the store to a temporary file and move it into place, so that the
+    // file is never left half written.
+    fn save(&self, values: &BTreeMap<String, String>) -> Result<(), Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let res = (|| {
+            if let Some(dir) = path.parent() {
+                DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+            }
+            let tmp = path.with_extension("json.tmp");
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&tmp)?;
+            file.write_all(&serde_json::to_vec(values)?)?;
+            file.sync_all()?;
+            fs::rename(&tmp, path)
+        })();
+        res.map_err(|e| format!("unable to save greeter state: {}", e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values() {
+        let state = GreeterState::load("", "seat0");
+        assert_eq!(state.get("user").unwrap(), None);
+        state
+            .set("user".to_string(), Some("alice".to_string()))
+            .unwrap();
+        state
+            .set("session.last".to_string(), Some("sway".to_string()))
+            .unwrap();
+        assert_eq!(state.get("user").unwrap(), Some("alice".to_string()));
+        state.set("user".to_string(), None).unwrap();
+        assert_eq!(state.get("user").unwrap(), None);
+        assert_eq!(state.get("session.last").unwrap(), Some("sway".to_string()));
+
+        assert!(state.get("").is_err());
+        assert!(state.get("../user").is_err());
+        assert!(state.set("x".repeat(65), Some(String::new())).is_err());
+        assert!(state
+            .set("layout".to_string(), Some("x".repeat(4097)))
+            .is_err());
+
+        for n in 1..MAX_KEYS {
+            state.set(format!("key{}", n), Some(String::new())).unwrap();
+        }
+        assert!(state.set("more".to_string(), Some(String::new())).is_err());
+        // Existing keys can still be changed.
+        state
+            .set("key1".to_string(), Some("value".to_string()))
+            .unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+//! Handing the terminal over from the greeter to a session without the console
+//! showing in between.
+//!
+//! The session is started while the greeter is still running, and reports that
+//! it is ready, typically once its compositor has taken over the outputs, using
+//! the sd_notify(3) protocol on the socket given to it in NOTIFY_SOCKET. The
+//! greeter is only terminated then.
+
+use std::{
+    fs::{self, Permissions},
+    os::unix::{fs::PermissionsExt, net::UnixDatagram},
+};
+
+use nix::unistd::{chown, getpid, Uid};
+
+use crate::error::Error;
+
+// Whether a notification contains the READY=1 assignment.
+fn is_ready(msg: &[u8]) -> bool {
+    msg.split(|&b| b == b'\n').any(|line| line == b"READY=1")
+}
+
+/// The socket that a session reports its readiness on.
+pub struct ReadySocket {
+    sock: UnixDatagram,
+    path: String,
+    ready: bool,
+}
+
+impl ReadySocket {
+    /// Create the readiness socket of a session, only accessible to its user.
+    pub fn create(session_id: u64, uid: Uid) -> Result<ReadySocket, Error> {
+        let path = format!(
+            "/run/greetd-{}-ready-{}.sock",
+            getpid().as_raw(),
+            session_id
+        );
+        let sock = ReadySocket::bind(path)?;
+        chown(sock.path.as_str(), Some(uid), None)
+            .map_err(|e| format!("unable to chown readiness socket at {}: {}", sock.path, e))?;
+        fs::set_permissions(&sock.path, Permissions::from_mode(0o600)).map_err(|e| {
+            format!(
+                "unable to set permissions of readiness socket at {}: {}",
+                sock.path, e
+            )
+        })?;
+        Ok(sock)
+    }
+
+    // Bind the socket at the given path, replacing any stale socket.
+    fn bind(path: String) -> Result<ReadySocket, Error> {
+        let _ = fs::remove_file(&path);
+        let sock = UnixDatagram::bind(&path)
+            .map_err(|e| format!("unable to open readiness socket: {}", e))?;
+        sock.set_nonblocking(true)?;
+        Ok(ReadySocket {
+            sock,
+            path,
+            ready: false,
+        })
+    }
+
+    /// The environment variable that tells the session about the socket.
+    pub fn env(&self) -> String {
+        format!("NOTIFY_SOCKET={}", self.path)
+    }
+
+    /// Check whether the session has reported that it is ready, without
+    /// waiting for it.
+    pub fn ready(&mut self) -> bool {
+        let mut buf = [0; 4096];
+        while !self.ready {
+            match self.sock.recv(&mut buf) {
+                Ok(len) => self.ready = is_ready(&buf[..len]),
+                Err(_) => break,
+            }
+        }
+        self.ready
+    }
+}
+
+impl Drop for ReadySocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready() {
+        assert!(is_ready(b"READY=1"));
+        assert!(is_ready(b"STATUS=Starting outputs\nREADY=1\n"));
+        assert!(!is_ready(b"STATUS=READY=1"));
+        assert!(!is_ready(b"READY=0"));
+        assert!(!is_ready(b""));
+    }
+
+    #[test]
+    fn socket() {
+        let path = std::env::temp_dir().join(format!("greetd-test-{}-ready.sock", getpid()));
+        let path = path.to_str().unwrap().to_string();
+        let mut sock = ReadySocket::bind(path.clone()).unwrap();
+        assert_eq!(sock.env(), format!("NOTIFY_SOCKET={}", path));
+        assert!(!sock.ready());
+
+        let session = UnixDatagram::unbound().unwrap();
+        session.send_to(b"STATUS=Starting outputs", &path).unwrap();
+        assert!(!sock.ready());
+
+        // Notifications queued after the ready one are not needed.
+        session.send_to(b"READY=1", &path).unwrap();
+        session.send_to(b"STOPPING=1", &path).unwrap();
+        assert!(sock.ready());
+        assert!(sock.ready());
+
+        drop(sock);
+        assert!(fs::metadata(&path).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Accounting of user sessions, keeping the most recent ones in memory for
+//! the admin socket.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use greetd_ipc::SessionRecord;
+use nix::sys::wait::WaitStatus;
+use serde::{Deserialize, Serialize};
+
+// The number of ended sessions to keep.
+const HISTORY_SIZE: usize = 100;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// The running session and the sessions that have ended, oldest first.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Sessions {
+    running: Option<(u64, SessionRecord)>,
+    ended: VecDeque<SessionRecord>,
+}
+
+/// History keeps track of user sessions.
+#[derive(Default)]
+pub struct History {
+    sessions: RefCell<Sessions>,
+}
+
+impl History {
+    /// Record that the session with the given ID was started.
+    pub fn start(&self, id: u64, username: &str, seat: &str, vt: Option<usize>) {
+        let record = SessionRecord {
+            username: username.to_string(),
+            seat: seat.to_string(),
+            vt,
+            start: now(),
+            end: None,
+            exit_code: None,
+        };
+        self.sessions.borrow_mut().running = Some((id, record));
+    }
+
+    /// Record that the session with the given ID ended, returning its record.
+    /// The status is that of the session worker, whose exit code is that of
+    /// the session.
+    pub fn end(&self, id: u64, status: WaitStatus) -> Option<SessionRecord> {
+        let mut sessions = self.sessions.borrow_mut();
+        let mut record = match sessions.running.take() {
+            Some((running, record)) if running == id => record,
+            other => {
+                sessions.running = other;
+                return None;
+            }
+        };
+        record.end = Some(now());
+        record.exit_code = match status {
+            WaitStatus::Exited(_, code) => Some(code),
+            _ => None,
+        };
+        if sessions.ended.len() == HISTORY_SIZE {
+            sessions.ended.pop_front();
+        }
+        sessions.ended.push_back(record.clone());
+        Some(record)
+    }
+
+    /// Return the record of the session with the given ID, if it is running.
+    pub fn running(&self, id: u64) -> Option<SessionRecord> {
+        match &self.sessions.borrow().running {
+            Some((running, record)) if *running == id => Some(record.clone()),
+            _ => None,
+        }
+    }
+
+    /// Return the sessions that have ended, oldest first.
+    pub fn get(&self) -> Vec<SessionRecord> {
+        self.sessions.borrow().ended.iter().cloned().collect()
+    }
+
+    /// Return the complete history, to be carried across a re-exec.
+    pub fn save(&self) -> Sessions {
+        self.sessions.borrow().clone()
+    }
+
+    /// Replace the history, such as with the one from before a re-exec.
+    pub fn restore(&self, sessions: Sessions) {
+        *self.sessions.borrow_mut() = sessions;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::Pid;
+
+    #[test]
+    fn records() {
+        let history = History::default();
+        history.start(1, "alice", "seat0", Some(1));
+        assert_eq!(
+            history.running(1).map(|r| r.username),
+            Some("alice".to_string())
+        );
+        assert_eq!(history.running(2), None);
+        assert_eq!(
+            history.end(2, WaitStatus::Exited(Pid::from_raw(1), 0)),
+            None
+        );
+        let record = history
+            .end(1, WaitStatus::Exited(Pid::from_raw(1), 3))
+            .expect("session not recorded");
+        assert_eq!(record.username, "alice");
+        assert_eq!(record.exit_code, Some(3));
+        assert!(record.end.is_some());
+        assert_eq!(history.running(1), None);
+        assert_eq!(history.get(), vec![record]);
+
+        for id in 0..HISTORY_SIZE as u64 + 1 {
+            history.start(id, &format!("user{}", id), "seat0", None);
+            history.end(id, WaitStatus::Exited(Pid::from_raw(1), 0));
+        }
+        let records = history.get();
+        assert_eq!(records.len(), HISTORY_SIZE);
+        assert_eq!(records[0].username, "user1");
+    }
+}
@@ -0,0 +1,86 @@
+//! logind inhibitors that block power actions, such as those taken by package
+//! managers during upgrades. The inhibitors are listed with busctl(1), which
+//! spares greetd a D-Bus implementation of its own.
+
+use std::process::Command;
+
+use serde_json::Value;
+
+use crate::error::Error;
+
+/// An inhibitor that blocks shutdown.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Inhibitor {
+    pub who: String,
+    pub why: String,
+}
+
+// Parse the reply to ListInhibitors, as printed by busctl --json=short. Each
+// inhibitor is an array of what, who, why, mode, UID and PID.
+fn parse(reply: &str) -> Result<Vec<Inhibitor>, Error> {
+    let reply: Value =
+        serde_json::from_str(reply).map_err(|e| format!("unable to parse inhibitors: {}", e))?;
+    let inhibitors = reply["data"][0]
+        .as_array()
+        .ok_or("unable to parse inhibitors: unexpected reply")?;
+    Ok(inhibitors
+        .iter()
+        .filter_map(|i| {
+            let what = i[0].as_str()?;
+            let mode = i[3].as_str()?;
+            if mode != "block" || !what.split(':').any(|w| w == "shutdown") {
+                return None;
+            }
+            Some(Inhibitor {
+                who: i[1].as_str()?.to_string(),
+                why: i[2].as_str()?.to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Return the inhibitors that currently block shutdown and reboot.
+pub fn shutdown_blockers() -> Result<Vec<Inhibitor>, Error> {
+    let output = Command::new("busctl")
+        .args([
+            "--json=short",
+            "call",
+            "org.freedesktop.login1",
+            "/org/freedesktop/login1",
+            "org.freedesktop.login1.Manager",
+            "ListInhibitors",
+        ])
+        .output()
+        .map_err(|e| format!("unable to run busctl: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "unable to list inhibitors: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    parse(&String::from_utf8_lossy(&output.stdout))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inhibitors() {
+        let reply = r#"{"type":"a(ssssuu)","data":[[["shutdown:sleep","PackageKit","Upgrading packages","block",0,812],["handle-power-key","sway","Handled by compositor","block",1000,1201],["shutdown","NetworkManager","Disconnecting","delay",0,633]]]}"#;
+        assert_eq!(
+            parse(reply).unwrap(),
+            vec![Inhibitor {
+                who: "PackageKit".to_string(),
+                why: "Upgrading packages".to_string(),
+            }]
+        );
+        assert_eq!(
+            parse(r#"{"type":"a(ssssuu)","data":[[]]}"#).unwrap(),
+            Vec::new()
+        );
+        assert!(parse("{}").is_err());
+        assert!(parse("").is_err());
+    }
+}
@@ -0,0 +1,82 @@
+//! Tracing of protocol messages, both between greeters and greetd and between
+//! greetd and its session workers. Messages are logged at debug level under
+//! the greetd_core::ipc target, which is enabled by --debug-ipc. Answers to
+//! authentication questions are redacted.
+
+use std::fmt;
+
+use tracing::{debug, Span};
+
+use crate::session::worker::{ParentToSessionChild, SessionChildToParent};
+use greetd_ipc::{Request, Response};
+
+/// Formats a message with any secrets redacted.
+pub struct Redacted<'a, T>(pub &'a T);
+
+impl fmt::Debug for Redacted<'_, Request> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Request::PostAuthMessageResponse { response: Some(_) } => {
+                f.write_str("PostAuthMessageResponse { response: Some(<redacted>) }")
+            }
+            req => fmt::Debug::fmt(req, f),
+        }
+    }
+}
+
+impl fmt::Debug for Redacted<'_, ParentToSessionChild<'_>> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            ParentToSessionChild::PamResponse { resp: Some(_) } => {
+                f.write_str("PamResponse { resp: Some(<redacted>) }")
+            }
+            msg => fmt::Debug::fmt(msg, f),
+        }
+    }
+}
+
+pub fn greeter_request(req: &Request) {
+    debug!("greeter -> greetd: {:?}", Redacted(req));
+}
+
+pub fn greeter_response(resp: &Response) {
+    debug!("greetd -> greeter: {:?}", resp);
+}
+
+pub fn worker_request(span: &Span, msg: &ParentToSessionChild) {
+    debug!(parent: span, "greetd -> worker: {:?}", Redacted(msg));
+}
+
+pub fn worker_response(span: &Span, msg: &SessionChildToParent) {
+    debug!(parent: span, "worker -> greetd: {:?}", msg);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redaction() {
+        let req = Request::PostAuthMessageResponse {
+            response: Some("hunter2".to_string()),
+        };
+        assert!(!format!("{:?}", Redacted(&req)).contains("hunter2"));
+        let req = Request::PostAuthMessageResponse { response: None };
+        assert_eq!(
+            format!("{:?}", Redacted(&req)),
+            "PostAuthMessageResponse { response: None }"
+        );
+
+        let msg = ParentToSessionChild::PamResponse {
+            resp: Some("hunter2".to_string()),
+        };
+        assert!(!format!("{:?}", Redacted(&msg)).contains("hunter2"));
+        assert_eq!(
+            format!(
+                "{:?}",
+                Redacted(&ParentToSessionChild::Start { handover: false })
+            ),
+            "Start { handover: false }"
+        );
+    }
+}
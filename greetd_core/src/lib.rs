@@ -0,0 +1,41 @@
+//! The login engine of greetd.
+//!
+//! This contains everything but the greetd executable itself: configuration,
+//! the session context and its state machine, the session workers and the
+//! protocol used to talk to them, and the server loop that ties them together.
+//! It allows custom daemons to embed the engine, and tests to drive it
+//! in-process.
+//!
+//! Session workers are started by re-executing the current executable with
+//! `--session-worker`, so an executable that embeds the engine must handle
+//! that argument like greetd does, by passing the configuration read by
+//! [`config::read_config`] to [`session::worker::main`] when
+//! `internal.session_worker` is set.
+
+mod accounts;
+pub mod config;
+pub mod context;
+mod devices;
+pub mod error;
+mod greeter_state;
+mod handover;
+mod history;
+mod inhibit;
+mod ipc;
+mod limiter;
+pub mod logging;
+mod logins;
+mod metrics;
+mod pam;
+mod plymouth;
+mod policy;
+mod readiness;
+mod reaper;
+mod scrambler;
+pub mod server;
+pub mod session;
+mod terminal;
+mod uevent;
+mod upgrade;
+mod user_session;
+mod vsock;
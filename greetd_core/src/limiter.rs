@@ -0,0 +1,202 @@
+//! Per-UID limits on connections to the greeter socket, so that a client stuck
+//! in a reconnect loop cannot exhaust file descriptors and starve the greeter.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Instant};
+
+struct UidState {
+    connections: usize,
+    tokens: f64,
+    last: Instant,
+    rejecting: bool,
+}
+
+/// A rejected connection attempt.
+#[derive(Debug, PartialEq)]
+pub struct Rejected {
+    pub reason: &'static str,
+    /// Whether this is the first rejection since a connection from this UID
+    /// was last accepted, used to avoid flooding the log.
+    pub first: bool,
+}
+
+/// Limits the number of concurrent connections and the rate of new
+/// connections per UID. New connections are rate limited with a token bucket
+/// that holds up to one second worth of connections. A limit of 0 disables it.
+pub struct Limiter {
+    max_connections: usize,
+    rate: u32,
+    uids: HashMap<u32, UidState>,
+}
+
+impl Limiter {
+    pub fn new(max_connections: usize, rate: u32) -> Limiter {
+        Limiter {
+            max_connections,
+            rate,
+            uids: HashMap::new(),
+        }
+    }
+
+    fn refill(&self, state: &mut UidState, now: Instant) {
+        let elapsed = now.saturating_duration_since(state.last).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+        state.last = now;
+    }
+
+    /// Account for a new connection from uid.
+    pub fn acquire(&mut self, uid: u32, now: Instant) -> Result<(), Rejected> {
+        // Forget idle UIDs whose buckets have refilled, as they are
+        // indistinguishable from UIDs that were never seen.
+        let rate = self.rate as f64;
+        let full = |state: &UidState| {
+            state.tokens + now.saturating_duration_since(state.last).as_secs_f64() * rate >= rate
+        };
+        self.uids
+            .retain(|_, state| state.connections > 0 || !full(state));
+
+        let mut state = self.uids.remove(&uid).unwrap_or(UidState {
+            connections: 0,
+            tokens: rate,
+            last: now,
+            rejecting: false,
+        });
+        self.refill(&mut state, now);
+
+        let res = if self.max_connections > 0 && state.connections >= self.max_connections {
+            Err("too many connections")
+        } else if self.rate > 0 && state.tokens < 1.0 {
+            Err("connection rate exceeded")
+        } else {
+            state.connections += 1;
+            if self.rate > 0 {
+                state.tokens -= 1.0;
+            }
+            Ok(())
+        };
+
+        let res = match res {
+            Ok(()) => {
+                state.rejecting = false;
+                Ok(())
+            }
+            Err(reason) => {
+                let first = !state.rejecting;
+                state.rejecting = true;
+                Err(Rejected { reason, first })
+            }
+        };
+        self.uids.insert(uid, state);
+        res
+    }
+
+    /// Account for a closed connection from uid.
+    pub fn release(&mut self, uid: u32) {
+        if let Some(state) = self.uids.get_mut(&uid) {
+            state.connections = state.connections.saturating_sub(1);
+        }
+    }
+}
+
+/// A connection accounted for by a Limiter, released when dropped.
+pub struct Permit {
+    limiter: Rc<RefCell<Limiter>>,
+    uid: u32,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.limiter.borrow_mut().release(self.uid);
+    }
+}
+
+/// Account for a new connection from uid, returning a permit that must be
+/// held for the lifetime of the connection.
+pub fn acquire(limiter: &Rc<RefCell<Limiter>>, uid: u32) -> Result<Permit, Rejected> {
+    limiter.borrow_mut().acquire(uid, Instant::now())?;
+    Ok(Permit {
+        limiter: limiter.clone(),
+        uid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    #[test]
+    fn max_connections() {
+        let now = Instant::now();
+        let mut limiter = Limiter::new(2, 0);
+        assert!(limiter.acquire(1000, now).is_ok());
+        assert!(limiter.acquire(1000, now).is_ok());
+        assert_eq!(
+            limiter.acquire(1000, now),
+            Err(Rejected {
+                reason: "too many connections",
+                first: true
+            })
+        );
+        assert_eq!(
+            limiter.acquire(1000, now),
+            Err(Rejected {
+                reason: "too many connections",
+                first: false
+            })
+        );
+
+        // Other UIDs are unaffected.
+        assert!(limiter.acquire(0, now).is_ok());
+
+        limiter.release(1000);
+        assert!(limiter.acquire(1000, now).is_ok());
+    }
+
+    #[test]
+    fn rate() {
+        let now = Instant::now();
+        let mut limiter = Limiter::new(0, 2);
+        assert!(limiter.acquire(1000, now).is_ok());
+        limiter.release(1000);
+        assert!(limiter.acquire(1000, now).is_ok());
+        limiter.release(1000);
+        assert_eq!(
+            limiter.acquire(1000, now),
+            Err(Rejected {
+                reason: "connection rate exceeded",
+                first: true
+            })
+        );
+
+        // Half a second refills one token.
+        let now = now + Duration::from_millis(500);
+        assert!(limiter.acquire(1000, now).is_ok());
+        limiter.release(1000);
+        assert!(limiter.acquire(1000, now).is_err());
+
+        // The bucket never holds more than one second worth of tokens.
+        let now = now + Duration::from_secs(60);
+        assert!(limiter.acquire(1000, now).is_ok());
+        assert!(limiter.acquire(1000, now).is_ok());
+        assert!(limiter.acquire(1000, now).is_err());
+    }
+
+    #[test]
+    fn disabled() {
+        let now = Instant::now();
+        let mut limiter = Limiter::new(0, 0);
+        for _ in 0..1000 {
+            assert!(limiter.acquire(1000, now).is_ok());
+        }
+    }
+
+    #[test]
+    fn permit() {
+        let limiter = Rc::new(RefCell::new(Limiter::new(1, 0)));
+        let permit = acquire(&limiter, 1000).expect("first connection rejected");
+        assert!(acquire(&limiter, 1000).is_err());
+        drop(permit);
+        assert!(acquire(&limiter, 1000).is_ok());
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::{Mutex, OnceLock};
+
+use tracing_subscriber::{fmt, prelude::*, reload, EnvFilter, Registry};
+
+use crate::error::Error;
+
+struct Filter {
+    handle: reload::Handle<EnvFilter, Registry>,
+    current: Mutex<String>,
+}
+
+static FILTER: OnceLock<Filter> = OnceLock::new();
+
+fn parse_filter(filter: &str) -> Result<EnvFilter, Error> {
+    EnvFilter::try_new(filter)
+        .map_err(|e| Error::ConfigError(format!("invalid log filter '{}': {}", filter, e)))
+}
+
+/// Set up logging with the given filter in env-filter syntax. If built with
+/// the journald feature, logs are sent to the systemd journal with their span
+/// fields as journal fields when it is available. Otherwise, logs are written
+/// to stderr.
+pub fn init(filter: &str) -> Result<(), Error> {
+    let (layer, handle) = reload::Layer::new(parse_filter(filter)?);
+    let _ = FILTER.set(Filter {
+        handle,
+        current: Mutex::new(filter.to_string()),
+    });
+
+    #[cfg(feature = "journald")]
+    {
+        if let Ok(journald) = tracing_journald::layer() {
+            tracing_subscriber::registry()
+                .with(layer)
+                .with(journald.with_syslog_identifier("greetd".to_string()))
+                .init();
+            return Ok(());
+        }
+    }
+
+    tracing_subscriber::registry()
+        .with(layer)
+        .with(fmt::layer().with_writer(std::io::stderr))
+        .init();
+    Ok(())
+}
+
+/// Replace the log filter.
+pub fn set_filter(filter: &str) -> Result<(), Error> {
+    let state = FILTER.get().ok_or("logging not initialized")?;
+    state
+        .handle
+        .reload(parse_filter(filter)?)
+        .map_err(|e| format!("unable to set log filter: {}", e))?;
+    *state.current.lock().unwrap() = filter.to_string();
+    Ok(())
+}
+
+/// The current log filter, to be passed on to session workers.
+pub fn filter() -> String {
+    match FILTER.get() {
+        Some(state) => state.current.lock().unwrap().clone(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,145 @@
+//! Tracking of the users logged in through greetd, across the instances of
+//! greetd serving different seats.
+//!
+//! Each instance records the user session it is running, if any, in a file
+//! named after its PID, which is written when the session starts. Records of
+//! instances that are no longer running are ignored.
+
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use nix::{
+    sys::signal::kill,
+    unistd::{getpid, Pid},
+};
+
+use crate::error::Error;
+
+const DIR: &str = "/run/greetd/logins";
+
+/// A user session run by an instance of greetd.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Login {
+    pub user: String,
+    pub seat: String,
+    pub vt: Option<usize>,
+}
+
+impl Login {
+    fn parse(contents: &str) -> Option<Login> {
+        let (mut user, mut seat, mut vt) = (None, None, None);
+        for line in contents.lines() {
+            match line.split_once('=') {
+                Some(("USER", v)) => user = Some(v.to_string()),
+                Some(("SEAT", v)) => seat = Some(v.to_string()),
+                Some(("VT", v)) => vt = Some(v.parse().ok()?),
+                _ => (),
+            }
+        }
+        Some(Login {
+            user: user?,
+            seat: seat?,
+            vt,
+        })
+    }
+
+    fn format(&self) -> String {
+        let mut s = format!("USER={}\nSEAT={}\n", self.user, self.seat);
+        if let Some(vt) = self.vt {
+            s.push_str(&format!("VT={}\n", vt));
+        }
+        s
+    }
+
+    /// Where the session is, such as "seat0, VT 2".
+    pub fn location(&self) -> String {
+        match self.vt {
+            Some(vt) => format!("{}, VT {}", self.seat, vt),
+            None => self.seat.clone(),
+        }
+    }
+}
+
+fn own_path() -> String {
+    format!("{}/{}", DIR, getpid().as_raw())
+}
+
+/// Record the user session of this instance, replacing any previous record.
+pub fn record(login: &Login) -> Result<(), Error> {
+    fs::create_dir_all(DIR).map_err(|e| format!("unable to create {}: {}", DIR, e))?;
+    let path = own_path();
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, login.format())
+        .and_then(|_| fs::rename(&tmp, &path))
+        .map_err(|e| format!("unable to record login: {}", e).into())
+}
+
+/// Remove the record of this instance.
+pub fn clear() {
+    let _ = fs::remove_file(own_path());
+}
+
+// The sessions run by other instances of greetd, along with when their
+// records were written.
+fn others() -> impl Iterator<Item = (Login, SystemTime)> {
+    let own = getpid().as_raw();
+    fs::read_dir(DIR)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(move |entry| {
+            let pid: i32 = entry.file_name().to_str()?.parse().ok()?;
+            if pid == own || kill(Pid::from_raw(pid), None).is_err() {
+                return None;
+            }
+            let path = Path::new(DIR).join(entry.file_name());
+            let modified = fs::metadata(&path).ok()?.modified().ok()?;
+            Some((Login::parse(&fs::read_to_string(path).ok()?)?, modified))
+        })
+}
+
+/// Find a session of the given user run by another instance of greetd.
+pub fn find(user: &str) -> Option<Login> {
+    others()
+        .map(|(login, _)| login)
+        .find(|login| login.user == user)
+}
+
+/// Return the sessions run by other instances of greetd, along with when they
+/// started in seconds since the Unix epoch.
+pub fn list() -> Vec<(Login, u64)> {
+    others()
+        .map(|(login, modified)| {
+            let start = modified
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default();
+            (login, start)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_format() {
+        let login = Login {
+            user: "alice".to_string(),
+            seat: "seat0".to_string(),
+            vt: Some(2),
+        };
+        assert_eq!(login.format(), "USER=alice\nSEAT=seat0\nVT=2\n");
+        assert_eq!(Login::parse(&login.format()), Some(login));
+        assert_eq!(
+            Login::parse("USER=bob\nSEAT=seat1\n").map(|l| l.location()),
+            Some("seat1".to_string())
+        );
+        assert_eq!(Login::parse("SEAT=seat0\nVT=2\n"), None);
+        assert_eq!(Login::parse("USER=bob\nSEAT=seat0\nVT=two\n"), None);
+    }
+}
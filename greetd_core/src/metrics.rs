@@ -0,0 +1,95 @@
+use std::{cell::RefCell, time::Duration};
+
+use greetd_ipc::{Bucket, Histogram, Metrics};
+
+// The upper bounds of the session duration buckets, in seconds: a minute, 5
+// and 15 minutes, an hour, 4 and 8 hours, a day and a week.
+const SESSION_DURATION_BUCKETS: [f64; 8] = [
+    60.0, 300.0, 900.0, 3600.0, 14400.0, 28800.0, 86400.0, 604800.0,
+];
+
+fn observe(histogram: &mut Histogram, value: f64) {
+    for bucket in histogram.buckets.iter_mut() {
+        if value <= bucket.le {
+            bucket.count += 1;
+        }
+    }
+    histogram.sum += value;
+    histogram.count += 1;
+}
+
+/// Recorder keeps track of the login metrics reported over the admin socket.
+pub struct Recorder {
+    metrics: RefCell<Metrics>,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        let buckets = SESSION_DURATION_BUCKETS
+            .iter()
+            .map(|&le| Bucket { le, count: 0 })
+            .collect();
+        Recorder {
+            metrics: RefCell::new(Metrics {
+                session_durations: Histogram {
+                    buckets,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Record that a user session was started after authentication.
+    pub fn login(&self) {
+        self.metrics.borrow_mut().logins += 1;
+    }
+
+    /// Record a failed authentication attempt.
+    pub fn auth_failure(&self) {
+        self.metrics.borrow_mut().auth_failures += 1;
+    }
+
+    /// Record that the greeter was started.
+    pub fn greeter_start(&self) {
+        self.metrics.borrow_mut().greeter_starts += 1;
+    }
+
+    /// Record that a user session ended after the given duration.
+    pub fn session_end(&self, duration: Duration) {
+        observe(
+            &mut self.metrics.borrow_mut().session_durations,
+            duration.as_secs_f64(),
+        );
+    }
+
+    /// Replace the metrics, such as with those counted before a re-exec.
+    pub fn restore(&self, metrics: Metrics) {
+        *self.metrics.borrow_mut() = metrics;
+    }
+
+    /// Return the current metrics.
+    pub fn get(&self) -> Metrics {
+        self.metrics.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_durations() {
+        let recorder = Recorder::new();
+        recorder.session_end(Duration::from_secs(30));
+        recorder.session_end(Duration::from_secs(60));
+        recorder.session_end(Duration::from_secs(7200));
+        recorder.session_end(Duration::from_secs(1_000_000));
+
+        let histogram = recorder.get().session_durations;
+        let counts: Vec<u64> = histogram.buckets.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![2, 2, 2, 2, 3, 3, 3, 3]);
+        assert_eq!(histogram.count, 4);
+        assert_eq!(histogram.sum, 1_007_290.0);
+    }
+}
@@ -0,0 +1,78 @@
+pub mod converse;
+mod env;
+mod ffi;
+pub mod session;
+
+use thiserror::Error as ThisError;
+
+use pam_sys::PamReturnCode;
+
+use crate::error::PamFailure;
+
+#[derive(Debug, ThisError)]
+pub enum PamError {
+    #[error("{0}")]
+    Error(String),
+    #[error("{0}")]
+    Failed(PamFailure),
+    #[error("abort error: {0}")]
+    AbortError(PamFailure),
+    #[error("{0}")]
+    AuthError(PamFailure),
+    #[error("{0}")]
+    AccountExpired(PamFailure),
+    #[error("{0}")]
+    AccountLocked(PamFailure),
+    #[error("{0}")]
+    PasswordExpired(PamFailure),
+}
+
+fn failure(function: &str, rc: PamReturnCode) -> PamFailure {
+    PamFailure {
+        function: function.to_string(),
+        code: rc as i32,
+        name: format!("{:?}", rc),
+    }
+}
+
+impl PamError {
+    pub fn from_rc(function: &str, rc: PamReturnCode) -> PamError {
+        match rc {
+            PamReturnCode::ABORT => PamError::AbortError(failure(function, rc)),
+            PamReturnCode::AUTH_ERR
+            | PamReturnCode::MAXTRIES
+            | PamReturnCode::CRED_EXPIRED
+            | PamReturnCode::ACCT_EXPIRED
+            | PamReturnCode::CRED_INSUFFICIENT
+            | PamReturnCode::USER_UNKNOWN
+            | PamReturnCode::PERM_DENIED
+            | PamReturnCode::SERVICE_ERR => PamError::AuthError(failure(function, rc)),
+            _ => PamError::Failed(failure(function, rc)),
+        }
+    }
+
+    /// Like from_rc, but for the result of pam_acct_mgmt, where some codes
+    /// describe the state of the account.
+    pub fn from_acct_mgmt_rc(rc: PamReturnCode) -> PamError {
+        let function = "pam_acct_mgmt";
+        match rc {
+            PamReturnCode::ACCT_EXPIRED => PamError::AccountExpired(failure(function, rc)),
+            // Returned by pam_faillock and similar modules for locked accounts.
+            PamReturnCode::PERM_DENIED | PamReturnCode::MAXTRIES => {
+                PamError::AccountLocked(failure(function, rc))
+            }
+            PamReturnCode::NEW_AUTHTOK_REQD => PamError::PasswordExpired(failure(function, rc)),
+            rc => PamError::from_rc(function, rc),
+        }
+    }
+
+    /// Like from_rc, but for the result of pam_chauthtok when changing an
+    /// expired password, which remains expired unless the change succeeds.
+    pub fn from_chauthtok_rc(rc: PamReturnCode) -> PamError {
+        let function = "pam_chauthtok";
+        match rc {
+            PamReturnCode::ABORT => PamError::from_rc(function, rc),
+            rc => PamError::PasswordExpired(failure(function, rc)),
+        }
+    }
+}
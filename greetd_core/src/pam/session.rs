@@ -25,14 +25,14 @@ pub struct PamSession<'a> {
 impl<'a> PamSession<'a> {
     pub fn start(
         service: &str,
-        user: &str,
+        user: Option<&str>,
         pam_conv: Pin<Box<dyn Converse + 'a>>,
     ) -> Result<PamSession<'a>, PamError> {
         let mut pch = Box::pin(PamConvHandlerWrapper { handler: pam_conv });
         let conv = make_conversation(&mut pch);
         let mut pam_handle: *mut PamHandle = ptr::null_mut();
 
-        match pam_sys::start(service, Some(user), &conv, &mut pam_handle) {
+        match pam_sys::start(service, user, &conv, &mut pam_handle) {
             PamReturnCode::SUCCESS => Ok(PamSession {
                 handle: unsafe { &mut *pam_handle },
                 lifetime_extender: pch,
@@ -54,7 +54,15 @@ impl<'a> PamSession<'a> {
         self.last_code = pam_sys::acct_mgmt(self.handle, flags);
         match self.last_code {
             PamReturnCode::SUCCESS => Ok(()),
-            rc => Err(PamError::from_rc("pam_acct_mgmt", rc)),
+            rc => Err(PamError::from_acct_mgmt_rc(rc)),
+        }
+    }
+
+    pub fn chauthtok(&mut self, flags: PamFlag) -> Result<(), PamError> {
+        self.last_code = pam_sys::chauthtok(self.handle, flags);
+        match self.last_code {
+            PamReturnCode::SUCCESS => Ok(()),
+            rc => Err(PamError::from_chauthtok_rc(rc)),
         }
     }
 
@@ -103,6 +111,18 @@ impl<'a> PamSession<'a> {
         }
     }
 
+    /// Request that failed authentication takes at least the given number of
+    /// microseconds. PAM waits for the longest delay requested by either the
+    /// application or its modules.
+    pub fn fail_delay(&mut self, usec: u32) -> Result<(), PamError> {
+        self.last_code =
+            PamReturnCode::from(unsafe { pam_sys::raw::pam_fail_delay(self.handle, usec) });
+        match self.last_code {
+            PamReturnCode::SUCCESS => Ok(()),
+            rc => Err(PamError::from_rc("pam_fail_delay", rc)),
+        }
+    }
+
     pub fn get_user(&mut self) -> Result<String, PamError> {
         let mut p: *const c_char = ptr::null_mut();
         self.last_code = pam_sys::get_user(self.handle, &mut p, ptr::null());
@@ -114,6 +134,20 @@ impl<'a> PamSession<'a> {
         }
     }
 
+    pub fn get_authtok(&mut self) -> Result<Option<String>, PamError> {
+        let mut p: *const c_void = ptr::null();
+        self.last_code = pam_sys::get_item(self.handle, PamItemType::AUTHTOK, &mut p);
+        match self.last_code {
+            PamReturnCode::SUCCESS if p.is_null() => Ok(None),
+            PamReturnCode::SUCCESS => Ok(Some(
+                (unsafe { CStr::from_ptr(p as *const c_char) })
+                    .to_string_lossy()
+                    .into_owned(),
+            )),
+            rc => Err(PamError::from_rc("pam_get_item", rc)),
+        }
+    }
+
     pub fn getenvlist(&mut self) -> Result<PamEnvList, PamError> {
         match get_pam_env(self.handle) {
             Some(v) => Ok(v),
@@ -0,0 +1,63 @@
+//! Handoff from a Plymouth boot splash to the greeter.
+//!
+//! Plymouth is deactivated before the greeter is started, so that it releases
+//! the display, but the splash is only removed once the greeter is ready. This
+//! avoids a flash of the text console in between.
+
+use std::process::{Command, Stdio};
+
+use tracing::{info, warn};
+
+fn plymouth(args: &[&str]) -> bool {
+    Command::new("plymouth")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// An ongoing handoff from Plymouth.
+pub struct Handoff {
+    pending: bool,
+}
+
+impl Handoff {
+    /// Deactivate Plymouth if it is running and the handoff is enabled.
+    pub fn start(enabled: bool) -> Handoff {
+        let pending = enabled && plymouth(&["--ping"]);
+        if pending {
+            info!("deactivating plymouth");
+            if !plymouth(&["deactivate"]) {
+                warn!("unable to deactivate plymouth");
+            }
+        }
+        Handoff { pending }
+    }
+
+    /// Whether Plymouth still needs to be told to quit.
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Tell Plymouth to quit. The splash is retained on screen if the greeter
+    /// or session took over, and removed otherwise so that any error messages
+    /// are visible.
+    pub fn finish(&mut self, retain_splash: bool) {
+        if !self.pending {
+            return;
+        }
+        self.pending = false;
+        info!(retain_splash, "quitting plymouth");
+        let ok = if retain_splash {
+            plymouth(&["quit", "--retain-splash"])
+        } else {
+            plymouth(&["quit"])
+        };
+        if !ok {
+            warn!("unable to quit plymouth");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Restrictions on the commands that greeters may start sessions with.
+//!
+//! The greeter runs as an unprivileged user, but chooses the command line of
+//! the user session it starts. If the greeter account is compromised, this
+//! policy limits the sessions it can start to the ones the administrator
+//! intended.
+
+use crate::{config::ConfigCommandPolicy, error::Error};
+use greetd_ipc::desktop;
+
+// Characters with a special meaning to the shell that session commands are run
+// by. Commands allowed by prefix must not contain any of them, as they could
+// otherwise run arbitrary commands before or instead of the allowed program.
+const SHELL_SPECIAL: &[char] = &[
+    ';', '&', '|', '$', '`', '<', '>', '(', ')', '{', '}', '[', ']', '*', '?', '~', '!', '#', '\'',
+    '"', '\\', '\n',
+];
+
+// Environment variables that can make an allowed command run other code, such
+// as by preloading libraries into it, making the shell run a script first or
+// finding its program elsewhere.
+const UNSAFE_ENV: &[&str] = &["BASH_ENV", "ENV", "IFS", "PATH", "SHELLOPTS"];
+const UNSAFE_ENV_PREFIXES: &[&str] = &["LD_", "BASH_FUNC_"];
+
+fn allowed_by_prefix(prefix: &str, command: &str) -> bool {
+    if command.contains(SHELL_SPECIAL) {
+        return false;
+    }
+    let program = match command.split_whitespace().next() {
+        Some(program) => program,
+        None => return false,
+    };
+    program.starts_with(prefix) && !program.split('/').any(|c| c == "..")
+}
+
+/// Check whether the policy allows a session to be started with the given
+/// command. The command is compared as the command line that will be run.
+/// Session desktop entries are looked up in the given directories.
+pub fn check(
+    policy: &ConfigCommandPolicy,
+    session_dirs: &str,
+    cmd: &[String],
+) -> Result<(), Error> {
+    let command = cmd.join(" ");
+    if policy.commands.contains(&command)
+        || policy
+            .prefixes
+            .iter()
+            .any(|p| allowed_by_prefix(p, &command))
+        || (policy.desktop_sessions
+            && desktop::entries(session_dirs)
+                .iter()
+                .any(|e| e.command_line() == command))
+    {
+        return Ok(());
+    }
+    Err(Error::CommandNotAllowed(command))
+}
+
+/// Check whether a session may be started with the given environment under a
+/// policy. Variables that could make an allowed command run other code are
+/// refused, as the policy would be worthless otherwise.
+pub fn check_env(env: &[String]) -> Result<(), Error> {
+    for var in env {
+        let name = var.split('=').next().unwrap_or_default();
+        if UNSAFE_ENV.contains(&name) || UNSAFE_ENV_PREFIXES.iter().any(|p| name.starts_with(p)) {
+            return Err(Error::CommandNotAllowed(format!(
+                "environment variable {}",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ConfigCommandPolicy {
+        ConfigCommandPolicy {
+            commands: vec!["sway --unsupported-gpu".to_string()],
+            prefixes: vec!["/usr/bin/".to_string()],
+            desktop_sessions: false,
+        }
+    }
+
+    fn allowed(cmd: &str) -> bool {
+        check(&policy(), "", &[cmd.to_string()]).is_ok()
+    }
+
+    #[test]
+    fn exact() {
+        assert!(allowed("sway --unsupported-gpu"));
+        assert!(check(
+            &policy(),
+            "",
+            &["sway".to_string(), "--unsupported-gpu".to_string()]
+        )
+        .is_ok());
+        assert!(!allowed("sway"));
+        assert!(!allowed("sway --unsupported-gpu; sh"));
+    }
+
+    #[test]
+    fn prefix() {
+        assert!(allowed("/usr/bin/sway"));
+        assert!(allowed("/usr/bin/sway --debug"));
+        assert!(!allowed("/usr/local/bin/sway"));
+        assert!(!allowed("/usr/bin/../../tmp/sh"));
+        assert!(!allowed("/usr/bin/sway; sh"));
+        assert!(!allowed("/usr/bin/sway $(sh)"));
+        assert!(!allowed(""));
+    }
+
+    #[test]
+    fn env() {
+        let env =
+            |vars: &[&str]| check_env(&vars.iter().map(|v| v.to_string()).collect::<Vec<_>>());
+        assert!(env(&[]).is_ok());
+        assert!(env(&["XDG_SESSION_TYPE=wayland", "LANG=C.UTF-8", "ENVIRONMENT=x"]).is_ok());
+        assert!(env(&["LD_PRELOAD=/tmp/x.so"]).is_err());
+        assert!(env(&["LANG=C", "LD_LIBRARY_PATH=/tmp"]).is_err());
+        assert!(env(&["BASH_ENV=/tmp/x"]).is_err());
+        assert!(env(&["ENV=/tmp/x"]).is_err());
+        assert!(env(&["PATH=/tmp:/usr/bin"]).is_err());
+        assert!(env(&["BASH_FUNC_sway%%=() { sh; }"]).is_err());
+    }
+}
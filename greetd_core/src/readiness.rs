@@ -0,0 +1,170 @@
+//! Telling service supervisors other than systemd, such as s6, runit or
+//! OpenRC, that greetd is up.
+//!
+//! Readiness is signalled with the s6 protocol: a newline is written to a file
+//! descriptor inherited from the supervisor, which is then closed. greetd is
+//! ready once its sockets accept connections and the first session has been
+//! started. A pidfile can be written for supervisors that track greetd by its
+//! PID instead, and is locked for as long as greetd runs, so that a stale
+//! pidfile is never mistaken for a running instance.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Read, Seek, Write},
+    os::unix::{
+        fs::OpenOptionsExt,
+        io::{AsRawFd, FromRawFd, RawFd},
+    },
+    path::PathBuf,
+};
+
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, flock, FcntlArg, FdFlag, FlockArg},
+    unistd::getpid,
+};
+use tracing::warn;
+
+use crate::error::Error;
+
+/// A pidfile, locked and removed when dropped.
+pub struct Pidfile {
+    path: PathBuf,
+    _file: File,
+}
+
+impl Pidfile {
+    /// Create or take over the pidfile at the given path and write our PID to
+    /// it. Fails if the pidfile is locked by another running instance.
+    pub fn create(path: &str) -> Result<Pidfile, Error> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .mode(0o644)
+            .custom_flags(libc::O_CLOEXEC)
+            .open(path)
+            .map_err(|e| Error::io(&format!("unable to open pidfile {}", path), e))?;
+        match flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => (),
+            Err(Errno::EWOULDBLOCK) => {
+                let mut pid = String::new();
+                let _ = file.read_to_string(&mut pid);
+                return Err(format!("greetd is already running with PID {}", pid.trim()).into());
+            }
+            Err(e) => return Err(format!("unable to lock pidfile {}: {}", path, e).into()),
+        }
+        let res = (|| {
+            file.set_len(0)?;
+            file.rewind()?;
+            writeln!(file, "{}", getpid())?;
+            file.sync_all()
+        })();
+        res.map_err(|e| Error::io(&format!("unable to write pidfile {}", path), e))?;
+        Ok(Pidfile {
+            path: path.into(),
+            _file: file,
+        })
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(e) => warn!("unable to remove pidfile {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+/// The file descriptor to signal readiness on.
+pub struct Notifier(File);
+
+impl Notifier {
+    /// Take the given file descriptor from the supervisor. It is closed on
+    /// exec, so that sessions do not inherit it.
+    pub fn new(fd: RawFd) -> Result<Notifier, Error> {
+        let mut flags = FdFlag::from_bits_retain(
+            fcntl(fd, FcntlArg::F_GETFD)
+                .map_err(|e| format!("invalid readiness fd {}: {}", fd, e))?,
+        );
+        flags.insert(FdFlag::FD_CLOEXEC);
+        fcntl(fd, FcntlArg::F_SETFD(flags))?;
+        Ok(Notifier(unsafe { File::from_raw_fd(fd) }))
+    }
+
+    /// Signal readiness, closing the file descriptor.
+    pub fn notify(mut self) -> Result<(), Error> {
+        self.0
+            .write_all(b"\n")
+            .map_err(|e| Error::io("unable to notify readiness", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::{fcntl::OFlag, unistd::pipe2};
+    use std::path::Path;
+
+    #[test]
+    fn pidfile() {
+        let path = std::env::temp_dir().join(format!("greetd-test-{}.pid", std::process::id()));
+        let path = path.to_str().unwrap();
+        let pidfile = Pidfile::create(path).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), format!("{}\n", getpid()));
+        assert!(Pidfile::create(path).is_err());
+        drop(pidfile);
+        assert!(!Path::new(path).exists());
+
+        // A stale pidfile is taken over.
+        fs::write(path, "1234567\n").unwrap();
+        let pidfile = Pidfile::create(path).unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), format!("{}\n", getpid()));
+        drop(pidfile);
+    }
+
+    #[test]
+    fn pidfile_locked() {
+        let path =
+            std::env::temp_dir().join(format!("greetd-test-{}-locked.pid", std::process::id()));
+        let path = path.to_str().unwrap();
+        let pidfile = Pidfile::create(path).unwrap();
+
+        // The lock is held by the open file, so a second attempt fails even
+        // from the same process, naming the holder and leaving its PID alone.
+        match Pidfile::create(path) {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                format!("greetd is already running with PID {}", getpid())
+            ),
+            Ok(_) => panic!("locked pidfile was taken over"),
+        }
+        assert_eq!(fs::read_to_string(path).unwrap(), format!("{}\n", getpid()));
+        assert!(Path::new(path).exists());
+
+        drop(pidfile);
+        let pidfile = Pidfile::create(path).unwrap();
+        drop(pidfile);
+    }
+
+    #[test]
+    fn notify() {
+        // Close on exec from the start, so that children spawned by other
+        // tests do not keep the pipe open.
+        let (rd, wr) = pipe2(OFlag::O_CLOEXEC).unwrap();
+        let mut rd = unsafe { File::from_raw_fd(rd) };
+        Notifier::new(wr).unwrap().notify().unwrap();
+        // The descriptor is closed once notified, so the supervisor sees the
+        // newline followed by the end of the file.
+        let mut msg = String::new();
+        rd.read_to_string(&mut msg).unwrap();
+        assert_eq!(msg, "\n");
+    }
+
+    #[test]
+    fn notify_invalid() {
+        assert!(Notifier::new(-1).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Reaping of child processes.
+//!
+//! Session workers are registered with their session ID as soon as they are
+//! forked, before greetd yields to any other task. A reaper task reaps all
+//! exited children on SIGCHLD and reports them tagged with the session they
+//! belonged to, so that an exit can never be attributed to the wrong session
+//! or be lost because it happened before the session was fully started. Other
+//! children whose exit matters are registered the same way.
+
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use nix::{
+    sys::wait::{waitpid, WaitPidFlag, WaitStatus},
+    unistd::Pid,
+};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    task,
+};
+use tracing::{debug, error};
+
+use crate::error::Error;
+
+/// What a registered child process is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tag {
+    /// The worker of the session with the given ID.
+    Session(u64),
+    /// The power command run after the last session exited.
+    PowerCommand,
+}
+
+/// An exited child process.
+#[derive(Debug)]
+pub struct Exit {
+    pub tag: Tag,
+    pub status: WaitStatus,
+}
+
+/// Registry of the child processes whose exit is reported.
+#[derive(Clone, Default)]
+pub struct Registry {
+    pids: Rc<RefCell<HashMap<Pid, Tag>>>,
+}
+
+impl Registry {
+    /// Register a child process with what it is. This must be done before
+    /// yielding to other tasks after forking.
+    pub fn register(&self, pid: Pid, tag: Tag) {
+        self.pids.borrow_mut().insert(pid, tag);
+    }
+
+    fn take(&self, pid: Pid) -> Option<Tag> {
+        self.pids.borrow_mut().remove(&pid)
+    }
+}
+
+fn reap(registry: &Registry, tx: &UnboundedSender<Exit>) {
+    loop {
+        match waitpid(None, Some(WaitPidFlag::WNOHANG)) {
+            // No more exits.
+            Ok(WaitStatus::StillAlive) => break,
+
+            Ok(status @ WaitStatus::Exited(pid, ..))
+            | Ok(status @ WaitStatus::Signaled(pid, ..)) => match registry.take(pid) {
+                Some(tag) => {
+                    let _ = tx.send(Exit { tag, status });
+                }
+                None => debug!(pid = pid.as_raw(), "reaped unregistered child"),
+            },
+
+            // Useless status.
+            Ok(_) => continue,
+
+            // Interrupted.
+            Err(nix::errno::Errno::EINTR) => continue,
+
+            // We do not have any children right now.
+            Err(nix::errno::Errno::ECHILD) => break,
+
+            Err(e) => {
+                error!("waitpid returned an unexpected error: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Start the reaper task, returning a stream of exited registered children.
+pub fn spawn(registry: Registry) -> Result<UnboundedReceiver<Exit>, Error> {
+    let mut sigchld =
+        signal(SignalKind::child()).map_err(|e| format!("unable to listen for SIGCHLD: {}", e))?;
+    let (tx, rx) = unbounded_channel();
+    task::spawn_local(async move {
+        // Children may have exited before we started listening for SIGCHLD.
+        reap(&registry, &tx);
+        while sigchld.recv().await.is_some() {
+            reap(&registry, &tx);
+        }
+    });
+    Ok(rx)
+}
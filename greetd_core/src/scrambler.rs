@@ -17,6 +17,14 @@ impl<T: Default> Scrambler for Vec<T> {
     }
 }
 
+impl<const N: usize> Scrambler for [u8; N] {
+    fn scramble(&mut self) {
+        for b in self.iter_mut() {
+            *b = 0
+        }
+    }
+}
+
 impl Scrambler for String {
     fn scramble(&mut self) {
         let v = unsafe { self.as_mut_vec() };
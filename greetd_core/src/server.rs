@@ -0,0 +1,789 @@
+use std::{
+    cell::RefCell,
+    fs,
+    future::Future,
+    os::unix::{
+        fs::PermissionsExt,
+        io::{AsRawFd, FromRawFd, RawFd},
+    },
+    path::Path,
+    rc::Rc,
+    time::Duration,
+};
+
+use nix::{
+    sys::signal::{raise, Signal},
+    unistd::{chown, getpid, Gid, Uid},
+};
+use tokio::{
+    net::{UnixListener, UnixStream},
+    signal::unix::{signal, SignalKind},
+    task,
+    time::{sleep, timeout},
+};
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    config::{self, Config, ConfigIpc, VtSelection},
+    context::{Context, Question},
+    devices,
+    error::Error,
+    ipc,
+    limiter::{self, Limiter},
+    logging,
+    plymouth::Handoff,
+    readiness::{Notifier, Pidfile},
+    reaper,
+    session::{interface, limits, spawn, worker::TerminalMode},
+    terminal::{self, Terminal},
+    upgrade::{self, State},
+    vsock::VsockListener,
+};
+use greetd_ipc::{
+    codec::{Error as CodecError, TokioCodec},
+    Request, Response,
+};
+
+// How long to wait for the greeter to connect before telling Plymouth to quit
+// regardless.
+const PLYMOUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn reset_vt(term_mode: &TerminalMode) -> Result<(), Error> {
+    match term_mode {
+        TerminalMode::Terminal { path, vt, .. } => {
+            let term = Terminal::open(path)?;
+            term.kd_setmode(terminal::KdMode::Text)?;
+            term.vt_setactivate(*vt)?;
+        }
+        TerminalMode::Stdin => (),
+    }
+    Ok(())
+}
+
+fn wait_vt(term_mode: &TerminalMode) -> Result<(), Error> {
+    match term_mode {
+        TerminalMode::Terminal { path, vt, .. } => {
+            let term = Terminal::open(path)?;
+            term.vt_waitactive(*vt)?;
+        }
+        TerminalMode::Stdin => (),
+    }
+    Ok(())
+}
+
+fn wrap_result<T>(res: Result<T, Error>) -> Response {
+    match res {
+        Ok(_) => Response::Success,
+        Err(e) => e.into(),
+    }
+}
+
+async fn question_response(ctx: &Context, res: Result<Option<Question>, Error>) -> Response {
+    match res {
+        Ok(Some(Question::Message(auth_message_type, auth_message))) => Response::AuthMessage {
+            auth_message_type,
+            auth_message,
+        },
+        Ok(Some(Question::PasswordChange)) => Response::PasswordChangeRequired,
+        // Sessions created without a username learn who was authenticated.
+        Ok(None) => match ctx.authenticated_user().await {
+            Some(username) => Response::Authenticated { username },
+            None => Response::Success,
+        },
+        res => wrap_result(res),
+    }
+}
+
+// Whether authentication continues after the response, so that a greeter
+// using concurrent authentication gets the responses that follow.
+fn follows_auth(resp: &Response) -> bool {
+    matches!(
+        resp,
+        Response::AuthMessage { .. } | Response::PasswordChangeRequired
+    )
+}
+
+async fn client_get_question(ctx: &Context) -> Response {
+    question_response(ctx, ctx.get_question().await).await
+}
+
+async fn client_next_question(ctx: &Context) -> Response {
+    question_response(ctx, ctx.next_question().await).await
+}
+
+// Run an operation on a client connection, failing if it does not complete
+// within the given number of seconds. A timeout of 0 disables the limit.
+async fn with_timeout<F: Future>(secs: u64, what: &str, fut: F) -> Result<F::Output, Error> {
+    if secs == 0 {
+        return Ok(fut.await);
+    }
+    timeout(Duration::from_secs(secs), fut)
+        .await
+        .map_err(|_| format!("timed out {}", what).into())
+}
+
+// Where a connection came from, which decides what it may request.
+#[derive(Clone, Copy, PartialEq)]
+enum Origin {
+    Greeter,
+    Admin,
+    // A greeter in a virtual machine, which is not trusted with the state of
+    // the host, and may only control its power if allowed to.
+    Vsock { allow_power: bool },
+}
+
+// Handle a connection on the greeter, admin or vsock socket. Requests that
+// manage sessions are only accepted from greeters, while admin requests are
+// only accepted on the admin socket. Greeters on vsock may not list the
+// sessions of the host or change its greeter state, and may only request
+// power actions if allowed to.
+//
+// If the banner is required, a greeter must retrieve it on the same
+// connection before it may create a session.
+//
+// The idle timeout limits how long the client may wait before sending its
+// next request, while the I/O timeout limits how long a request or response,
+// once started, may take to transfer.
+//
+// With concurrent authentication, the session under configuration is
+// followed while the greeter has yet to answer, and its messages are sent as
+// they arrive. Answers then get no response of their own.
+//
+// The ID of the last session created on the connection is stored in created.
+async fn client_handler(
+    ctx: &Context,
+    mut s: UnixStream,
+    origin: Origin,
+    cfg: ConfigIpc,
+    created: &mut Option<u64>,
+) -> Result<(), Error> {
+    let admin = origin == Origin::Admin;
+    let vsock = matches!(origin, Origin::Vsock { .. });
+    let mut banner_shown = false;
+    let mut concurrent_auth = false;
+    let mut upgrade = false;
+    loop {
+        let event = if concurrent_auth {
+            tokio::select! {
+                res = with_timeout(cfg.idle_timeout, "waiting for request", s.readable()) => {
+                    res??;
+                    None
+                }
+                resp = client_next_question(ctx) => Some(resp),
+            }
+        } else {
+            with_timeout(cfg.idle_timeout, "waiting for request", s.readable()).await??;
+            None
+        };
+
+        let req = match event {
+            Some(resp) => {
+                concurrent_auth = follows_auth(&resp);
+                ipc::greeter_response(&resp);
+                with_timeout(cfg.timeout, "writing response", resp.write_to(&mut s)).await??;
+                continue;
+            }
+            None => match with_timeout(cfg.timeout, "reading request", Request::read_from(&mut s))
+                .await?
+            {
+                Ok(req) => req,
+                Err(CodecError::Eof) => return Ok(()),
+                Err(e) => return Err(e.into()),
+            },
+        };
+        ipc::greeter_request(&req);
+
+        let resp = match req {
+            Request::GetMetrics if admin => Response::Metrics {
+                metrics: ctx.metrics(),
+            },
+            Request::GetSessionHistory if admin => Response::SessionHistory {
+                sessions: ctx.session_history(),
+            },
+            Request::Upgrade if admin => match ctx.suspend().await {
+                Ok(_) => {
+                    upgrade = true;
+                    Response::Success
+                }
+                res => wrap_result(res),
+            },
+            Request::GetMetrics | Request::GetSessionHistory | Request::Upgrade => {
+                wrap_result::<()>(Err("request only available on the admin socket".into()))
+            }
+            Request::GetSessions | Request::SetGreeterState { .. } if vsock => {
+                wrap_result::<()>(Err("request not available on vsock connections".into()))
+            }
+            Request::GetSessions => Response::Sessions {
+                sessions: ctx.sessions().await,
+            },
+            _ if admin => {
+                wrap_result::<()>(Err("request not available on the admin socket".into()))
+            }
+            Request::GetBanner => match ctx.banner() {
+                Ok(banner) => {
+                    banner_shown = true;
+                    Response::Banner { banner }
+                }
+                res => wrap_result(res),
+            },
+            Request::GetUserInfo { username } => match ctx.user_info(&username) {
+                Ok(info) => Response::UserInfo { info },
+                res => wrap_result(res),
+            },
+            Request::GetHostInfo => match ctx.host_info() {
+                Ok(info) => Response::HostInfo { info },
+                res => wrap_result(res),
+            },
+            Request::GetGreeterState { key } => match ctx.greeter_state(&key) {
+                Ok(value) => Response::GreeterState { value },
+                res => wrap_result(res),
+            },
+            Request::SetGreeterState { key, value } => {
+                wrap_result(ctx.set_greeter_state(key, value))
+            }
+            Request::GetDefaultSession => match ctx.default_session().await {
+                Ok(session) => Response::DefaultSession { session },
+                res => wrap_result(res),
+            },
+            Request::CreateSession { .. } if ctx.banner_required() && !banner_shown => {
+                wrap_result::<()>(Err(
+                    "banner must be retrieved before creating a session".into()
+                ))
+            }
+            Request::CreateSession {
+                username,
+                concurrent_auth: concurrent,
+                locale,
+            } => match ctx.create_session(username, locale).await {
+                Ok(id) => {
+                    *created = Some(id);
+                    let resp = client_get_question(ctx).await;
+                    concurrent_auth = concurrent && follows_auth(&resp);
+                    resp
+                }
+                res => wrap_result(res),
+            },
+            Request::PostAuthMessageResponse { response } if concurrent_auth => {
+                match ctx.post_answer(response).await {
+                    Ok(()) => continue,
+                    res => {
+                        concurrent_auth = false;
+                        wrap_result(res)
+                    }
+                }
+            }
+            Request::PostAuthMessageResponse { response } => {
+                match ctx.post_response(response).await {
+                    Ok(()) => client_get_question(ctx).await,
+                    res => wrap_result(res),
+                }
+            }
+            Request::StartSession {
+                cmd,
+                env,
+                session_type,
+            } => wrap_result(ctx.start(cmd, env, session_type).await),
+            Request::StartDesktopSession { id, env } => {
+                wrap_result(ctx.start_desktop(&id, env).await)
+            }
+            Request::CancelSession => {
+                concurrent_auth = false;
+                wrap_result(ctx.cancel().await)
+            }
+            Request::PowerAction { .. } if origin == Origin::Vsock { allow_power: false } => {
+                wrap_result::<()>(Err("power actions not allowed on vsock connections".into()))
+            }
+            Request::PowerAction { action } => wrap_result(ctx.power(action).await),
+        };
+
+        if let Response::Error {
+            error_type,
+            description,
+        } = &resp
+        {
+            info!(?error_type, "request failed: {}", description);
+        }
+
+        ipc::greeter_response(&resp);
+        with_timeout(cfg.timeout, "writing response", resp.write_to(&mut s)).await??;
+
+        // The re-exec is left to the main loop, which owns the listeners.
+        if upgrade {
+            raise(Signal::SIGUSR2)?;
+            return Ok(());
+        }
+    }
+}
+
+// Serve a greeter connection. If the connection fails, such as when it times
+// out, the session it created is cancelled, while a session created by another
+// connection, such as that of the greeter when this is a lock screen, is left
+// alone. The session worker may already be gone, so a failure to cancel is
+// only logged.
+async fn greeter_connection(ctx: &Context, stream: UnixStream, origin: Origin, cfg: ConfigIpc) {
+    info!("client connected");
+    let mut created = None;
+    if let Err(e) = client_handler(ctx, stream, origin, cfg, &mut created).await {
+        warn!("client loop failed: {}", e);
+        if let Some(id) = created {
+            if let Err(e) = ctx.cancel_session(id).await {
+                warn!("unable to cancel session: {}", e);
+            }
+        }
+    }
+}
+
+// Return a TTY path and the TTY/VT number, based on the configured target.
+//
+// If the target is VtSelection::Current, return the path to the TTY
+// referenced by stdin and the TTY number it is connected to if possible. If
+// the referenced TTY is a PTY, fail. Otherwise, open tty0, get the current VT
+// number, and return the path to that TTY and VT.
+//
+// If the target is VtSelection::Next, open tty0 and request the next VT
+// number. Return the TTY and VT
+//
+// If the target is VtSelection::Specific, simply return the specified TTY and
+// VT.
+//
+// If the target is VtSelection::None, return nothing.
+fn get_tty(config: &Config) -> Result<TerminalMode, Error> {
+    const TTY_PREFIX: &str = "/dev/tty";
+    const PTS_PREFIX: &str = "/dev/pts";
+
+    let term = match config.file.terminal.vt {
+        VtSelection::Current => {
+            let term = Terminal::stdin();
+            match term.ttyname() {
+                // We have a usable terminal, so let's decipher and return that
+                Ok(term_name)
+                    if term_name.starts_with(TTY_PREFIX) && term_name.len() > TTY_PREFIX.len() =>
+                {
+                    let vt = term_name[TTY_PREFIX.len()..]
+                        .parse()
+                        .map_err(|e| Error::Error(format!("unable to parse tty number: {}", e)))?;
+
+                    TerminalMode::Terminal {
+                        path: term_name,
+                        vt,
+                        switch: false,
+                    }
+                }
+                Ok(term_name) if term_name.starts_with(PTS_PREFIX) => {
+                    return Err("cannot use current VT when started from a psuedo terminal".into())
+                }
+                // We don't have a usable terminal, so we have to jump through some hoops
+                _ => {
+                    let sys_term = Terminal::open("/dev/tty0")
+                        .map_err(|e| format!("unable to open terminal: {}", e))?;
+                    let vt = sys_term
+                        .vt_get_current()
+                        .map_err(|e| format!("unable to get current VT: {}", e))?;
+                    TerminalMode::Terminal {
+                        path: format!("/dev/tty{}", vt),
+                        vt,
+                        switch: false,
+                    }
+                }
+            }
+        }
+        VtSelection::Next => {
+            let term = Terminal::open("/dev/tty0")
+                .map_err(|e| format!("unable to open terminal: {}", e))?;
+            let vt = term
+                .vt_get_next()
+                .map_err(|e| format!("unable to get next VT: {}", e))?;
+            TerminalMode::Terminal {
+                path: format!("/dev/tty{}", vt),
+                vt,
+                switch: config.file.terminal.switch,
+            }
+        }
+        VtSelection::None => TerminalMode::Stdin,
+        VtSelection::Specific(vt) => TerminalMode::Terminal {
+            path: format!("/dev/tty{}", vt),
+            vt,
+            switch: config.file.terminal.switch,
+        },
+    };
+    return Ok(term);
+}
+
+// Re-read the configuration, applying the settings that can be changed at
+// runtime. This is called on SIGHUP.
+fn reload() -> Result<(), Error> {
+    let config = config::read_config()?;
+    logging::set_filter(&config.log_filter())?;
+    info!(filter = %config.log_filter(), "configuration reloaded");
+    Ok(())
+}
+
+// Listener is a convenience wrapper for creating the UnixListener we need, and
+// for providing cleanup on Drop.
+struct Listener(UnixListener);
+
+impl Listener {
+    fn create(uid: Uid, gid: Gid) -> Result<(String, Listener), Error> {
+        let path = format!("/run/greetd-{}.sock", getpid().as_raw());
+        let _ = std::fs::remove_file(&path);
+        let listener =
+            UnixListener::bind(&path).map_err(|e| format!("unable to open listener: {}", e))?;
+        chown(path.as_str(), Some(uid), Some(gid))
+            .map_err(|e| format!("unable to chown greetd socket at {}: {}", path, e))?;
+        Ok((path, Listener(listener)))
+    }
+
+    // Create the admin socket, only accessible to root.
+    fn create_admin(path: &str) -> Result<Listener, Error> {
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)
+            .map_err(|e| format!("unable to open admin listener: {}", e))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600)).map_err(|e| {
+            format!(
+                "unable to set permissions of admin socket at {}: {}",
+                path, e
+            )
+        })?;
+        Ok(Listener(listener))
+    }
+
+    // Take over a listening socket inherited across a re-exec.
+    fn from_raw_fd(fd: RawFd) -> Result<Listener, Error> {
+        let listener = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+        listener.set_nonblocking(true)?;
+        Ok(Listener(UnixListener::from_std(listener)?))
+    }
+
+    async fn accept_admin(
+        listener: &Option<Listener>,
+    ) -> std::io::Result<(UnixStream, tokio::net::unix::SocketAddr)> {
+        match listener {
+            Some(listener) => listener.0.accept().await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let addr = match self.0.local_addr() {
+            Ok(addr) => addr,
+            Err(_) => return,
+        };
+        if let Some(path) = addr.as_pathname() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// Re-execute greetd, handing over the listeners and the running session. This
+// only returns if greetd could not re-exec.
+async fn reexec(
+    ctx: &Context,
+    listener_path: &str,
+    listener: &Listener,
+    admin_listener: &Option<Listener>,
+    vsock_listener: &Option<VsockListener>,
+    term_mode: &TerminalMode,
+) -> Result<(), Error> {
+    // The new process would take the sessions down with it if it failed to
+    // start, so make sure that it will at least be able to read the
+    // configuration.
+    config::read_config()?;
+    let state = State {
+        listener: listener.0.as_raw_fd(),
+        listener_path: listener_path.to_string(),
+        admin_listener: admin_listener.as_ref().map(|l| l.0.as_raw_fd()),
+        vsock_listener: vsock_listener.as_ref().map(|l| l.as_raw_fd()),
+        term_mode: term_mode.clone(),
+        next_session_id: interface::next_session_id(),
+        current: ctx.suspend().await?,
+        metrics: ctx.metrics(),
+        history: ctx.save_history(),
+    };
+    info!("re-executing greetd");
+    Err(upgrade::exec(&state))
+}
+
+pub async fn main(config: Config) -> Result<(), Error> {
+    let resumed = upgrade::take()?;
+
+    // The pidfile is taken over after a re-exec, but readiness has already
+    // been signalled.
+    let _pidfile = config
+        .internal
+        .pidfile
+        .as_deref()
+        .map(Pidfile::create)
+        .transpose()?;
+    let notifier = match (config.internal.ready_fd, &resumed) {
+        (Some(fd), None) => Some(Notifier::new(fd)?),
+        _ => None,
+    };
+
+    // Keep greetd out of reach of the OOM killer, so that memory pressure does
+    // not leave the seat without a greeter.
+    if let Err(e) = limits::set_oom_score_adj(config.file.general.oom_score_adj) {
+        warn!("{}", e);
+    }
+
+    spawn::init(&config.file.general.worker_cgroup)?;
+
+    let service = if Path::new(&format!("/etc/pam.d/{}", config.file.general.service)).exists() {
+        &config.file.general.service
+    } else if Path::new(&format!("/usr/lib/pam.d/{}", config.file.general.service)).exists() {
+        &config.file.general.service
+    } else {
+        return Err(format!("PAM '{}' service missing", config.file.general.service).into());
+    };
+
+    let greeter_service = if Path::new(&format!(
+        "/etc/pam.d/{}",
+        config.file.default_session.service
+    ))
+    .exists()
+    {
+        &config.file.default_session.service
+    } else if Path::new(&format!(
+        "/usr/lib/pam.d/{}",
+        config.file.default_session.service
+    ))
+    .exists()
+    {
+        &config.file.default_session.service
+    } else {
+        service
+    };
+
+    let u = nix::unistd::User::from_name(&config.file.default_session.user)?.ok_or(format!(
+        "configured default session user '{}' not found",
+        &config.file.default_session.user
+    ))?;
+
+    // After a re-exec, the listeners and the terminal are carried over as they
+    // were, and changes to their configuration only apply after a restart.
+    let (listener_path, listener, admin_listener, vsock_listener, term_mode) = match &resumed {
+        Some(state) => (
+            state.listener_path.clone(),
+            Listener::from_raw_fd(state.listener)?,
+            state
+                .admin_listener
+                .map(Listener::from_raw_fd)
+                .transpose()?,
+            state
+                .vsock_listener
+                .map(VsockListener::from_raw_fd)
+                .transpose()?,
+            state.term_mode.clone(),
+        ),
+        None => {
+            let (listener_path, listener) = Listener::create(u.uid, u.gid)?;
+            let admin_listener = match config.file.general.admin_socket.as_str() {
+                "" => None,
+                path => Some(Listener::create_admin(path)?),
+            };
+            let vsock_listener = match &config.file.vsock {
+                Some(vsock) => Some(VsockListener::bind(vsock.port)?),
+                None => None,
+            };
+            let term_mode = get_tty(&config)?;
+            if !config.file.terminal.switch {
+                wait_vt(&term_mode).map_err(|e| format!("unable to wait VT: {}", e))?;
+            }
+            (
+                listener_path,
+                listener,
+                admin_listener,
+                vsock_listener,
+                term_mode,
+            )
+        }
+    };
+    let (vsock_cids, vsock_origin) = match &config.file.vsock {
+        Some(vsock) => (
+            vsock.allowed_cids.clone(),
+            Origin::Vsock {
+                allow_power: vsock.allow_power,
+            },
+        ),
+        None => (Vec::new(), Origin::Vsock { allow_power: false }),
+    };
+
+    let ctx = Rc::new(Context::new(
+        &config.file,
+        greeter_service.to_string(),
+        service.to_string(),
+        term_mode.clone(),
+        listener_path.clone(),
+    ));
+    let resumed = match resumed {
+        Some(state) => {
+            interface::set_next_session_id(state.next_session_id);
+            let running = state.current.is_some();
+            ctx.resume(state.current, state.metrics, state.history)
+                .await;
+            Some(running)
+        }
+        None => None,
+    };
+    let mut exits = reaper::spawn(ctx.registry())?;
+
+    let ipc_config = config.file.ipc;
+    let limiter = Rc::new(RefCell::new(Limiter::new(
+        ipc_config.max_connections,
+        ipc_config.connection_rate,
+    )));
+    // vsock clients are limited per CID, separately from local clients.
+    let vsock_limiter = Rc::new(RefCell::new(Limiter::new(
+        ipc_config.max_connections,
+        ipc_config.connection_rate,
+    )));
+
+    let mut plymouth = Handoff::start(config.file.general.plymouth && resumed.is_none());
+
+    if let (Some(devices), None) = (&config.file.devices, resumed) {
+        devices::wait(&devices.wait, Duration::from_secs(devices.timeout)).await;
+    }
+
+    if let Some(running) = resumed {
+        // The session that was running before the re-exec carries on, and a
+        // greeter is only needed if there was none.
+        if !running {
+            if let Err(e) = ctx.greet().await {
+                error!("unable to start greeter: {}", e);
+                reset_vt(&term_mode).map_err(|e| format!("unable to reset VT: {}", e))?;
+
+                std::process::exit(1);
+            }
+        }
+    } else if let (Some(s), true) = (config.file.initial_session, ctx.is_first_run()) {
+        if let Err(e) = ctx.start_user_session(&s.user, vec![s.command]).await {
+            error!("unable to start initial session: {}", e);
+            plymouth.finish(false);
+            reset_vt(&term_mode).map_err(|e| format!("unable to reset VT: {}", e))?;
+
+            std::process::exit(1);
+        }
+        // The initial session does not connect to us, so there is nothing
+        // more to wait for.
+        plymouth.finish(true);
+    } else if let Err(e) = ctx.greet().await {
+        error!("unable to start greeter: {}", e);
+        plymouth.finish(false);
+        reset_vt(&term_mode).map_err(|e| format!("unable to reset VT: {}", e))?;
+
+        std::process::exit(1);
+    }
+
+    // The greeter is considered ready when it first connects. Greeters that
+    // only connect once the user has entered something are not waited for
+    // longer than PLYMOUTH_TIMEOUT.
+    let plymouth_timeout = sleep(PLYMOUTH_TIMEOUT);
+    tokio::pin!(plymouth_timeout);
+
+    ctx.create_runfile();
+
+    if let Some(Err(e)) = notifier.map(Notifier::notify) {
+        warn!("{}", e);
+    }
+
+    let mut alarm = signal(SignalKind::alarm()).expect("unable to listen for SIGALRM");
+    let mut term = signal(SignalKind::terminate()).expect("unable to listen for SIGTERM");
+    let mut int = signal(SignalKind::interrupt()).expect("unable to listen for SIGINT");
+    let mut hup = signal(SignalKind::hangup()).expect("unable to listen for SIGHUP");
+    let mut usr2 = signal(SignalKind::user_defined2()).expect("unable to listen for SIGUSR2");
+
+    let mut connection_id: u64 = 0;
+    loop {
+        tokio::select! {
+            Some(exit) = exits.recv() => ctx.child_exited(exit).await.map_err(|e| format!("child_exited: {}", e))?,
+            _ = &mut plymouth_timeout, if plymouth.pending() => plymouth.finish(true),
+            _ = alarm.recv() => ctx.alarm().await.map_err(|e| format!("alarm: {}", e))?,
+            _ = term.recv() => {
+                ctx.terminate().await.map_err(|e| format!("terminate: {}", e))?;
+                break;
+            }
+            _ = int.recv() => {
+                ctx.terminate().await.map_err(|e| format!("terminate: {}", e))?;
+                break;
+            }
+            _ = hup.recv() => {
+                if let Err(e) = reload() {
+                    warn!("unable to reload configuration: {}", e);
+                }
+            }
+            _ = usr2.recv() => {
+                if let Err(e) = reexec(&ctx, &listener_path, &listener, &admin_listener, &vsock_listener, &term_mode).await {
+                    warn!("unable to re-execute greetd: {}", e);
+                }
+            }
+            stream = listener.0.accept() => match stream {
+                Ok((stream, _)) => {
+                    plymouth.finish(true);
+                    connection_id += 1;
+                    let cred = stream.peer_cred().ok();
+                    let pid = cred.and_then(|cred| cred.pid());
+                    // Clients without credentials share a single limit.
+                    let uid = cred.map(|cred| cred.uid()).unwrap_or(u32::MAX);
+                    let permit = match limiter::acquire(&limiter, uid) {
+                        Ok(permit) => permit,
+                        Err(rejected) => {
+                            if rejected.first {
+                                warn!(uid, pid, "rejecting connections: {}", rejected.reason);
+                            }
+                            continue;
+                        }
+                    };
+                    let span = info_span!("connection", id = connection_id, pid);
+                    let client_ctx = ctx.clone();
+                    task::spawn_local(async move {
+                        let _permit = permit;
+                        greeter_connection(&client_ctx, stream, Origin::Greeter, ipc_config).await;
+                    }.instrument(span));
+                },
+                Err(err) => return Err(format!("accept: {}", err).into()),
+            },
+            stream = Listener::accept_admin(&admin_listener) => match stream {
+                Ok((stream, _)) => {
+                    connection_id += 1;
+                    let span = info_span!("admin_connection", id = connection_id);
+                    let client_ctx = ctx.clone();
+                    task::spawn_local(async move {
+                        if let Err(e) = client_handler(&client_ctx, stream, Origin::Admin, ipc_config, &mut None).await {
+                            warn!("admin client loop failed: {}", e);
+                        }
+                    }.instrument(span));
+                },
+                Err(err) => return Err(format!("admin accept: {}", err).into()),
+            },
+            stream = VsockListener::accept_opt(&vsock_listener) => match stream {
+                Ok((stream, cid)) => {
+                    if !vsock_cids.contains(&cid) {
+                        warn!(cid, "rejecting vsock connection from CID not in vsock.allowed_cids");
+                        continue;
+                    }
+                    plymouth.finish(true);
+                    connection_id += 1;
+                    let permit = match limiter::acquire(&vsock_limiter, cid) {
+                        Ok(permit) => permit,
+                        Err(rejected) => {
+                            if rejected.first {
+                                warn!(cid, "rejecting vsock connections: {}", rejected.reason);
+                            }
+                            continue;
+                        }
+                    };
+                    let span = info_span!("vsock_connection", id = connection_id, cid);
+                    let client_ctx = ctx.clone();
+                    task::spawn_local(async move {
+                        let _permit = permit;
+                        greeter_connection(&client_ctx, stream, vsock_origin, ipc_config).await;
+                    }.instrument(span));
+                },
+                Err(err) => return Err(format!("vsock accept: {}", err).into()),
+            }
+        }
+    }
+
+    Ok(())
+}
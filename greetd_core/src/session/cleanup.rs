@@ -0,0 +1,323 @@
+//! Removal of runtime files left behind by a session, such as the sockets of
+//! a compositor that crashed, which could otherwise prevent the next session
+//! from starting.
+//!
+//! Files in directories controlled by the user are removed by a child process
+//! running as the user, so that the user cannot trick greetd into removing
+//! anything else by replacing parts of the path with symlinks. Stale X locks
+//! are removed by greetd itself, as they may belong to root, relative to the
+//! directories they are in and without following symlinks.
+
+use std::{
+    ffi::CString,
+    fs::{self, File},
+    io::Read,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::MetadataExt,
+        io::{AsRawFd, FromRawFd, RawFd},
+    },
+    path::Path,
+};
+
+use nix::{
+    dir::Dir,
+    errno::Errno,
+    fcntl::{flock, openat, AtFlags, FlockArg, OFlag},
+    sys::{
+        signal::kill,
+        stat::{fstatat, Mode, SFlag},
+        wait::waitpid,
+    },
+    unistd::{fork, initgroups, setgid, setuid, unlinkat, ForkResult, Pid, UnlinkatFlags, User},
+};
+use tracing::{info, warn};
+
+use crate::config::ConfigCleanup;
+
+/// Match a file name against a pattern, where '*' matches any sequence of
+/// characters and '?' matches any single character.
+pub(crate) fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.split_first(), name.split_first()) {
+        (None, None) => true,
+        (Some((b'*', rest)), _) => {
+            matches(rest, name) || (!name.is_empty() && matches(pattern, &name[1..]))
+        }
+        (Some((b'?', p)), Some((_, n))) => matches(p, n),
+        (Some((p, prest)), Some((n, nrest))) if p == n => matches(prest, nrest),
+        _ => false,
+    }
+}
+
+// Substitute the variables supported in cleanup patterns.
+fn expand(pattern: &str, user: &User, runtime_dir: Option<&str>) -> Option<String> {
+    let mut res = pattern
+        .replace("$USER", &user.name)
+        .replace("$UID", &user.uid.to_string())
+        .replace("$HOME", &user.dir.to_string_lossy());
+    if res.contains("$XDG_RUNTIME_DIR") {
+        res = res.replace("$XDG_RUNTIME_DIR", runtime_dir?);
+    }
+    Some(res)
+}
+
+// Parse the PID from the contents of an X lock file, which is written as a
+// right-aligned decimal number followed by a newline.
+fn parse_x_lock(contents: &str) -> Option<i32> {
+    contents.trim().parse().ok().filter(|pid| *pid > 0)
+}
+
+fn remove(path: &Path) {
+    let res = match fs::symlink_metadata(path) {
+        Ok(m) if m.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => return,
+    };
+    match res {
+        Ok(()) => info!(path = %path.display(), "removed stale runtime file"),
+        Err(e) => warn!(path = %path.display(), "unable to remove stale runtime file: {}", e),
+    }
+}
+
+// Remove Wayland sockets in the runtime directory whose lock is not held. A
+// compositor holds the lock of its socket for as long as it runs.
+fn wayland_sockets(runtime_dir: &Path) {
+    let entries = match fs::read_dir(runtime_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let name = entry.file_name();
+        let socket = match name
+            .to_str()
+            .filter(|n| n.starts_with("wayland-"))
+            .and_then(|n| n.strip_suffix(".lock"))
+        {
+            Some(socket) => socket.to_string(),
+            None => continue,
+        };
+        let lock = entry.path();
+        let held = match File::open(&lock) {
+            Ok(f) => flock(f.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_err(),
+            Err(_) => true,
+        };
+        if !held {
+            remove(&runtime_dir.join(socket));
+            remove(&lock);
+        }
+    }
+}
+
+fn open_dir(path: &str) -> Option<Dir> {
+    Dir::open(
+        path,
+        OFlag::O_RDONLY | OFlag::O_DIRECTORY | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )
+    .ok()
+}
+
+// Whether the entry of the directory is of the given type and owned by the
+// user or root, without following symlinks.
+fn owned_entry(dir: RawFd, name: &str, kind: SFlag, user: &User) -> bool {
+    match fstatat(dir, name, AtFlags::AT_SYMLINK_NOFOLLOW) {
+        Ok(st) => {
+            SFlag::from_bits_truncate(st.st_mode) & SFlag::S_IFMT == kind
+                && (st.st_uid == user.uid.as_raw() || st.st_uid == 0)
+        }
+        Err(_) => false,
+    }
+}
+
+fn unlink_entry(dir: RawFd, dir_path: &str, name: &str) {
+    match unlinkat(Some(dir), name, UnlinkatFlags::NoRemoveDir) {
+        Ok(()) => info!(path = %format!("{}/{}", dir_path, name), "removed stale runtime file"),
+        Err(Errno::ENOENT) => (),
+        Err(e) => warn!(
+            path = %format!("{}/{}", dir_path, name),
+            "unable to remove stale runtime file: {}",
+            e
+        ),
+    }
+}
+
+// Read the PID from an X lock file, without following symlinks.
+fn read_x_lock(dir: RawFd, name: &str) -> Option<i32> {
+    let fd = openat(
+        dir,
+        name,
+        OFlag::O_RDONLY | OFlag::O_NOFOLLOW | OFlag::O_CLOEXEC | OFlag::O_NONBLOCK,
+        Mode::empty(),
+    )
+    .ok()?;
+    let mut contents = String::new();
+    unsafe { File::from_raw_fd(fd) }
+        .take(64)
+        .read_to_string(&mut contents)
+        .ok()?;
+    parse_x_lock(&contents)
+}
+
+// Remove X lock files and sockets owned by the user or root whose server is
+// no longer running. /tmp is writable by anyone, so everything is done
+// relative to the directories, which are opened without following symlinks.
+fn x_locks(user: &User) {
+    let mut tmp = match open_dir("/tmp") {
+        Some(tmp) => tmp,
+        None => return,
+    };
+    let tmp_fd = tmp.as_raw_fd();
+    let sockets = open_dir("/tmp/.X11-unix");
+    let names: Vec<String> = tmp
+        .iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().to_str().ok().map(str::to_string))
+        .collect();
+    for name in names {
+        let display = match name
+            .strip_prefix(".X")
+            .and_then(|n| n.strip_suffix("-lock"))
+            .filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        {
+            Some(display) => display.to_string(),
+            None => continue,
+        };
+        if !owned_entry(tmp_fd, &name, SFlag::S_IFREG, user) {
+            continue;
+        }
+        let alive = match read_x_lock(tmp_fd, &name) {
+            Some(pid) => kill(Pid::from_raw(pid), None) != Err(Errno::ESRCH),
+            None => false,
+        };
+        if alive {
+            continue;
+        }
+        if let Some(sockets) = &sockets {
+            let socket = format!("X{}", display);
+            if owned_entry(sockets.as_raw_fd(), &socket, SFlag::S_IFSOCK, user) {
+                unlink_entry(sockets.as_raw_fd(), "/tmp/.X11-unix", &socket);
+            }
+        }
+        unlink_entry(tmp_fd, "/tmp", &name);
+    }
+}
+
+// Remove the files matching a pattern that are owned by the user. Only the
+// last component of the pattern may contain wildcards.
+fn pattern(pattern: &str, user: &User) {
+    let path = Path::new(pattern);
+    let (dir, name) = match (path.parent(), path.file_name().and_then(|n| n.to_str())) {
+        (Some(dir), Some(name)) if path.is_absolute() => (dir, name),
+        _ => {
+            warn!(pattern, "ignoring relative cleanup pattern");
+            return;
+        }
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|e| e.ok()) {
+        let owned = match fs::symlink_metadata(entry.path()) {
+            Ok(m) => m.uid() == user.uid.as_raw(),
+            Err(_) => false,
+        };
+        if owned && matches(name.as_bytes(), entry.file_name().as_bytes()) {
+            remove(&entry.path());
+        }
+    }
+}
+
+// Run a function in a child process that has dropped to the user, waiting for
+// it to finish.
+fn as_user(user: &User, f: impl FnOnce()) {
+    let name = match CString::new(user.name.as_str()) {
+        Ok(name) => name,
+        Err(_) => return,
+    };
+    match unsafe { fork() } {
+        Ok(ForkResult::Child) => {
+            let dropped = initgroups(&name, user.gid)
+                .and_then(|_| setgid(user.gid))
+                .and_then(|_| setuid(user.uid));
+            match dropped {
+                Ok(()) => f(),
+                Err(e) => warn!("unable to drop privileges for cleanup: {}", e),
+            }
+            unsafe { libc::_exit(0) }
+        }
+        Ok(ForkResult::Parent { child }) => loop {
+            match waitpid(child, None) {
+                Err(Errno::EINTR) => continue,
+                Err(e) => {
+                    warn!("unable to wait for cleanup: {}", e);
+                    break;
+                }
+                Ok(_) => break,
+            }
+        },
+        Err(e) => warn!("unable to fork for cleanup: {}", e),
+    }
+}
+
+/// Remove stale runtime files after a session of the user has ended.
+pub fn run(cfg: &ConfigCleanup, user: &User, runtime_dir: Option<&str>) {
+    if cfg.x_locks {
+        x_locks(user);
+    }
+    let patterns: Vec<_> = cfg
+        .paths
+        .iter()
+        .filter_map(|p| expand(p, user, runtime_dir))
+        .collect();
+    let wayland_dir = runtime_dir.filter(|_| cfg.wayland_sockets);
+    if wayland_dir.is_none() && patterns.is_empty() {
+        return;
+    }
+    as_user(user, || {
+        if let Some(runtime_dir) = wayland_dir {
+            wayland_sockets(Path::new(runtime_dir));
+        }
+        for p in &patterns {
+            pattern(p, user);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches(b"wayland-*", b"wayland-0"));
+        assert!(matches(b"wayland-*", b"wayland-"));
+        assert!(matches(b"*.lock", b"wayland-1.lock"));
+        assert!(matches(b"sway-ipc.?.sock", b"sway-ipc.1.sock"));
+        assert!(matches(b"pulse", b"pulse"));
+        assert!(!matches(b"wayland-*", b"pulse"));
+        assert!(!matches(b"?", b""));
+        assert!(!matches(b"a*b", b"a-c"));
+    }
+
+    #[test]
+    fn x_lock() {
+        assert_eq!(parse_x_lock("      1234\n"), Some(1234));
+        assert_eq!(parse_x_lock("0\n"), None);
+        assert_eq!(parse_x_lock("garbage"), None);
+    }
+
+    #[test]
+    fn variables() {
+        let user = User::from_uid(nix::unistd::getuid()).unwrap().unwrap();
+        assert_eq!(
+            expand("$XDG_RUNTIME_DIR/sway-ipc.*", &user, Some("/run/user/1000")),
+            Some("/run/user/1000/sway-ipc.*".to_string())
+        );
+        assert_eq!(expand("$XDG_RUNTIME_DIR/pulse", &user, None), None);
+        assert_eq!(
+            expand("/tmp/$USER-*", &user, None),
+            Some(format!("/tmp/{}-*", user.name))
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use std::{os::unix::net::UnixDatagram, sync::Mutex};
+
+use tracing::error;
+
+use super::worker::{AuthMessageType, ParentToSessionChild, SessionChildToParent};
+use crate::pam::converse::Converse;
+
+/// SessionConv is a PAM conversation implementation that forwards questions
+/// over a socket.
+///
+/// PAM modules that run several authentication mechanisms at once, such as a
+/// fingerprint reader alongside a password prompt, may call the conversation
+/// from several threads, and may finish authentication while a prompt is still
+/// waiting for its answer. Informational and error messages are therefore
+/// sent without waiting for a response, while prompts wait for their answer
+/// with the receive lock held. The worker holds the same lock when it receives
+/// messages itself, so that an answer cannot be taken by the wrong thread.
+pub struct SessionConv<'a> {
+    sock: &'a UnixDatagram,
+    send_lock: Mutex<()>,
+    recv_lock: &'a Mutex<()>,
+}
+
+impl<'a> SessionConv<'a> {
+    fn send(&self, msg: &str, style: AuthMessageType) -> Result<(), ()> {
+        let msg = SessionChildToParent::PamMessage {
+            style,
+            msg: msg.to_string(),
+        };
+        let _guard = self.send_lock.lock().map_err(|_| ())?;
+        msg.send(self.sock).map_err(|e| error!("pam_conv: {}", e))
+    }
+
+    fn question(&self, msg: &str, style: AuthMessageType) -> Result<Option<String>, ()> {
+        let _guard = self.recv_lock.lock().map_err(|_| ())?;
+        self.send(msg, style)?;
+
+        let mut data = Vec::new();
+        let msg = ParentToSessionChild::recv(self.sock, &mut data)
+            .map_err(|e| error!("pam_conv: {}", e))?;
+
+        match msg {
+            ParentToSessionChild::PamResponse { resp, .. } => Ok(resp),
+            ParentToSessionChild::Cancel => Err(()),
+            _ => Err(()),
+        }
+    }
+
+    /// Create a new `SessionConv` handler. Prompts hold recv_lock while they
+    /// wait for their answer.
+    pub fn new(sock: &'a UnixDatagram, recv_lock: &'a Mutex<()>) -> SessionConv<'a> {
+        SessionConv {
+            sock,
+            send_lock: Mutex::new(()),
+            recv_lock,
+        }
+    }
+}
+
+impl<'a> Converse for SessionConv<'a> {
+    fn prompt_echo(&self, msg: &str) -> Result<String, ()> {
+        match self.question(msg, AuthMessageType::Visible) {
+            Ok(Some(response)) => Ok(response),
+            _ => Err(()),
+        }
+    }
+    fn prompt_blind(&self, msg: &str) -> Result<String, ()> {
+        match self.question(msg, AuthMessageType::Secret) {
+            Ok(Some(response)) => Ok(response),
+            _ => Err(()),
+        }
+    }
+    fn info(&self, msg: &str) -> Result<(), ()> {
+        self.send(msg, AuthMessageType::Info)
+    }
+    fn error(&self, msg: &str) -> Result<(), ()> {
+        self.send(msg, AuthMessageType::Error)
+    }
+}
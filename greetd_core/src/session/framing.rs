@@ -0,0 +1,228 @@
+//! Framing for messages between greetd and its session workers.
+//!
+//! A message may be larger than what fits in a single datagram, such as when
+//! starting a session with a large environment. Messages are therefore split
+//! into frames of at most DATAGRAM_SIZE bytes, each starting with a flag byte
+//! that tells whether more frames follow.
+
+use std::os::unix::net::UnixDatagram;
+
+use tokio::net::UnixDatagram as TokioUnixDatagram;
+
+use crate::{error::Error, scrambler::Scrambler};
+
+/// The maximum size of a single datagram, including the flag byte.
+pub const DATAGRAM_SIZE: usize = 10240;
+
+/// The maximum size of a reassembled message.
+pub const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+const PAYLOAD_SIZE: usize = DATAGRAM_SIZE - 1;
+
+const FLAG_FINAL: u8 = 0;
+const FLAG_MORE: u8 = 1;
+
+/// A single frame of a message.
+#[derive(Debug, PartialEq)]
+pub struct Frame<'a> {
+    pub more: bool,
+    pub payload: &'a [u8],
+}
+
+impl Frame<'_> {
+    /// Write the frame to the buffer, returning the number of bytes written.
+    pub fn encode(&self, buf: &mut [u8; DATAGRAM_SIZE]) -> usize {
+        buf[0] = if self.more { FLAG_MORE } else { FLAG_FINAL };
+        buf[1..=self.payload.len()].copy_from_slice(self.payload);
+        self.payload.len() + 1
+    }
+}
+
+/// An iterator over the frames of a message. A message always has at least
+/// one frame, the last of which is marked final.
+pub struct Frames<'a> {
+    remaining: Option<&'a [u8]>,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Frame<'a>> {
+        let remaining = self.remaining.take()?;
+        if remaining.len() > PAYLOAD_SIZE {
+            let (payload, rest) = remaining.split_at(PAYLOAD_SIZE);
+            self.remaining = Some(rest);
+            Some(Frame {
+                more: true,
+                payload,
+            })
+        } else {
+            Some(Frame {
+                more: false,
+                payload: remaining,
+            })
+        }
+    }
+}
+
+/// Split a message into frames.
+pub fn frames(msg: &[u8]) -> Frames<'_> {
+    Frames {
+        remaining: Some(msg),
+    }
+}
+
+/// Append a received datagram to a message being reassembled. Returns true if
+/// the message is complete.
+pub fn reassemble(msg: &mut Vec<u8>, datagram: &[u8]) -> Result<bool, Error> {
+    let (flag, payload) = datagram
+        .split_first()
+        .ok_or_else(|| Error::ProtocolError("empty datagram".to_string()))?;
+    if msg.len() + payload.len() > MAX_MESSAGE_SIZE {
+        return Err(Error::ProtocolError(format!(
+            "message exceeds {} bytes",
+            MAX_MESSAGE_SIZE
+        )));
+    }
+    msg.extend_from_slice(payload);
+    match *flag {
+        FLAG_FINAL => Ok(true),
+        FLAG_MORE => Ok(false),
+        flag => Err(Error::ProtocolError(format!(
+            "invalid frame flag: {}",
+            flag
+        ))),
+    }
+}
+
+/// Send a message on a blocking socket.
+pub fn send(sock: &UnixDatagram, msg: &[u8]) -> Result<(), Error> {
+    let mut buf = [0; DATAGRAM_SIZE];
+    let res = frames(msg).try_for_each(|frame| {
+        let len = frame.encode(&mut buf);
+        sock.send(&buf[..len]).map(|_| ())
+    });
+    buf.scramble();
+    res.map_err(|e| Error::io("unable to send message", e))
+}
+
+/// Receive a message on a blocking socket into msg, replacing its contents.
+pub fn recv(sock: &UnixDatagram, msg: &mut Vec<u8>) -> Result<(), Error> {
+    let mut buf = [0; DATAGRAM_SIZE];
+    msg.clear();
+    loop {
+        let len = sock
+            .recv(&mut buf)
+            .map_err(|e| Error::io("unable to receive message", e))?;
+        if reassemble(msg, &buf[..len])? {
+            break;
+        }
+    }
+    buf.scramble();
+    Ok(())
+}
+
+/// Send a message on an async socket.
+pub async fn send_async(sock: &TokioUnixDatagram, msg: &[u8]) -> Result<(), Error> {
+    let mut buf = [0; DATAGRAM_SIZE];
+    for frame in frames(msg) {
+        let len = frame.encode(&mut buf);
+        if let Err(e) = sock.send(&buf[..len]).await {
+            buf.scramble();
+            return Err(Error::io("unable to send message", e));
+        }
+    }
+    buf.scramble();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::session::worker::ParentToSessionChild;
+
+    fn roundtrip(msg: &[u8]) -> Vec<u8> {
+        let mut buf = [0; DATAGRAM_SIZE];
+        let mut out = Vec::new();
+        let mut done = false;
+        for frame in frames(msg) {
+            assert!(!done, "frame after final frame");
+            let len = frame.encode(&mut buf);
+            assert!(len <= DATAGRAM_SIZE);
+            done = reassemble(&mut out, &buf[..len]).expect("unable to reassemble");
+        }
+        assert!(done, "no final frame");
+        out
+    }
+
+    #[test]
+    fn frame_count() {
+        for (len, count) in [
+            (0, 1),
+            (1, 1),
+            (PAYLOAD_SIZE - 1, 1),
+            (PAYLOAD_SIZE, 1),
+            (PAYLOAD_SIZE + 1, 2),
+            (2 * PAYLOAD_SIZE, 2),
+            (2 * PAYLOAD_SIZE + 1, 3),
+        ] {
+            let msg = vec![b'x'; len];
+            let frames: Vec<_> = frames(&msg).collect();
+            assert_eq!(frames.len(), count, "message of {} bytes", len);
+            assert!(frames[..count - 1].iter().all(|f| f.more));
+            assert!(!frames[count - 1].more);
+        }
+    }
+
+    #[test]
+    fn boundary_roundtrip() {
+        for len in [
+            0,
+            PAYLOAD_SIZE - 1,
+            PAYLOAD_SIZE,
+            PAYLOAD_SIZE + 1,
+            DATAGRAM_SIZE,
+            3 * PAYLOAD_SIZE + 7,
+        ] {
+            let msg: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            assert_eq!(roundtrip(&msg), msg, "message of {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let mut msg = Vec::new();
+        assert!(reassemble(&mut msg, &[]).is_err());
+        assert!(reassemble(&mut msg, &[2, b'x']).is_err());
+
+        let mut msg = vec![0; MAX_MESSAGE_SIZE];
+        assert!(reassemble(&mut msg, &[FLAG_FINAL]).is_ok());
+        assert!(reassemble(&mut msg, &[FLAG_FINAL, b'x']).is_err());
+    }
+
+    #[test]
+    fn socket_roundtrip() {
+        let (a, b) = UnixDatagram::pair().expect("unable to create socket pair");
+        let args = ParentToSessionChild::Args {
+            env: (0..64)
+                .map(|i| format!("VAR{}={}", i, "x".repeat(500)))
+                .collect(),
+            cmd: vec!["sway".to_string()],
+        };
+        let out = serde_json::to_vec(&args).expect("unable to serialize");
+        assert!(out.len() > 2 * DATAGRAM_SIZE);
+        send(&a, &out).expect("unable to send");
+
+        let mut data = Vec::new();
+        recv(&b, &mut data).expect("unable to receive");
+        match serde_json::from_slice(&data).expect("unable to deserialize") {
+            ParentToSessionChild::Args { env, cmd } => {
+                assert_eq!(env.len(), 64);
+                assert_eq!(env[63], format!("VAR63={}", "x".repeat(500)));
+                assert_eq!(cmd, vec!["sway".to_string()]);
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Support for users whose home directories are managed by systemd-homed.
+//!
+//! Such homes may be encrypted, and must be activated with the user's
+//! password before the session is opened. Homes activated by greetd are
+//! deactivated again when the session ends.
+
+use std::time::Duration;
+
+use dbus::blocking::{Connection, Proxy};
+use tracing::info;
+
+use crate::{error::Error, scrambler::Scrambler};
+
+const DESTINATION: &str = "org.freedesktop.home1";
+const PATH: &str = "/org/freedesktop/home1";
+const MANAGER: &str = "org.freedesktop.home1.Manager";
+
+// Unlocking an encrypted home can take a while, as the key derivation is
+// deliberately slow.
+const TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The outcome of activating a home.
+#[derive(Debug, PartialEq)]
+pub enum Activation {
+    /// The user's home is not managed by systemd-homed.
+    NotManaged,
+    /// The home was already active, and is left alone.
+    AlreadyActive,
+    /// The home was activated, and should be deactivated when the session
+    /// ends.
+    Activated,
+}
+
+// The reply to GetHomeByName: UID, state, GID, real name, home directory,
+// shell and object path.
+type HomeRecord = (
+    u32,
+    String,
+    u32,
+    String,
+    String,
+    String,
+    dbus::Path<'static>,
+);
+
+fn proxy(conn: &Connection) -> Proxy<'_, &Connection> {
+    conn.with_proxy(DESTINATION, PATH, TIMEOUT)
+}
+
+/// Activate the home of a user, if it is managed by systemd-homed and not yet
+/// active.
+pub fn activate(user: &str, password: Option<&str>) -> Result<Activation, Error> {
+    let conn = match Connection::new_system() {
+        Ok(conn) => conn,
+        // Without a system bus, there is no systemd-homed.
+        Err(_) => return Ok(Activation::NotManaged),
+    };
+    let proxy = proxy(&conn);
+
+    let res: Result<HomeRecord, dbus::Error> = proxy.method_call(MANAGER, "GetHomeByName", (user,));
+    let state = match res {
+        Ok((_, state, ..)) => state,
+        Err(e)
+            if matches!(
+                e.name(),
+                Some("org.freedesktop.home1.NoHomeForUser")
+                    | Some("org.freedesktop.DBus.Error.ServiceUnknown")
+            ) =>
+        {
+            return Ok(Activation::NotManaged)
+        }
+        Err(e) => return Err(format!("unable to look up home: {}", e).into()),
+    };
+
+    if state.starts_with("active") {
+        return Ok(Activation::AlreadyActive);
+    }
+
+    let password = password.ok_or("home is not active, and no password is available")?;
+    let mut secret = serde_json::json!({ "password": [password] }).to_string();
+    let res: Result<(), dbus::Error> =
+        proxy.method_call(MANAGER, "ActivateHome", (user, secret.as_str()));
+    secret.scramble();
+    res.map_err(|e| format!("unable to activate home: {}", e))?;
+
+    info!(user, "home activated");
+    Ok(Activation::Activated)
+}
+
+/// Deactivate the home of a user.
+pub fn deactivate(user: &str) -> Result<(), Error> {
+    let conn =
+        Connection::new_system().map_err(|e| format!("unable to connect to system bus: {}", e))?;
+    let res: Result<(), dbus::Error> = proxy(&conn).method_call(MANAGER, "DeactivateHome", (user,));
+    res.map_err(|e| format!("unable to deactivate home: {}", e))?;
+    info!(user, "home deactivated");
+    Ok(())
+}
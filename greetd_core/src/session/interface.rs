@@ -0,0 +1,431 @@
+use std::{
+    future::Future,
+    os::unix::{io::AsRawFd, net::UnixDatagram},
+    rc::Rc,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    sys::signal::Signal,
+    unistd::Pid,
+};
+
+use async_trait::async_trait;
+
+use tokio::net::UnixDatagram as TokioUnixDatagram;
+use tracing::{info_span, Span};
+
+use super::{
+    framing, proctree, spawn,
+    worker::{
+        AuthMessageType, ParentToSessionChild, SessionChildToParent, SessionClass, TerminalMode,
+    },
+};
+use crate::{
+    config::{ConfigCleanup, ConfigLimits, ConfigSmartcard, ConfigX11},
+    error::Error,
+    ipc, logging,
+    scrambler::Scrambler,
+};
+
+#[async_trait]
+trait AsyncSend {
+    async fn send(&self, sock: &TokioUnixDatagram) -> Result<(), Error>;
+}
+
+#[async_trait]
+impl<'a> AsyncSend for ParentToSessionChild<'a> {
+    async fn send(&self, sock: &TokioUnixDatagram) -> Result<(), Error> {
+        let mut out = serde_json::to_vec(self)
+            .map_err(|e| Error::ProtocolError(format!("unable to serialize message: {}", e)))?;
+        let res = framing::send_async(sock, &out).await;
+        out.scramble();
+        res
+    }
+}
+
+// Session IDs are only used to correlate log messages, and need only be
+// unique for the lifetime of the daemon.
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The ID that the next session will get.
+pub fn next_session_id() -> u64 {
+    NEXT_SESSION_ID.load(Ordering::Relaxed)
+}
+
+/// Continue numbering sessions from the given ID, such as after a re-exec.
+pub fn set_next_session_id(id: u64) {
+    NEXT_SESSION_ID.store(id, Ordering::Relaxed);
+}
+
+/// SessionChild tracks the processes spawned by a session
+pub struct SessionChild {
+    pub id: u64,
+    pub task: Pid,
+    pub sub_task: Pid,
+    pub span: Span,
+}
+
+impl SessionChild {
+    /// Send SIGTERM to the session child and all other processes of the
+    /// session.
+    pub fn term(&self) {
+        let _ = nix::sys::signal::kill(self.sub_task, Signal::SIGTERM);
+        proctree::signal_descendants(self.task, Signal::SIGTERM);
+    }
+
+    /// Send SIGKILL to all processes of the session, and then to the session
+    /// worker.
+    pub fn kill(&self) {
+        let _ = nix::sys::signal::kill(self.sub_task, Signal::SIGKILL);
+        // Processes may fork while we kill them, so make a few passes.
+        for _ in 0..3 {
+            if proctree::signal_descendants(self.task, Signal::SIGKILL) == 0 {
+                break;
+            }
+        }
+        let _ = nix::sys::signal::kill(self.task, Signal::SIGKILL);
+    }
+}
+
+#[derive(Debug)]
+pub enum SessionState {
+    Question(AuthMessageType, String),
+    PasswordChange,
+    Ready,
+}
+
+/// What sessions are started with that does not change between them.
+pub struct SessionSettings {
+    pub seat: String,
+    pub term_mode: TerminalMode,
+    pub source_profile: bool,
+    pub listener_path: String,
+    pub home_unlock_command: String,
+    pub cleanup: ConfigCleanup,
+    pub x11: Option<ConfigX11>,
+    pub smartcard: Option<ConfigSmartcard>,
+    pub greeter_limits: ConfigLimits,
+    pub user_limits: ConfigLimits,
+    pub fail_delay_ms: u32,
+}
+
+impl SessionSettings {
+    /// The limits that apply to sessions of the given class.
+    pub fn limits(&self, class: &SessionClass) -> &ConfigLimits {
+        match class {
+            SessionClass::Greeter => &self.greeter_limits,
+            SessionClass::User => &self.user_limits,
+        }
+    }
+}
+
+/// A device to initiate a logged in PAM session.
+pub struct Session {
+    id: u64,
+    span: Span,
+    task: Pid,
+    sock: Rc<TokioUnixDatagram>,
+    last_msg: Option<SessionChildToParent>,
+    // Whether the session worker is waiting for the answer to a prompt. This
+    // need not be the last message, as a PAM module may send further messages
+    // while a prompt is pending.
+    awaiting_answer: bool,
+    // The user the session was initiated for, which may be empty.
+    initiated_user: String,
+    // The user as determined by PAM, for sessions initiated without one.
+    user: Option<String>,
+    // A partially received message, kept across calls so that receiving can be
+    // cancelled without losing frames.
+    partial: Vec<u8>,
+}
+
+impl Session {
+    /// Create a session started as an external process.
+    pub fn new_external() -> Result<Session, Error> {
+        // Pipe used to communicate the true PID of the final child.
+        let (parentfd, childfd) =
+            UnixDatagram::pair().map_err(|e| Error::io("could not create pipe", e))?;
+
+        let raw_child = childfd.as_raw_fd();
+        let mut cur_flags = FdFlag::from_bits_retain(fcntl(raw_child, FcntlArg::F_GETFD)?);
+        cur_flags.remove(FdFlag::FD_CLOEXEC);
+        fcntl(raw_child, FcntlArg::F_SETFD(cur_flags))?;
+
+        let child = spawn::worker(raw_child)?;
+        parentfd.set_nonblocking(true)?;
+
+        let id = NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed);
+        Ok(Session {
+            id,
+            span: info_span!("session", id, worker = child.as_raw()),
+            task: child,
+            sock: Rc::new(TokioUnixDatagram::from_std(parentfd)?),
+            last_msg: None,
+            awaiting_answer: false,
+            initiated_user: String::new(),
+            user: None,
+            partial: Vec::new(),
+        })
+    }
+
+    /// The ID of this session.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The PID of the session worker.
+    pub fn pid(&self) -> Pid {
+        self.task
+    }
+
+    /// The span that log messages about this session should be emitted in.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    async fn send(&mut self, msg: &ParentToSessionChild<'_>) -> Result<(), Error> {
+        ipc::worker_request(&self.span, msg);
+        msg.send(&self.sock).await
+    }
+
+    // Receive a message from the session worker. This is cancellation safe.
+    async fn recv(&mut self) -> Result<SessionChildToParent, Error> {
+        let mut buf = [0; framing::DATAGRAM_SIZE];
+        loop {
+            let len = self
+                .sock
+                .recv(&mut buf)
+                .await
+                .map_err(|e| Error::io("unable to receive message", e))?;
+            if framing::reassemble(&mut self.partial, &buf[..len])? {
+                break;
+            }
+        }
+        buf.scramble();
+        let res = serde_json::from_slice(&self.partial)
+            .map_err(|e| Error::ProtocolError(format!("unable to deserialize message: {}", e)));
+        self.partial.scramble();
+        self.partial.clear();
+        let msg = res?;
+        ipc::worker_response(&self.span, &msg);
+        Ok(msg)
+    }
+
+    /// Kill the session worker. Used when the worker did not follow the
+    /// protocol, as it then cannot be trusted to honor a cancellation.
+    pub fn kill(&self) {
+        let _ = nix::sys::signal::kill(self.task, Signal::SIGKILL);
+    }
+
+    /// Initiates the session, which will cause authentication to begin.
+    pub async fn initiate(
+        &mut self,
+        service: &str,
+        class: SessionClass,
+        user: &str,
+        authenticate: bool,
+        settings: &SessionSettings,
+        locale: Option<&str>,
+    ) -> Result<(), Error> {
+        self.initiated_user = user.to_string();
+        let log_filter = logging::filter();
+        let limits = settings.limits(&class).clone();
+        let msg = ParentToSessionChild::InitiateLogin {
+            session_id: self.id,
+            log_filter: &log_filter,
+            service: service,
+            class: class,
+            user: user,
+            seat: &settings.seat,
+            authenticate,
+            tty: settings.term_mode.clone(),
+            source_profile: settings.source_profile,
+            listener_path: &settings.listener_path,
+            home_unlock_command: &settings.home_unlock_command,
+            cleanup: settings.cleanup.clone(),
+            x11: settings.x11.clone(),
+            smartcard: settings.smartcard.clone(),
+            limits,
+            fail_delay_ms: settings.fail_delay_ms,
+            locale,
+        };
+        self.send(&msg).await?;
+        Ok(())
+    }
+
+    /// Return the current state of this session. This is cancellation safe.
+    pub async fn get_state(&mut self) -> Result<SessionState, Error> {
+        let msg = match self.last_msg.take() {
+            Some(msg) => msg,
+            None => loop {
+                match self.recv().await? {
+                    SessionChildToParent::User(user) => self.user = Some(user),
+                    msg => {
+                        if let SessionChildToParent::PamMessage { style, .. } = &msg {
+                            self.awaiting_answer |= style.expects_response();
+                        }
+                        break msg;
+                    }
+                }
+            },
+        };
+
+        self.last_msg = Some(msg.clone());
+
+        match msg {
+            SessionChildToParent::PamMessage { style, msg } => {
+                Ok(SessionState::Question(style, msg))
+            }
+            SessionChildToParent::PasswordChangeRequired => Ok(SessionState::PasswordChange),
+            SessionChildToParent::Success => {
+                // Authentication completed while a prompt was pending, such
+                // as when a fingerprint was accepted while a password was
+                // asked for. Release the prompt, as it will not be answered.
+                if self.awaiting_answer {
+                    self.send(&ParentToSessionChild::PamResponse { resp: None })
+                        .await?;
+                    self.awaiting_answer = false;
+                }
+                Ok(SessionState::Ready)
+            }
+            SessionChildToParent::Error(e) => Err(e),
+            msg => Err(Error::ProtocolError(format!(
+                "expected PamMessage, PasswordChangeRequired, Success or Error from session worker, got: {:?}",
+                msg
+            ))),
+        }
+    }
+
+    /// Move on from the current message, and return the next state of this
+    /// session. A pending prompt is left pending. This is cancellation safe.
+    pub async fn next_state(&mut self) -> Result<SessionState, Error> {
+        if let Some(
+            SessionChildToParent::PamMessage { .. } | SessionChildToParent::PasswordChangeRequired,
+        ) = self.last_msg
+        {
+            self.last_msg = None;
+        }
+        self.get_state().await
+    }
+
+    /// Wait until the session worker has sent a message. The returned future
+    /// does not borrow the session, so that it can be awaited without holding
+    /// a lock on it.
+    pub fn readable(&self) -> impl Future<Output = Result<(), Error>> {
+        let sock = self.sock.clone();
+        async move { sock.readable().await.map_err(Error::from) }
+    }
+
+    /// The user as determined by PAM, if the session was initiated without
+    /// one and has been authenticated.
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
+    }
+
+    /// The user of the session, which is empty if the session was initiated
+    /// without one and PAM has yet to determine it.
+    pub fn username(&self) -> &str {
+        self.user.as_deref().unwrap_or(&self.initiated_user)
+    }
+
+    /// Whether the session worker is waiting for the answer to a prompt.
+    pub fn awaiting_answer(&self) -> bool {
+        self.awaiting_answer
+    }
+
+    /// Cancel the session.
+    pub async fn cancel(&mut self) -> Result<(), Error> {
+        self.last_msg = None;
+        self.awaiting_answer = false;
+        self.send(&ParentToSessionChild::Cancel).await?;
+        Ok(())
+    }
+
+    /// Send a response to an authentication question, or None to cancel the
+    /// authentication attempt. Informational and error messages, as well as
+    /// the notice that the password must be changed, need no response, so
+    /// responses to them are only used to move on to the next message.
+    pub async fn post_response(&mut self, mut answer: Option<String>) -> Result<(), Error> {
+        self.last_msg = None;
+        if !self.awaiting_answer {
+            if let Some(answer) = &mut answer {
+                answer.scramble();
+            }
+            return Ok(());
+        }
+        self.awaiting_answer = false;
+        let r = ParentToSessionChild::PamResponse { resp: answer };
+        self.send(&r).await?;
+        if let ParentToSessionChild::PamResponse {
+            resp: Some(mut resp),
+        } = r
+        {
+            resp.scramble();
+        }
+        Ok(())
+    }
+
+    ///
+    /// Send the arguments that will be used to start the session.
+    ///
+    pub async fn send_args(&mut self, cmd: Vec<String>, env: Vec<String>) -> Result<(), Error> {
+        let msg = ParentToSessionChild::Args { env, cmd };
+        self.send(&msg).await?;
+
+        let msg = self.recv().await?;
+
+        self.last_msg = Some(msg.clone());
+
+        match msg {
+            SessionChildToParent::Success => Ok(()),
+            SessionChildToParent::Error(e) => Err(e),
+            msg => Err(Error::ProtocolError(format!(
+                "expected Success or Error from session worker, got: {:?}",
+                msg
+            ))),
+        }
+    }
+
+    ///
+    /// Start the session. During a handover, the greeter is still running on
+    /// the terminal of the session.
+    ///
+    pub async fn start(&mut self, handover: bool) -> Result<SessionChild, Error> {
+        let msg = ParentToSessionChild::Start { handover };
+        self.send(&msg).await?;
+
+        let sub_task = loop {
+            match self.recv().await? {
+                SessionChildToParent::Error(e) => return Err(e),
+                SessionChildToParent::FinalChildPid(raw_pid) => {
+                    break Pid::from_raw(raw_pid as i32)
+                }
+                SessionChildToParent::PamMessage { style, .. } => {
+                    // pam_conv after start, ignore
+                    if style.expects_response() {
+                        self.send(&ParentToSessionChild::PamResponse { resp: None })
+                            .await?;
+                    }
+                    continue;
+                }
+                msg => {
+                    return Err(Error::ProtocolError(format!(
+                        "expected Error or FinalChildPid from session worker, got: {:?}",
+                        msg
+                    )))
+                }
+            };
+        };
+
+        self.sock.shutdown(std::net::Shutdown::Both)?;
+
+        Ok(SessionChild {
+            id: self.id,
+            task: self.task,
+            sub_task,
+            span: self.span.clone(),
+        })
+    }
+}
@@ -0,0 +1,65 @@
+//! Process attributes of sessions, such as their umask and resource limits,
+//! which are applied to the session process before it drops its privileges.
+
+use std::fs;
+
+use nix::{
+    errno::Errno,
+    sys::{
+        resource::{setrlimit, Resource},
+        stat::{umask, Mode},
+    },
+};
+
+use crate::{
+    config::{ConfigIoPriority, ConfigLimits, IoClass},
+    error::Error,
+};
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+fn ioprio_set(prio: &ConfigIoPriority) -> Result<(), Errno> {
+    let class = match prio.class {
+        IoClass::Realtime => 1,
+        IoClass::BestEffort => 2,
+        IoClass::Idle => 3,
+    };
+    let ioprio = class << IOPRIO_CLASS_SHIFT | prio.level as libc::c_int;
+    Errno::result(unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) })
+        .map(drop)
+}
+
+/// Set the OOM score adjustment of the current process, which is inherited by
+/// its children.
+pub fn set_oom_score_adj(value: i32) -> Result<(), Error> {
+    fs::write("/proc/self/oom_score_adj", value.to_string())
+        .map_err(|e| format!("unable to set OOM score adjustment: {}", e).into())
+}
+
+/// Apply the configured attributes to the current process, to be inherited
+/// by the session.
+pub fn apply(limits: &ConfigLimits) -> Result<(), Error> {
+    set_oom_score_adj(limits.oom_score_adj)?;
+    if let Some(mask) = limits.umask {
+        umask(Mode::from_bits_truncate(mask));
+    }
+    if let Some(nice) = limits.nice {
+        Errno::result(unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) })
+            .map_err(|e| format!("unable to set nice value: {}", e))?;
+    }
+    if let Some(prio) = &limits.ionice {
+        ioprio_set(prio).map_err(|e| format!("unable to set I/O priority: {}", e))?;
+    }
+    for (name, resource, limit) in [
+        ("nofile", Resource::RLIMIT_NOFILE, &limits.nofile),
+        ("core", Resource::RLIMIT_CORE, &limits.core),
+        ("memlock", Resource::RLIMIT_MEMLOCK, &limits.memlock),
+    ] {
+        if let Some(limit) = limit {
+            setrlimit(resource, limit.soft, limit.hard)
+                .map_err(|e| format!("unable to set {} limit: {}", name, e))?;
+        }
+    }
+    Ok(())
+}
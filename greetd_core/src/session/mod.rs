@@ -0,0 +1,18 @@
+pub(crate) mod cleanup;
+pub mod conv;
+mod framing;
+#[cfg(feature = "homed")]
+mod homed;
+pub mod interface;
+pub(crate) mod limits;
+mod prctl;
+mod proctree;
+mod sandbox;
+#[cfg(feature = "libseat")]
+mod seat;
+#[cfg(feature = "smartcard")]
+mod smartcard;
+pub(crate) mod spawn;
+mod unlock;
+pub mod worker;
+mod xorg;
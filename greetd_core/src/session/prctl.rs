@@ -0,0 +1,27 @@
+use nix::{errno::Errno, Result};
+
+pub const PRCTL_SET_PDEATHSIG: i32 = 1;
+pub const PRCTL_SET_CHILD_SUBREAPER: i32 = 36;
+pub const PRCTL_SET_NO_NEW_PRIVS: i32 = 38;
+
+#[allow(non_camel_case_types)]
+pub enum PrctlOption {
+    SET_PDEATHSIG(i32),
+    SET_CHILD_SUBREAPER(bool),
+    SET_NO_NEW_PRIVS,
+}
+
+pub fn prctl(option: PrctlOption) -> Result<()> {
+    Errno::result(match option {
+        PrctlOption::SET_PDEATHSIG(sig) => unsafe {
+            libc::prctl(PRCTL_SET_PDEATHSIG, sig, 0, 0, 0)
+        },
+        PrctlOption::SET_CHILD_SUBREAPER(enable) => unsafe {
+            libc::prctl(PRCTL_SET_CHILD_SUBREAPER, enable as libc::c_ulong, 0, 0, 0)
+        },
+        PrctlOption::SET_NO_NEW_PRIVS => unsafe {
+            libc::prctl(PRCTL_SET_NO_NEW_PRIVS, 1 as libc::c_ulong, 0, 0, 0)
+        },
+    })
+    .map(drop)
+}
@@ -0,0 +1,118 @@
+//! Discovery of the processes that make up a session.
+//!
+//! Session workers are child subreapers, so processes that are orphaned within
+//! a session, such as by a wrapper script that double-forks, are reparented to
+//! the worker rather than escaping to init. The whole session is therefore the
+//! process tree below its worker, which is found by walking /proc.
+
+use std::{collections::HashMap, fs};
+
+use nix::{sys::signal::Signal, unistd::Pid};
+
+// Parse the PID and parent PID from the contents of /proc/[pid]/stat. The
+// command name is enclosed in parentheses and may itself contain spaces and
+// parentheses, so fields are located relative to the last closing one.
+fn parse_stat(stat: &str) -> Option<(i32, i32)> {
+    let (pid, _) = stat.split_once(" (")?;
+    let rest = &stat[stat.rfind(')')? + 1..];
+    let mut fields = rest.split_whitespace();
+    let _state = fields.next()?;
+    let ppid = fields.next()?;
+    Some((pid.parse().ok()?, ppid.parse().ok()?))
+}
+
+// Find all descendants of root, given a list of (pid, ppid) pairs.
+fn descendants_of(procs: &[(i32, i32)], root: i32) -> Vec<i32> {
+    let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+    for &(pid, ppid) in procs {
+        children.entry(ppid).or_default().push(pid);
+    }
+
+    let mut res = Vec::new();
+    let mut queue = vec![root];
+    while let Some(pid) = queue.pop() {
+        if let Some(c) = children.get(&pid) {
+            for &child in c {
+                // Guard against cycles from PID reuse during the scan.
+                if child != root && !res.contains(&child) {
+                    res.push(child);
+                    queue.push(child);
+                }
+            }
+        }
+    }
+    res
+}
+
+/// Return the PIDs of all descendants of root. Processes that exit or are
+/// created during the scan may be missed.
+pub fn descendants(root: Pid) -> Vec<Pid> {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let procs: Vec<(i32, i32)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name();
+            let name = name.to_str()?;
+            if !name.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            parse_stat(&fs::read_to_string(entry.path().join("stat")).ok()?)
+        })
+        .collect();
+
+    descendants_of(&procs, root.as_raw())
+        .into_iter()
+        .map(Pid::from_raw)
+        .collect()
+}
+
+/// Send a signal to all descendants of root. Returns the number of processes
+/// signalled.
+pub fn signal_descendants(root: Pid, signal: Signal) -> usize {
+    let pids = descendants(root);
+    for pid in &pids {
+        let _ = nix::sys::signal::kill(*pid, signal);
+    }
+    pids.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat() {
+        assert_eq!(
+            parse_stat("1234 (sway) S 1200 1234 1234 1025 1234 4194560 0 0"),
+            Some((1234, 1200))
+        );
+        assert_eq!(
+            parse_stat("42 (a (weird) ) name) R 7 42 42 0 -1"),
+            Some((42, 7))
+        );
+        assert_eq!(parse_stat("42 (sway"), None);
+        assert_eq!(parse_stat(""), None);
+    }
+
+    #[test]
+    fn tree() {
+        let procs = [
+            (1, 0),
+            (100, 1),   // worker
+            (101, 100), // session leader
+            (102, 101),
+            (103, 102),
+            (104, 100), // orphan reparented to the worker
+            (200, 1),
+            (201, 200),
+        ];
+        let mut res = descendants_of(&procs, 100);
+        res.sort_unstable();
+        assert_eq!(res, vec![101, 102, 103, 104]);
+        assert!(descendants_of(&procs, 103).is_empty());
+        assert!(descendants_of(&procs, 999).is_empty());
+    }
+}
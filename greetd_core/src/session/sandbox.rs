@@ -0,0 +1,78 @@
+//! Hardening of greeter sessions, limiting what a compromised or misbehaving
+//! greeter can reach. Namespaces are set up in the session process while it is
+//! still privileged, and no_new_privs once it has dropped its privileges.
+
+use std::ffi::CStr;
+
+use nix::{
+    mount::{mount, MsFlags},
+    sched::{unshare, CloneFlags},
+    unistd::{getgrouplist, Gid, Group},
+};
+
+use super::prctl::{prctl, PrctlOption};
+use crate::{config::ConfigSandbox, error::Error};
+
+/// Return the supplementary groups of the user that the sandbox leaves the
+/// session with, or None if the groups are not restricted.
+pub fn groups(cfg: &ConfigSandbox, user: &CStr, gid: Gid) -> Result<Option<Vec<Gid>>, Error> {
+    let allowed = match &cfg.groups {
+        Some(allowed) => allowed,
+        None => return Ok(None),
+    };
+    let allowed = allowed
+        .iter()
+        .map(|name| {
+            Group::from_name(name)?
+                .map(|group| group.gid)
+                .ok_or_else(|| format!("group '{}' not found", name).into())
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let groups = getgrouplist(user, gid)
+        .map_err(|e| format!("unable to get groups: {}", e))?
+        .into_iter()
+        .filter(|group| *group == gid || allowed.contains(group))
+        .collect();
+    Ok(Some(groups))
+}
+
+/// Set up the namespaces of the sandbox for the current process, to be
+/// inherited by the session.
+pub fn enter(cfg: &ConfigSandbox) -> Result<(), Error> {
+    if cfg.private_network {
+        unshare(CloneFlags::CLONE_NEWNET)
+            .map_err(|e| format!("unable to create network namespace: {}", e))?;
+    }
+    if cfg.private_tmp {
+        unshare(CloneFlags::CLONE_NEWNS)
+            .map_err(|e| format!("unable to create mount namespace: {}", e))?;
+        // Keep our mounts from propagating back to the host.
+        mount(
+            None::<&str>,
+            "/",
+            None::<&str>,
+            MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+            None::<&str>,
+        )
+        .map_err(|e| format!("unable to make mounts private: {}", e))?;
+        mount(
+            Some("tmpfs"),
+            "/tmp",
+            Some("tmpfs"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+            Some("mode=1777"),
+        )
+        .map_err(|e| format!("unable to mount private /tmp: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Apply the parts of the sandbox that must wait until the process has
+/// dropped its privileges.
+pub fn restrict(cfg: &ConfigSandbox) -> Result<(), Error> {
+    if cfg.no_new_privs {
+        prctl(PrctlOption::SET_NO_NEW_PRIVS)
+            .map_err(|e| format!("unable to set no_new_privs: {}", e))?;
+    }
+    Ok(())
+}
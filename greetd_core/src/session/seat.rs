@@ -0,0 +1,51 @@
+//! Coordination of seat handover on systems using libseat, such as those
+//! running seatd.
+//!
+//! Only one session can have control of a seat at a time, and a compositor
+//! that starts while the seat is still held by the previous session may fail
+//! to open its devices. Before a session is started, greetd therefore takes
+//! control of the seat itself, which only succeeds once the seat has been
+//! released and the VT of the session is active, and then releases it again
+//! for the session. Sessions started by a handover skip this, as the greeter
+//! they replace keeps the seat until they are ready.
+
+use std::{
+    cell::Cell,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use libseat::{Seat, SeatEvent};
+use tracing::info;
+
+use crate::error::Error;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait for the seat to become available to the session.
+pub fn wait_for_seat() -> Result<(), Error> {
+    let enabled = Rc::new(Cell::new(false));
+    let listener = enabled.clone();
+    let mut seat = Seat::open(move |seat, event| match event {
+        SeatEvent::Enable => listener.set(true),
+        SeatEvent::Disable => {
+            listener.set(false);
+            let _ = seat.disable();
+        }
+    })
+    .map_err(|e| format!("unable to open seat: {}", e))?;
+
+    let deadline = Instant::now() + TIMEOUT;
+    while !enabled.get() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err("timed out waiting for seat".into());
+        }
+        seat.dispatch(remaining.as_millis() as i32)
+            .map_err(|e| format!("unable to dispatch seat events: {}", e))?;
+    }
+    info!(seat = seat.name(), "seat available");
+
+    // The seat is closed, and thereby released, when dropped.
+    Ok(())
+}
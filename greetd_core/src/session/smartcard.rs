@@ -0,0 +1,162 @@
+//! Reacting to the removal of the smartcard token used to log in.
+//!
+//! Tokens are recognized as USB interfaces of the smart card (CCID) class, as
+//! used by USB tokens and readers alike. The tokens that are present when the
+//! user authenticates are taken to be the ones used to log in, and the removal
+//! of any of them is detected through kernel uevents. Removing a card from a
+//! reader that stays connected is therefore not detected.
+
+use std::{
+    collections::HashSet,
+    fs,
+    os::unix::io::AsRawFd,
+    path::Path,
+    process::Command,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use nix::{
+    sys::{
+        signal::Signal,
+        socket::{recv, MsgFlags, SockFlag},
+        wait::WaitStatus,
+    },
+    unistd::{getpid, Pid},
+};
+use tracing::{error, info, warn, Span};
+
+use super::proctree;
+use crate::{
+    config::{ConfigSmartcard, SmartcardAction},
+    error::Error,
+    uevent,
+};
+
+const USB_DEVICES: &str = "/sys/bus/usb/devices";
+
+// The USB interface class of smart card readers.
+const CCID_CLASS: &str = "0b";
+
+/// Return the device paths of the smartcard tokens that are present, relative
+/// to /sys as in uevents.
+pub fn tokens() -> Vec<String> {
+    let entries = match fs::read_dir(USB_DEVICES) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let class = fs::read_to_string(path.join("bInterfaceClass")).ok()?;
+            if class.trim() != CCID_CLASS {
+                return None;
+            }
+            let path = fs::canonicalize(path).ok()?;
+            Some(path.strip_prefix("/sys").ok()?.to_str()?.to_string())
+        })
+        .collect()
+}
+
+/// The lock commands that are running. The session worker reaps every child
+/// of the session, lock commands included, so it is the one to report how
+/// they exited.
+#[derive(Clone, Default)]
+pub struct LockCommands(Arc<Mutex<HashSet<Pid>>>);
+
+impl LockCommands {
+    /// Report how a reaped child exited if it was a lock command.
+    pub fn reaped(&self, status: &WaitStatus) {
+        let pid = match status.pid() {
+            Some(pid) => pid,
+            None => return,
+        };
+        let mut pids = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        if !pids.remove(&pid) {
+            return;
+        }
+        match status {
+            WaitStatus::Exited(_, 0) => (),
+            WaitStatus::Exited(_, code) => warn!("lock command failed with exit code {}", code),
+            WaitStatus::Signaled(_, signal, _) => warn!("lock command killed by {}", signal),
+            _ => (),
+        }
+    }
+}
+
+fn run_action(
+    cfg: &ConfigSmartcard,
+    commands: &LockCommands,
+    session: Pid,
+    session_id: Option<&str>,
+) {
+    match cfg.action {
+        SmartcardAction::Lock => {
+            info!("smartcard removed, locking session");
+            // Held until the command is known, so that the session worker
+            // cannot reap it before then.
+            let mut pids = commands.0.lock().unwrap_or_else(|e| e.into_inner());
+            let res = Command::new("/bin/sh")
+                .arg("-c")
+                .arg(&cfg.lock_command)
+                .env("XDG_SESSION_ID", session_id.unwrap_or_default())
+                .spawn();
+            match res {
+                Ok(child) => {
+                    pids.insert(Pid::from_raw(child.id() as i32));
+                }
+                Err(e) => warn!("unable to run lock command: {}", e),
+            }
+        }
+        SmartcardAction::Terminate => {
+            info!("smartcard removed, terminating session");
+            let _ = nix::sys::signal::kill(session, Signal::SIGTERM);
+            proctree::signal_descendants(getpid(), Signal::SIGTERM);
+        }
+    }
+}
+
+/// Watch for the removal of any of the given tokens for the remainder of the
+/// session, running the configured action when one is removed. The session is
+/// given by its leader and its logind session ID, if any. Lock commands are
+/// added to the given set, and left for the session worker to reap.
+pub fn watch(
+    cfg: ConfigSmartcard,
+    commands: LockCommands,
+    tokens: Vec<String>,
+    session: Pid,
+    session_id: Option<String>,
+) -> Result<(), Error> {
+    let fd = uevent::open(uevent::GROUP_KERNEL, SockFlag::empty())?;
+    let span = Span::current();
+    thread::spawn(move || {
+        let _span = span.entered();
+
+        // A token may have been removed before we started listening.
+        if tokens
+            .iter()
+            .any(|t| !Path::new(&format!("/sys{}", t)).exists())
+        {
+            run_action(&cfg, &commands, session, session_id.as_deref());
+        }
+
+        let mut buf = [0; 8192];
+        loop {
+            let len = match recv(fd.as_raw_fd(), &mut buf, MsgFlags::empty()) {
+                Ok(len) => len,
+                Err(nix::errno::Errno::EINTR) | Err(nix::errno::Errno::ENOBUFS) => continue,
+                Err(e) => {
+                    error!("unable to receive uevent: {}", e);
+                    return;
+                }
+            };
+            match uevent::parse(&buf[..len]) {
+                Some(("remove", devpath)) if tokens.iter().any(|t| t == devpath) => {
+                    run_action(&cfg, &commands, session, session_id.as_deref())
+                }
+                _ => (),
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,201 @@
+//! Spawning of session workers.
+//!
+//! Workers are started with clone3(2), which can place them directly into the
+//! configured worker cgroup, so that they never run in the cgroup of greetd
+//! itself. They then execute greetd through a file descriptor opened once at
+//! startup, which saves resolving the path of the binary for every worker and
+//! keeps workers on the same binary as the daemon if it is replaced on disk.
+//! Kernels without clone3 get a plain fork.
+
+use std::{
+    env,
+    ffi::CString,
+    fs::{self, File},
+    io::ErrorKind,
+    os::unix::{
+        ffi::OsStrExt,
+        fs::OpenOptionsExt,
+        io::{AsRawFd, OwnedFd, RawFd},
+    },
+    path::Path,
+    ptr,
+    sync::OnceLock,
+};
+
+use nix::{
+    errno::Errno,
+    unistd::{fork, ForkResult, Pid},
+};
+use tracing::{info, warn};
+
+use crate::error::Error;
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+// Not yet in libc.
+const CLONE_INTO_CGROUP: u64 = 0x200000000;
+
+// struct clone_args of the kernel, as of the version that added
+// CLONE_INTO_CGROUP.
+#[repr(C)]
+#[derive(Default)]
+struct CloneArgs {
+    flags: u64,
+    pidfd: u64,
+    child_tid: u64,
+    parent_tid: u64,
+    exit_signal: u64,
+    stack: u64,
+    stack_size: u64,
+    tls: u64,
+    set_tid: u64,
+    set_tid_size: u64,
+    cgroup: u64,
+}
+
+struct Launcher {
+    exe: OwnedFd,
+    name: CString,
+    cgroup: Option<OwnedFd>,
+}
+
+static LAUNCHER: OnceLock<Launcher> = OnceLock::new();
+
+// Open the cgroup with the given path below the cgroup root, creating it if
+// it does not exist.
+fn open_cgroup(path: &str) -> Result<OwnedFd, Error> {
+    let path = Path::new(CGROUP_ROOT).join(path.trim_start_matches('/'));
+    match fs::create_dir(&path) {
+        Ok(()) => info!("created worker cgroup {}", path.display()),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+        Err(e) => return Err(format!("unable to create {}: {}", path.display(), e).into()),
+    }
+    File::options()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY | libc::O_CLOEXEC)
+        .open(&path)
+        .map(OwnedFd::from)
+        .map_err(|e| format!("unable to open {}: {}", path.display(), e).into())
+}
+
+impl Launcher {
+    fn new(cgroup: &str) -> Result<Launcher, Error> {
+        let exe = File::options()
+            .read(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open("/proc/self/exe")
+            .map_err(|e| format!("unable to open own executable: {}", e))?;
+        let name = CString::new(env::current_exe()?.as_os_str().as_bytes())?;
+        let cgroup = match cgroup {
+            "" => None,
+            path => match open_cgroup(path) {
+                Ok(fd) => Some(fd),
+                Err(e) => {
+                    warn!("starting workers in the cgroup of greetd: {}", e);
+                    None
+                }
+            },
+        };
+        Ok(Launcher {
+            exe: exe.into(),
+            name,
+            cgroup,
+        })
+    }
+
+    // Like fork, but into the given cgroup. Returns None in the child.
+    fn clone3(&self, cgroup: Option<RawFd>) -> Result<Option<Pid>, Errno> {
+        let mut args = CloneArgs {
+            exit_signal: libc::SIGCHLD as u64,
+            ..Default::default()
+        };
+        if let Some(fd) = cgroup {
+            args.flags |= CLONE_INTO_CGROUP;
+            args.cgroup = fd as u64;
+        }
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_clone3,
+                &args as *const CloneArgs,
+                std::mem::size_of::<CloneArgs>(),
+            )
+        };
+        match Errno::result(res)? {
+            0 => Ok(None),
+            pid => Ok(Some(Pid::from_raw(pid as libc::pid_t))),
+        }
+    }
+
+    fn spawn(&self) -> Result<Option<Pid>, Error> {
+        let cgroup = self.cgroup.as_ref().map(|fd| fd.as_raw_fd());
+        let res = match self.clone3(cgroup) {
+            Err(e) if e != Errno::ENOSYS && cgroup.is_some() => {
+                warn!("unable to start worker in its cgroup: {}", e);
+                self.clone3(None)
+            }
+            res => res,
+        };
+        match res {
+            Ok(res) => Ok(res),
+            Err(Errno::ENOSYS) => match unsafe { fork() } {
+                Ok(ForkResult::Parent { child }) => Ok(Some(child)),
+                Ok(ForkResult::Child) => Ok(None),
+                Err(e) => Err(format!("unable to fork: {}", e).into()),
+            },
+            Err(e) => Err(format!("unable to clone: {}", e).into()),
+        }
+    }
+}
+
+fn launcher() -> Result<&'static Launcher, Error> {
+    if let Some(launcher) = LAUNCHER.get() {
+        return Ok(launcher);
+    }
+    let launcher = Launcher::new("")?;
+    Ok(LAUNCHER.get_or_init(|| launcher))
+}
+
+/// Prepare to spawn workers into the given cgroup, given by its path below
+/// /sys/fs/cgroup, or into the cgroup of greetd if empty. Must be called before
+/// the first worker is spawned.
+pub fn init(cgroup: &str) -> Result<(), Error> {
+    LAUNCHER
+        .set(Launcher::new(cgroup)?)
+        .map_err(|_| "workers have already been spawned".into())
+}
+
+/// Spawn a session worker that talks over the given socket, which must not be
+/// closed on exec.
+pub fn worker(sock: RawFd) -> Result<Pid, Error> {
+    let launcher = launcher()?;
+
+    // Everything is prepared ahead, as the child may not allocate.
+    let args = [
+        launcher.name.clone(),
+        CString::new("--session-worker")?,
+        CString::new(format!("{}", sock as usize))?,
+    ];
+    let env = env::vars_os()
+        .map(|(k, v)| CString::new([k.as_bytes(), b"=", v.as_bytes()].concat()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut argv: Vec<_> = args.iter().map(|s| s.as_ptr()).collect();
+    argv.push(ptr::null());
+    let mut envp: Vec<_> = env.iter().map(|s| s.as_ptr()).collect();
+    envp.push(ptr::null());
+    let empty = CString::default();
+
+    match launcher.spawn()? {
+        Some(child) => Ok(child),
+        None => unsafe {
+            libc::syscall(
+                libc::SYS_execveat,
+                launcher.exe.as_raw_fd(),
+                empty.as_ptr(),
+                argv.as_ptr(),
+                envp.as_ptr(),
+                libc::AT_EMPTY_PATH,
+            );
+            libc::_exit(1)
+        },
+    }
+}
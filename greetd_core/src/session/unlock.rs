@@ -0,0 +1,78 @@
+//! Unlocking of encrypted home directories by an external helper, such as
+//! fscrypt or cryptsetup, before the session is opened.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use nix::unistd::User;
+use tracing::info;
+
+use crate::error::Error;
+
+/// Run the home unlock command for a user as root, writing the password to
+/// its standard input. The password is written without a trailing newline,
+/// after which the pipe is closed. Should the command fail, what it wrote to
+/// its standard error is part of the error.
+pub fn run(command: &str, user: &User, password: &str) -> Result<(), Error> {
+    let mut child = Command::new("/bin/sh")
+        .arg("-c")
+        .arg(command)
+        .env("USER", &user.name)
+        .env("HOME", &user.dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("unable to run home unlock command: {}", e))?;
+
+    // The pipe is closed when stdin is dropped. A command that exits without
+    // reading the password is not an error in itself, so the exit status is
+    // what decides the outcome. The password fits in the pipe, so writing it
+    // cannot block on a command that writes to stderr first.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(password.as_bytes());
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("unable to wait for home unlock command: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.trim() {
+            "" => format!("home unlock command failed: {}", output.status),
+            stderr => format!("home unlock command failed: {}: {}", output.status, stderr),
+        }
+        .into());
+    }
+    info!(user = %user.name, "home unlocked");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::unistd::getuid;
+
+    #[test]
+    fn failure() {
+        let user = User::from_uid(getuid()).unwrap().unwrap();
+        assert!(run(
+            "read -r password; test \"$password\" = secret",
+            &user,
+            "secret"
+        )
+        .is_ok());
+        let err = run("echo 'no key for $HOME' >&2; exit 1", &user, "secret").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "home unlock command failed: exit status: 1: no key for $HOME"
+        );
+        let err = run("exit 2", &user, "secret").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "home unlock command failed: exit status: 2"
+        );
+    }
+}
@@ -0,0 +1,616 @@
+use std::{env, ffi::CString, os::unix::net::UnixDatagram, sync::Mutex};
+
+use nix::{
+    sys::wait::{waitpid, WaitStatus},
+    unistd::{execve, fork, initgroups, setgid, setgroups, setsid, setuid, ForkResult},
+};
+use pam_sys::{PamFlag, PamItemType};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, info_span, warn, Span};
+
+#[cfg(feature = "homed")]
+use super::homed;
+#[cfg(feature = "libseat")]
+use super::seat;
+#[cfg(feature = "smartcard")]
+use super::smartcard;
+use super::{
+    cleanup,
+    conv::SessionConv,
+    framing, limits,
+    prctl::{prctl, PrctlOption},
+    sandbox, unlock, xorg,
+};
+use crate::{
+    config::{ConfigCleanup, ConfigLimits, ConfigSmartcard, ConfigX11},
+    error::Error,
+    ipc, logging,
+    pam::{session::PamSession, PamError},
+    scrambler::Scrambler,
+    terminal,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AuthMessageType {
+    Visible,
+    Secret,
+    Info,
+    Error,
+}
+
+impl AuthMessageType {
+    /// Whether the session worker waits for a response to messages of this
+    /// type. Informational and error messages are not answered.
+    pub fn expects_response(&self) -> bool {
+        matches!(self, AuthMessageType::Visible | AuthMessageType::Secret)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TerminalMode {
+    Terminal {
+        path: String,
+        vt: usize,
+        switch: bool,
+    },
+    Stdin,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SessionClass {
+    Greeter,
+    User,
+}
+
+impl SessionClass {
+    fn as_str(&self) -> &str {
+        match self {
+            SessionClass::Greeter => "greeter",
+            SessionClass::User => "user",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
+pub enum ParentToSessionChild<'a> {
+    InitiateLogin {
+        session_id: u64,
+        log_filter: &'a str,
+        service: &'a str,
+        class: SessionClass,
+        user: &'a str,
+        seat: &'a str,
+        authenticate: bool,
+        tty: TerminalMode,
+        source_profile: bool,
+        listener_path: &'a str,
+        home_unlock_command: &'a str,
+        cleanup: ConfigCleanup,
+        x11: Option<ConfigX11>,
+        smartcard: Option<ConfigSmartcard>,
+        limits: ConfigLimits,
+        fail_delay_ms: u32,
+        locale: Option<&'a str>,
+    },
+    PamResponse {
+        resp: Option<String>,
+    },
+    Args {
+        env: Vec<String>,
+        cmd: Vec<String>,
+    },
+    Start {
+        /// Whether the session takes over from a greeter that is still
+        /// running on the terminal, which is then left as it is.
+        handover: bool,
+    },
+    Cancel,
+}
+
+impl<'a> ParentToSessionChild<'a> {
+    pub fn recv(
+        sock: &UnixDatagram,
+        data: &'a mut Vec<u8>,
+    ) -> Result<ParentToSessionChild<'a>, Error> {
+        framing::recv(sock, data)?;
+        let msg = serde_json::from_slice(data)?;
+        ipc::worker_request(&Span::current(), &msg);
+        Ok(msg)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SessionChildToParent {
+    Success,
+    Error(Error),
+    PamMessage { style: AuthMessageType, msg: String },
+    User(String),
+    PasswordChangeRequired,
+    FinalChildPid(u64),
+}
+
+impl SessionChildToParent {
+    pub fn send(&self, sock: &UnixDatagram) -> Result<(), Error> {
+        ipc::worker_response(&Span::current(), self);
+        let mut out = serde_json::to_vec(self)?;
+        let res = framing::send(sock, &out);
+        out.scramble();
+        res
+    }
+}
+
+// Set the locale of the worker, which PAM modules translate their messages to.
+// The variables are set as well, as gettext prefers LANGUAGE over the locale.
+fn set_locale(locale: &str) {
+    env::set_var("LC_ALL", locale);
+    env::set_var("LANG", locale);
+    env::remove_var("LANGUAGE");
+    let empty = CString::default();
+    if unsafe { libc::setlocale(libc::LC_ALL, empty.as_ptr()) }.is_null() {
+        warn!(locale, "locale is not available");
+    }
+}
+
+/// The entry point for the session worker process. The session worker is
+/// responsible for the entirety of the session setup and execution. It is
+/// started by Session::start.
+fn worker(sock: &UnixDatagram) -> Result<i32, Error> {
+    let mut data = Vec::new();
+    let (
+        session_id,
+        service,
+        class,
+        user,
+        seat,
+        authenticate,
+        tty,
+        source_profile,
+        listener_path,
+        home_unlock_command,
+        cleanup,
+        x11,
+        smartcard,
+        limits,
+        fail_delay_ms,
+        locale,
+    ) = match ParentToSessionChild::recv(sock, &mut data)? {
+        ParentToSessionChild::InitiateLogin {
+            session_id,
+            log_filter,
+            service,
+            class,
+            user,
+            seat,
+            authenticate,
+            tty,
+            source_profile,
+            listener_path,
+            home_unlock_command,
+            cleanup,
+            x11,
+            smartcard,
+            limits,
+            fail_delay_ms,
+            locale,
+        } => {
+            // The worker does not read the configuration, so it gets its
+            // log filter from the parent.
+            if let Err(e) = logging::set_filter(log_filter) {
+                warn!("unable to set log filter: {}", e);
+            }
+            (
+                session_id,
+                service,
+                class,
+                user,
+                seat,
+                authenticate,
+                tty,
+                source_profile,
+                listener_path,
+                // Copied, as the buffer it borrows from is reused below.
+                home_unlock_command.to_string(),
+                cleanup,
+                x11,
+                smartcard,
+                limits,
+                fail_delay_ms,
+                // Copied, as the buffer it borrows from is reused below.
+                locale.map(str::to_string),
+            )
+        }
+        ParentToSessionChild::Cancel => return Err("cancelled".into()),
+        msg => return Err(format!("expected InitiateLogin or Cancel, got: {:?}", msg).into()),
+    };
+
+    let _span = info_span!("session", id = session_id).entered();
+
+    // Have PAM talk to the user in their language.
+    if let Some(locale) = &locale {
+        set_locale(locale);
+    }
+
+    // Held while receiving from the parent, as a PAM module may have left a
+    // prompt waiting for its answer when authentication completes. The parent
+    // answers such prompts on its own once it learns of the outcome.
+    let recv_lock = Mutex::new(());
+    let conv = Box::pin(SessionConv::new(sock, &recv_lock));
+    // Without a user, PAM determines it during authentication, such as by
+    // mapping a smartcard to a user or by asking for it.
+    let mut pam = PamSession::start(service, Some(user).filter(|u| !u.is_empty()), conv)?;
+
+    if authenticate {
+        if fail_delay_ms > 0 {
+            pam.fail_delay(fail_delay_ms.saturating_mul(1000))?;
+        }
+        pam.authenticate(PamFlag::NONE)?;
+        info!(user, service, "authenticated");
+    }
+
+    // The smartcard tokens present now are taken to be the ones used to
+    // authenticate.
+    #[cfg(feature = "smartcard")]
+    let tokens = match &smartcard {
+        Some(_) if authenticate => smartcard::tokens(),
+        _ => Vec::new(),
+    };
+    #[cfg(not(feature = "smartcard"))]
+    if smartcard.is_some() {
+        warn!("smartcard support is not enabled, ignoring smartcard configuration");
+    }
+    // An expired password is changed as part of the login, with the prompts
+    // for the new password going through the conversation as usual.
+    match pam.acct_mgmt(PamFlag::NONE) {
+        Err(PamError::PasswordExpired(_)) => {
+            info!("password expired, changing it");
+            SessionChildToParent::PasswordChangeRequired.send(sock)?;
+            pam.chauthtok(PamFlag::CHANGE_EXPIRED_AUTHTOK)?;
+        }
+        res => res?,
+    }
+
+    // Not the credentials you think.
+    pam.setcred(PamFlag::ESTABLISH_CRED)?;
+
+    // Tell the parent who was authenticated if it did not know.
+    if user.is_empty() {
+        let user = pam.get_user()?;
+        info!(%user, "user determined by PAM");
+        SessionChildToParent::User(user).send(sock)?;
+    }
+
+    // Mark authentication as a success.
+    SessionChildToParent::Success.send(sock)?;
+
+    // Add GREETD_SOCK if this is a greeter session - we do this early as we are about to reuse the
+    // buffer, invalidating our borrow.
+    if let SessionClass::Greeter = class {
+        pam.putenv(&format!("GREETD_SOCK={}", &listener_path))?;
+    }
+
+    pam.putenv(&format!("XDG_SEAT={}", seat))?;
+
+    let recv_guard = recv_lock
+        .lock()
+        .map_err(|_| "PAM conversation lock poisoned")?;
+
+    // Fetch our arguments from the parent.
+    let (env, cmd) = match ParentToSessionChild::recv(sock, &mut data)? {
+        ParentToSessionChild::Args { env, cmd } => (env, cmd),
+        ParentToSessionChild::Cancel => return Err("cancelled".into()),
+        msg => return Err(format!("expected Args or Cancel, got: {:?}", msg).into()),
+    };
+
+    SessionChildToParent::Success.send(sock)?;
+
+    // Await start request from our parent.
+    let handover = match ParentToSessionChild::recv(sock, &mut data)? {
+        ParentToSessionChild::Start { handover } => handover,
+        ParentToSessionChild::Cancel => return Err("cancelled".into()),
+        msg => return Err(format!("expected Start or Cancel, got: {:?}", msg).into()),
+    };
+    drop(recv_guard);
+
+    let pam_username = pam.get_user()?;
+
+    let user = nix::unistd::User::from_name(&pam_username)?.ok_or("unable to get user info")?;
+
+    // Make this process a session leader.
+    setsid().map_err(|e| format!("unable to become session leader: {}", e))?;
+
+    let vt = match &tty {
+        TerminalMode::Terminal { vt, .. } => Some(*vt),
+        TerminalMode::Stdin => None,
+    };
+    match tty {
+        TerminalMode::Stdin => (),
+        TerminalMode::Terminal { path, vt, switch } => {
+            // Tell PAM what TTY we're targetting, which is used by logind.
+            pam.set_item(PamItemType::TTY, &format!("tty{}", vt))?;
+            pam.putenv(&format!("XDG_VTNR={}", vt))?;
+
+            // Opening our target terminal.
+            let target_term = terminal::Terminal::open(&path)?;
+
+            // The greeter is still showing on the terminal during a handover,
+            // and is left alone until the session replaces it.
+            if !handover {
+                // Set the target VT mode to text for compatibility. Other login managers
+                // set this to graphics, but that disallows start of textual applications,
+                // which greetd aims to support.
+                target_term.kd_setmode(terminal::KdMode::Text)?;
+
+                // Clear TTY so that it will be empty when we switch to it.
+                target_term.term_clear()?;
+            }
+
+            // A bit more work if a VT switch is required.
+            if switch && vt != target_term.vt_get_current()? {
+                // Perform a switch to the target VT, simultaneously resetting it to
+                // VT_AUTO.
+                target_term.vt_setactivate(vt)?;
+            }
+
+            // Connect std(in|out|err), and make this our controlling TTY.
+            target_term.term_connect_pipes()?;
+            target_term.term_take_ctty()?;
+        }
+    }
+
+    // PAM has to be provided a bunch of environment variables before
+    // open_session. We pass any environment variables from our greeter
+    // through here as well. This allows them to affect PAM (more
+    // specifically, pam_systemd.so), as well as make it easier to gather
+    // and set all environment variables later.
+    let prepared_env = [
+        format!("XDG_SESSION_CLASS={}", class.as_str()),
+        format!("USER={}", user.name),
+        format!("LOGNAME={}", user.name),
+        format!("HOME={}", user.dir.to_string_lossy()),
+        format!("SHELL={}", user.shell.to_string_lossy()),
+        format!(
+            "TERM={}",
+            env::var("TERM").unwrap_or_else(|_| "linux".to_string())
+        ),
+    ];
+    for e in env.iter().chain(prepared_env.iter()) {
+        pam.putenv(e)?;
+    }
+
+    // Unlock the home of the user if needed. This needs the password given
+    // during authentication, which PAM keeps as the authentication token.
+    let mut password = pam.get_authtok()?;
+
+    // Activate the home of the user if it is managed by systemd-homed.
+    #[cfg(feature = "homed")]
+    let home = homed::activate(&pam_username, password.as_deref());
+
+    let unlocked = match (home_unlock_command.as_str(), &password) {
+        ("", _) => Ok(()),
+        (command, Some(password)) => unlock::run(command, &user, password),
+        (_, None) => {
+            info!("no password available, not running home unlock command");
+            Ok(())
+        }
+    };
+    if let Some(password) = &mut password {
+        password.scramble();
+    }
+    #[cfg(feature = "homed")]
+    let home = home?;
+    unlocked?;
+
+    // Session time!
+    pam.open_session(PamFlag::NONE)?;
+    info!(user = %user.name, class = class.as_str(), "session opened");
+
+    // We are done with PAM, clear variables that the child will not need.
+    _ = pam.putenv(&"XDG_SESSION_CLASS");
+
+    // Prepare some strings in C format that we'll need.
+    let cusername = CString::new(user.name.as_str())?;
+    let command = if source_profile {
+        format!(
+            "[ -f /etc/profile ] && . /etc/profile; [ -f $HOME/.profile ] && . $HOME/.profile; exec {}",
+            cmd.join(" ")
+        )
+    } else {
+        format!("exec {}", cmd.join(" "))
+    };
+
+    // Extract PAM environment for use with execve below.
+    let pamenvlist = pam.getenvlist()?;
+    let mut envvec = pamenvlist.to_vec();
+    let runtime_dir = envvec.iter().find_map(|e| {
+        e.to_str()
+            .ok()?
+            .strip_prefix("XDG_RUNTIME_DIR=")
+            .map(str::to_string)
+    });
+    #[cfg(feature = "smartcard")]
+    let session_id = envvec.iter().find_map(|e| {
+        e.to_str()
+            .ok()?
+            .strip_prefix("XDG_SESSION_ID=")
+            .map(str::to_string)
+    });
+
+    // Become a subreaper, so that processes orphaned within the session are
+    // reparented to us rather than escaping to init. This keeps the whole
+    // session in our process tree, where greetd can find it.
+    prctl(PrctlOption::SET_CHILD_SUBREAPER(true))
+        .map_err(|e| format!("unable to become subreaper: {}", e))?;
+
+    // PAM is weird and gets upset if you exec from the process that opened
+    // the session, registering it automatically as a log-out. Thus, we must
+    // exec in a new child.
+    // Make sure that the previous session has released the seat before the
+    // devices of this one are opened. During a handover, the greeter keeps
+    // the seat until the session replaces it, so there is nothing to wait for.
+    #[cfg(feature = "libseat")]
+    if !handover {
+        seat::wait_for_seat()?;
+    }
+
+    // X11 sessions are run on an X server started for them, unless the X
+    // server is left to the session itself.
+    let x11_session = env.iter().any(|e| e == "XDG_SESSION_TYPE=x11");
+    let mut xserver = match &x11 {
+        Some(cfg) if x11_session => Some(xorg::XServer::start(
+            cfg,
+            &user,
+            vt,
+            runtime_dir.as_deref(),
+            &envvec,
+        )?),
+        _ => None,
+    };
+    let xenv = xserver
+        .iter()
+        .flat_map(|x| x.env())
+        .map(CString::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    envvec.extend(xenv.iter().map(|e| e.as_c_str()));
+
+    let groups = match &limits.sandbox {
+        Some(cfg) => sandbox::groups(cfg, &cusername, user.gid)?,
+        None => None,
+    };
+
+    let child = match unsafe { fork() }.map_err(|e| format!("unable to fork: {}", e))? {
+        ForkResult::Parent { child, .. } => child,
+        ForkResult::Child => {
+            // It is important that we do *not* return from here by
+            // accidentally using '?'. The process *must* exit from within
+            // this match arm.
+
+            // Apply the limits of the session class, while we are still
+            // privileged enough to raise them.
+            limits::apply(&limits).expect("unable to apply session limits");
+            if let Some(cfg) = &limits.sandbox {
+                sandbox::enter(cfg).expect("unable to enter sandbox");
+            }
+
+            // Drop privileges to target user
+            match &groups {
+                Some(groups) => setgroups(groups).expect("unable to set groups"),
+                None => initgroups(&cusername, user.gid).expect("unable to init groups"),
+            }
+            setgid(user.gid).expect("unable to set GID");
+            setuid(user.uid).expect("unable to set UID");
+            if let Some(cfg) = &limits.sandbox {
+                sandbox::restrict(cfg).expect("unable to restrict session");
+            }
+
+            // Set our parent death signal. setuid/setgid above resets the
+            // death signal, which is why we do this here.
+            prctl(PrctlOption::SET_PDEATHSIG(libc::SIGTERM)).expect("unable to set death signal");
+
+            // Change working directory
+            if let Err(e) = env::set_current_dir(user.dir) {
+                warn!("unable to set working directory: {}", e);
+            }
+
+            // Run
+            let cpath = CString::new("/bin/sh").unwrap();
+            execve(
+                &cpath,
+                &[
+                    &cpath,
+                    &CString::new("-c").unwrap(),
+                    &CString::new(command).unwrap(),
+                ],
+                &envvec,
+            )
+            .expect("unable to exec");
+
+            unreachable!("after exec");
+        }
+    };
+
+    // Signal the inner PID to the parent process.
+    SessionChildToParent::FinalChildPid(child.as_raw() as u64).send(sock)?;
+    sock.shutdown(std::net::Shutdown::Both)?;
+
+    // Set our parent death signal. setsid above resets the signal, hence our
+    // late assignment, which is why we do this here.
+    prctl(PrctlOption::SET_PDEATHSIG(libc::SIGTERM))?;
+
+    #[cfg(feature = "smartcard")]
+    let lock_commands = smartcard::LockCommands::default();
+    #[cfg(feature = "smartcard")]
+    if let (Some(cfg), false) = (smartcard, tokens.is_empty()) {
+        info!(?tokens, "watching smartcard tokens");
+        if let Err(e) = smartcard::watch(cfg, lock_commands.clone(), tokens, child, session_id) {
+            warn!("unable to watch smartcard tokens: {}", e);
+        }
+    }
+
+    // Wait for process to terminate, handling EINTR as necessary. Orphans and
+    // lock commands that exit in the meantime are reaped along the way.
+    let mut exit_code = 0;
+    loop {
+        match waitpid(None, None) {
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => {
+                error!("waitpid on inner child failed: {}", e);
+                break;
+            }
+            Ok(status) if status.pid() == Some(child) => {
+                exit_code = match status {
+                    WaitStatus::Exited(_, code) => code,
+                    WaitStatus::Signaled(_, signal, _) => 128 + signal as i32,
+                    _ => 0,
+                };
+                break;
+            }
+            Ok(status @ (WaitStatus::Exited(..) | WaitStatus::Signaled(..))) => {
+                #[cfg(feature = "smartcard")]
+                lock_commands.reaped(&status);
+                if let (Some(xserver), Some(pid)) = (&mut xserver, status.pid()) {
+                    xserver.reaped(pid);
+                }
+            }
+            Ok(_) => continue,
+        }
+    }
+
+    // Remove runtime files left behind by the session. This is done before
+    // the session is closed, as the runtime directory may be removed then.
+    if let Some(xserver) = xserver {
+        xserver.stop();
+    }
+    cleanup::run(&cleanup, &user, runtime_dir.as_deref());
+
+    // Close the session. This step requires root privileges to run, as it
+    // will result in various forms of login teardown (including unmounting
+    // home folders, telling logind that the session ended, etc.). This is
+    // why we cannot drop privileges in this process, but must do it in the
+    // inner-most child.
+    pam.close_session(PamFlag::NONE)?;
+    pam.setcred(PamFlag::DELETE_CRED)?;
+    pam.end()?;
+    info!(exit_code, "session closed");
+
+    #[cfg(feature = "homed")]
+    if home == homed::Activation::Activated {
+        if let Err(e) = homed::deactivate(&pam_username) {
+            warn!("{}", e);
+        }
+    }
+
+    Ok(exit_code)
+}
+
+/// Run the session worker, returning the exit code of the session for the
+/// worker to exit with, so that greetd learns how the session ended.
+pub fn main(sock: &UnixDatagram) -> Result<i32, Error> {
+    match worker(sock) {
+        Ok(code) => Ok(code),
+        Err(e) => {
+            SessionChildToParent::Error(e.clone()).send(sock)?;
+            Err(e)
+        }
+    }
+}
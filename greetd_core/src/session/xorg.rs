@@ -0,0 +1,331 @@
+//! Management of the X server for X11 sessions.
+//!
+//! The X server is started as the user on the VT of the session, much like
+//! startx would, and the session command is only run once the server is ready
+//! to accept connections. The X server signals this by sending SIGUSR1 to its
+//! parent if it inherited SIGUSR1 as ignored. A server that does not become
+//! ready within the configured timeout is killed.
+//!
+//! Access to the X server is controlled with an MIT-MAGIC-COOKIE-1 cookie,
+//! which is written to an authority file only readable by the user.
+
+use std::{
+    convert::TryFrom,
+    ffi::CString,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::{Path, PathBuf},
+    ptr,
+    time::{Duration, Instant},
+};
+
+use nix::{
+    errno::Errno,
+    sys::{
+        signal::{kill, signal, sigprocmask, SigHandler, SigSet, SigmaskHow, Signal},
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{execve, fchown, fork, initgroups, setgid, setuid, ForkResult, Pid, User},
+};
+use tracing::{error, info, warn};
+
+use crate::{config::ConfigX11, error::Error, scrambler::Scrambler};
+
+// The family of authority entries that match any address.
+const FAMILY_WILD: u16 = 0xffff;
+const COOKIE_NAME: &str = "MIT-MAGIC-COOKIE-1";
+
+// How long to wait for SIGCHLD at a time while waiting for the X server to
+// exit. Other threads of the session worker may take the signal instead, so
+// the server is checked on regularly.
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A running X server.
+pub struct XServer {
+    pid: Pid,
+    display: u32,
+    xauthority: PathBuf,
+    // How long the server may take to start, and to exit once terminated.
+    timeout: Duration,
+    // Whether the server has exited and been reaped, after which its PID may
+    // belong to another process.
+    reaped: bool,
+}
+
+// Wait for one of the signals in the blocked mask until the deadline,
+// returning None on timeout.
+fn wait_signal(mask: &SigSet, deadline: Instant) -> Result<Option<Signal>, Errno> {
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let timeout = libc::timespec {
+            tv_sec: left.as_secs() as libc::time_t,
+            tv_nsec: left.subsec_nanos() as libc::c_long,
+        };
+        let res = unsafe { libc::sigtimedwait(mask.as_ref(), ptr::null_mut(), &timeout) };
+        match Errno::result(res) {
+            Ok(signal) => return Signal::try_from(signal).map(Some),
+            Err(Errno::EINTR) => continue,
+            Err(Errno::EAGAIN) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// Encode an authority file entry granting access to the display with the
+// cookie, as read by libXau.
+fn xauth_entry(display: u32, cookie: &[u8]) -> Vec<u8> {
+    let mut entry = Vec::new();
+    entry.extend_from_slice(&FAMILY_WILD.to_be_bytes());
+    let number = display.to_string();
+    for field in [&b""[..], number.as_bytes(), COOKIE_NAME.as_bytes(), cookie] {
+        entry.extend_from_slice(&(field.len() as u16).to_be_bytes());
+        entry.extend_from_slice(field);
+    }
+    entry
+}
+
+// Write a new authority file for the display, owned by and only accessible to
+// the user. The file is in a directory the user can write to, so it is created
+// without following symlinks and given to the user through the open file,
+// leaving no window for it to be swapped for a link to another file.
+fn write_xauthority(path: &Path, display: u32, user: &User) -> Result<(), Error> {
+    let mut cookie = [0u8; 16];
+    File::open("/dev/urandom")
+        .and_then(|mut f| f.read_exact(&mut cookie))
+        .map_err(|e| format!("unable to generate X cookie: {}", e))?;
+    let mut entry = xauth_entry(display, &cookie);
+    cookie.scramble();
+
+    let _ = fs::remove_file(path);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_CLOEXEC)
+        .open(path)
+        .map_err(|e| format!("unable to create X authority file: {}", e))?;
+    fchown(file.as_raw_fd(), Some(user.uid), Some(user.gid))
+        .map_err(|e| format!("unable to chown X authority file: {}", e))?;
+    let res = file.write_all(&entry);
+    entry.scramble();
+    res.map_err(|e| format!("unable to write X authority file: {}", e).into())
+}
+
+// Find a display number that is not in use, judging by its lock file.
+fn free_display() -> Option<u32> {
+    (0..64).find(|n| !Path::new(&format!("/tmp/.X{}-lock", n)).exists())
+}
+
+// Build the command line to run the X server with.
+fn command_line(cfg: &ConfigX11, display: u32, vt: Option<usize>, xauthority: &Path) -> String {
+    let mut cmd = format!(
+        "exec {} :{} -auth {}",
+        cfg.server_command,
+        display,
+        xauthority.display()
+    );
+    if let Some(vt) = vt {
+        cmd.push_str(&format!(" vt{}", vt));
+    }
+    cmd
+}
+
+impl XServer {
+    /// Start the X server as the given user and wait for it to become ready.
+    /// The authority file is written to the runtime directory of the user, or
+    /// their home directory if there is none.
+    pub fn start(
+        cfg: &ConfigX11,
+        user: &User,
+        vt: Option<usize>,
+        runtime_dir: Option<&str>,
+        env: &[&std::ffi::CStr],
+    ) -> Result<XServer, Error> {
+        let display = free_display().ok_or("no free X display")?;
+        let xauthority = match runtime_dir {
+            Some(dir) => Path::new(dir).join("Xauthority"),
+            None => user.dir.join(".Xauthority"),
+        };
+        write_xauthority(&xauthority, display, user)?;
+        let command = CString::new(command_line(cfg, display, vt, &xauthority))?;
+        let cusername = CString::new(user.name.as_str())?;
+
+        // Block the signals we wait for, so that they are not lost if they
+        // arrive before we start waiting.
+        let mut mask = SigSet::empty();
+        mask.add(Signal::SIGUSR1);
+        mask.add(Signal::SIGCHLD);
+        let mut old_mask = SigSet::empty();
+        sigprocmask(SigmaskHow::SIG_BLOCK, Some(&mask), Some(&mut old_mask))?;
+
+        let pid = match unsafe { fork() }.map_err(|e| format!("unable to fork: {}", e))? {
+            ForkResult::Parent { child, .. } => child,
+            ForkResult::Child => {
+                // As for the session itself, the process must not return
+                // from here.
+                initgroups(&cusername, user.gid).expect("unable to init groups");
+                setgid(user.gid).expect("unable to set GID");
+                setuid(user.uid).expect("unable to set UID");
+
+                // An ignored SIGUSR1 is what asks the server to signal
+                // readiness.
+                unsafe { signal(Signal::SIGUSR1, SigHandler::SigIgn) }
+                    .expect("unable to ignore SIGUSR1");
+                sigprocmask(SigmaskHow::SIG_SETMASK, Some(&old_mask), None)
+                    .expect("unable to restore signal mask");
+
+                let cpath = CString::new("/bin/sh").unwrap();
+                let e = execve(
+                    &cpath,
+                    &[&cpath, &CString::new("-c").unwrap(), &command],
+                    env,
+                )
+                .unwrap_err();
+                error!("unable to exec X server: {}", e);
+                unsafe { libc::_exit(1) }
+            }
+        };
+
+        let mut xserver = XServer {
+            pid,
+            display,
+            xauthority,
+            timeout: Duration::from_secs(cfg.timeout),
+            reaped: false,
+        };
+        let deadline = Instant::now() + xserver.timeout;
+        let res = loop {
+            match wait_signal(&mask, deadline) {
+                Ok(Some(Signal::SIGUSR1)) => break Ok(()),
+                Ok(Some(_)) => match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                    Ok(WaitStatus::StillAlive) => continue,
+                    Ok(status) => {
+                        xserver.reaped = true;
+                        break Err(format!("X server exited: {:?}", status));
+                    }
+                    Err(e) => break Err(format!("unable to wait for X server: {}", e)),
+                },
+                Ok(None) => break Err(format!("X server not ready after {} seconds", cfg.timeout)),
+                Err(e) => break Err(format!("unable to wait for X server: {}", e)),
+            }
+        };
+        sigprocmask(SigmaskHow::SIG_SETMASK, Some(&old_mask), None)?;
+
+        if let Err(e) = res {
+            // A server that is not ready may not react to SIGTERM.
+            xserver.terminate(Signal::SIGKILL);
+            return Err(e.into());
+        }
+        info!(display = xserver.display, "X server ready");
+        Ok(xserver)
+    }
+
+    /// The DISPLAY and XAUTHORITY variables for the session.
+    pub fn env(&self) -> Vec<String> {
+        vec![
+            format!("DISPLAY=:{}", self.display),
+            format!("XAUTHORITY={}", self.xauthority.display()),
+        ]
+    }
+
+    /// Record that the given child process has been reaped, such as by the
+    /// loop that waits for the session, in case it was the X server.
+    pub fn reaped(&mut self, pid: Pid) {
+        if pid == self.pid {
+            warn!(display = self.display, "X server exited during the session");
+            self.reaped = true;
+        }
+    }
+
+    /// Terminate the X server and wait for it to exit, killing it if it does
+    /// not exit in time.
+    pub fn stop(self) {
+        self.terminate(Signal::SIGTERM);
+    }
+
+    // Wait for the X server to exit until the deadline, returning whether it
+    // is gone.
+    fn wait_exit(&self, deadline: Instant) -> bool {
+        let mut mask = SigSet::empty();
+        mask.add(Signal::SIGCHLD);
+        let mut old_mask = SigSet::empty();
+        if let Err(e) = sigprocmask(SigmaskHow::SIG_BLOCK, Some(&mask), Some(&mut old_mask)) {
+            warn!("unable to block SIGCHLD: {}", e);
+        }
+        let exited = loop {
+            match waitpid(self.pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::StillAlive) if Instant::now() >= deadline => break false,
+                Ok(WaitStatus::StillAlive) => {
+                    let _ = wait_signal(&mask, deadline.min(Instant::now() + EXIT_POLL_INTERVAL));
+                }
+                Err(Errno::EINTR) => continue,
+                Err(e) => {
+                    warn!("unable to wait for X server: {}", e);
+                    break true;
+                }
+                Ok(_) => break true,
+            }
+        };
+        let _ = sigprocmask(SigmaskHow::SIG_SETMASK, Some(&old_mask), None);
+        exited
+    }
+
+    fn terminate(self, signal: Signal) {
+        if !self.reaped {
+            let _ = kill(self.pid, signal);
+            if !self.wait_exit(Instant::now() + self.timeout) {
+                warn!(
+                    display = self.display,
+                    "X server did not exit after {} seconds, killing it",
+                    self.timeout.as_secs()
+                );
+                let _ = kill(self.pid, Signal::SIGKILL);
+                loop {
+                    match waitpid(self.pid, None) {
+                        Err(Errno::EINTR) => continue,
+                        Err(e) => {
+                            warn!("unable to wait for X server: {}", e);
+                            break;
+                        }
+                        Ok(_) => break,
+                    }
+                }
+            }
+        }
+        let _ = fs::remove_file(&self.xauthority);
+        info!(display = self.display, "X server stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command() {
+        let cfg = ConfigX11 {
+            server_command: "/usr/bin/Xorg -nolisten tcp".to_string(),
+            timeout: 30,
+        };
+        let xauthority = Path::new("/run/user/1000/Xauthority");
+        assert_eq!(
+            command_line(&cfg, 1, Some(7), xauthority),
+            "exec /usr/bin/Xorg -nolisten tcp :1 -auth /run/user/1000/Xauthority vt7"
+        );
+        assert_eq!(
+            command_line(&cfg, 0, None, xauthority),
+            "exec /usr/bin/Xorg -nolisten tcp :0 -auth /run/user/1000/Xauthority"
+        );
+    }
+
+    #[test]
+    fn xauth() {
+        let entry = xauth_entry(12, &[0xaa; 16]);
+        let mut expected = vec![0xff, 0xff, 0, 0, 0, 2, b'1', b'2', 0, 18];
+        expected.extend_from_slice(b"MIT-MAGIC-COOKIE-1");
+        expected.extend_from_slice(&[0, 16]);
+        expected.extend_from_slice(&[0xaa; 16]);
+        assert_eq!(entry, expected);
+    }
+}
@@ -0,0 +1,60 @@
+//! Kernel uevents, which announce devices as they are added and removed. These
+//! are the events that udev acts upon, received without depending on udev.
+
+use std::os::unix::io::{AsRawFd, OwnedFd};
+
+use nix::sys::socket::{
+    bind, socket, AddressFamily, NetlinkAddr, SockFlag, SockProtocol, SockType,
+};
+
+use crate::error::Error;
+
+/// The multicast group of uevents sent by the kernel.
+pub const GROUP_KERNEL: u32 = 1;
+
+/// The multicast group of uevents sent by udev once it has processed them,
+/// by which time the device nodes and symlinks it manages exist.
+pub const GROUP_UDEV: u32 = 2;
+
+/// Open a socket that receives uevents of the given groups.
+pub fn open(groups: u32, flags: SockFlag) -> Result<OwnedFd, Error> {
+    let fd = socket(
+        AddressFamily::Netlink,
+        SockType::Datagram,
+        SockFlag::SOCK_CLOEXEC | flags,
+        SockProtocol::NetlinkKObjectUEvent,
+    )
+    .map_err(|e| format!("unable to create uevent socket: {}", e))?;
+    bind(fd.as_raw_fd(), &NetlinkAddr::new(0, groups))
+        .map_err(|e| format!("unable to bind uevent socket: {}", e))?;
+    Ok(fd)
+}
+
+/// Parse the action and device path from a kernel uevent, which starts with
+/// "action@devpath", followed by NUL-separated properties.
+#[cfg_attr(not(feature = "smartcard"), allow(dead_code))]
+pub fn parse(msg: &[u8]) -> Option<(&str, &str)> {
+    let header = msg.split(|&b| b == 0).next()?;
+    std::str::from_utf8(header).ok()?.split_once('@')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uevent() {
+        assert_eq!(
+            parse(
+                b"remove@/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0\0ACTION=remove\0SUBSYSTEM=usb\0"
+            ),
+            Some(("remove", "/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0"))
+        );
+        assert_eq!(
+            parse(b"add@/devices/virtual/net/lo"),
+            Some(("add", "/devices/virtual/net/lo"))
+        );
+        assert_eq!(parse(b"libudev\0\xfe\xed"), None);
+        assert_eq!(parse(b""), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Re-executing greetd in place, such as after a package upgrade, without
+//! ending the sessions it supervises.
+//!
+//! The PID of greetd stays the same across execve, so its session workers
+//! remain its children. The new process is told about them, along with the
+//! listening sockets it inherits, through the environment. Sessions that are
+//! still being set up talk to greetd over a socket that does not survive the
+//! re-exec, so greetd refuses to re-exec while there are any.
+
+use std::{convert::Infallible, env, ffi::CString, os::unix::io::RawFd, time::Duration};
+
+use nix::{
+    fcntl::{fcntl, FcntlArg, FdFlag},
+    unistd::execv,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, history::Sessions, session::worker::TerminalMode};
+use greetd_ipc::Metrics;
+
+const STATE_VAR: &str = "GREETD_UPGRADE_STATE";
+
+/// A running session carried across a re-exec.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedSession {
+    pub id: u64,
+    pub task: i32,
+    pub sub_task: i32,
+    pub is_greeter: bool,
+    /// How long the session has been running.
+    pub age: Duration,
+}
+
+/// Everything the new process needs to take over from the old one.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct State {
+    pub listener: RawFd,
+    pub listener_path: String,
+    pub admin_listener: Option<RawFd>,
+    pub vsock_listener: Option<RawFd>,
+    pub term_mode: TerminalMode,
+    pub next_session_id: u64,
+    pub current: Option<SavedSession>,
+    pub metrics: Metrics,
+    pub history: Sessions,
+}
+
+impl State {
+    fn fds(&self) -> impl Iterator<Item = RawFd> {
+        std::iter::once(self.listener)
+            .chain(self.admin_listener)
+            .chain(self.vsock_listener)
+    }
+}
+
+fn set_cloexec(fd: RawFd, cloexec: bool) -> Result<(), Error> {
+    let mut flags = FdFlag::from_bits_retain(fcntl(fd, FcntlArg::F_GETFD)?);
+    flags.set(FdFlag::FD_CLOEXEC, cloexec);
+    fcntl(fd, FcntlArg::F_SETFD(flags))?;
+    Ok(())
+}
+
+/// Take the state left by the process that re-executed us, if any. It is
+/// removed from the environment so that sessions do not inherit it.
+pub fn take() -> Result<Option<State>, Error> {
+    let state = match env::var(STATE_VAR) {
+        Ok(state) => state,
+        Err(_) => return Ok(None),
+    };
+    env::remove_var(STATE_VAR);
+    let state: State = serde_json::from_str(&state)
+        .map_err(|e| format!("unable to parse upgrade state: {}", e))?;
+    for fd in state.fds() {
+        set_cloexec(fd, true)?;
+    }
+    Ok(Some(state))
+}
+
+// The path of the binary we were started from. If it was replaced since, such
+// as by a package upgrade, /proc/self/exe refers to the deleted file.
+fn executable() -> Result<CString, Error> {
+    let exe = env::current_exe()?;
+    let exe = exe.to_str().ok_or("executable path is not valid UTF-8")?;
+    Ok(CString::new(exe.strip_suffix(" (deleted)").unwrap_or(exe))?)
+}
+
+fn try_exec(state: &State) -> Result<Infallible, Error> {
+    let exe = executable()?;
+    let args = env::args()
+        .map(CString::new)
+        .collect::<Result<Vec<_>, _>>()?;
+    let encoded = serde_json::to_string(state)?;
+    for fd in state.fds() {
+        set_cloexec(fd, false)?;
+    }
+    env::set_var(STATE_VAR, encoded);
+    Ok(execv(&exe, &args).map_err(|e| format!("unable to exec: {}", e))?)
+}
+
+/// Re-execute greetd, handing over the given state. This only returns if the
+/// re-exec failed, in which case greetd carries on as before.
+pub fn exec(state: &State) -> Error {
+    let err = match try_exec(state) {
+        Ok(never) => match never {},
+        Err(err) => err,
+    };
+    env::remove_var(STATE_VAR);
+    for fd in state.fds() {
+        let _ = set_cloexec(fd, true);
+    }
+    err
+}
@@ -0,0 +1,91 @@
+//! The default sessions that users choose for themselves.
+//!
+//! A user may name their default session in ~/.config/greetd/session, either
+//! by the ID of a session desktop entry such as "sway", or as a command line.
+//! Otherwise, the session recorded by AccountsService is used, which is always
+//! the ID of a desktop entry.
+
+use std::{
+    fs::File,
+    io::Read,
+    os::unix::fs::{MetadataExt, OpenOptionsExt},
+    path::Path,
+};
+
+use greetd_ipc::{desktop, UserSession};
+use nix::unistd::User;
+
+use crate::accounts;
+
+const USER_FILE: &str = ".config/greetd/session";
+
+// The file has no business being larger than this.
+const MAX_SIZE: u64 = 4096;
+
+// Return the first line that is neither empty nor a comment.
+fn parse_user_file(contents: &str) -> Option<&str> {
+    contents
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+// Read a small file. The file is not followed if it is a symlink, and must be
+// owned by the given UID, as the contents of the file end up in a command line
+// that is visible to the user.
+fn read_file(path: &Path, owner: u32) -> Option<String> {
+    let file = File::options()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+        .ok()?;
+    let meta = file.metadata().ok()?;
+    if !meta.is_file() || meta.uid() != owner {
+        return None;
+    }
+    let mut contents = String::new();
+    file.take(MAX_SIZE).read_to_string(&mut contents).ok()?;
+    Some(contents)
+}
+
+fn resolve(session: &str, session_dirs: &str, from_user: bool) -> Option<UserSession> {
+    match desktop::find(session_dirs, session).filter(|e| e.installed()) {
+        Some(entry) => Some(UserSession {
+            cmd: vec![entry.command_line()],
+            desktop_id: Some(entry.id),
+            name: Some(entry.name),
+        }),
+        // AccountsService only records desktop entries.
+        None if from_user => Some(UserSession {
+            cmd: vec![session.to_string()],
+            desktop_id: None,
+            name: None,
+        }),
+        None => None,
+    }
+}
+
+/// Return the default session of the given user, if they have chosen one.
+pub fn find(user: &User, session_dirs: &str) -> Option<UserSession> {
+    if let Some(contents) = read_file(&user.dir.join(USER_FILE), user.uid.as_raw()) {
+        if let Some(session) = parse_user_file(&contents) {
+            return resolve(session, session_dirs, true);
+        }
+    }
+    resolve(&accounts::read(&user.name).session?, session_dirs, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_file() {
+        assert_eq!(parse_user_file("sway\n"), Some("sway"));
+        assert_eq!(
+            parse_user_file("# My session\n\n  startx /usr/bin/i3  \nignored\n"),
+            Some("startx /usr/bin/i3")
+        );
+        assert_eq!(parse_user_file("# nothing\n\n"), None);
+    }
+}
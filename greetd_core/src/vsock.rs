@@ -0,0 +1,87 @@
+//! Listening for greeters on an AF_VSOCK socket, for greeters that run in a
+//! virtual machine or otherwise cannot reach the greeter socket.
+//!
+//! tokio has no vsock socket types. A connected vsock stream socket supports
+//! the same I/O operations as a connected unix stream socket, so accepted
+//! connections are handed to the client handler as a UnixStream. Address and
+//! credential queries on it do not apply, and are not used.
+
+use std::os::unix::{
+    io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    net::UnixStream as StdUnixStream,
+};
+
+use nix::sys::socket::{
+    accept4, bind, getpeername, listen, socket, AddressFamily, SockFlag, SockType, VsockAddr,
+};
+use tokio::{io::unix::AsyncFd, net::UnixStream};
+
+use crate::error::Error;
+
+pub struct VsockListener {
+    fd: AsyncFd<OwnedFd>,
+}
+
+impl VsockListener {
+    /// Listen on the given port for connections from any CID.
+    pub fn bind(port: u32) -> Result<VsockListener, Error> {
+        let fd = socket(
+            AddressFamily::Vsock,
+            SockType::Stream,
+            SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .map_err(|e| format!("unable to create vsock socket: {}", e))?;
+        bind(fd.as_raw_fd(), &VsockAddr::new(libc::VMADDR_CID_ANY, port))
+            .map_err(|e| format!("unable to bind vsock port {}: {}", port, e))?;
+        listen(&fd, 16).map_err(|e| format!("unable to listen on vsock: {}", e))?;
+        Ok(VsockListener {
+            fd: AsyncFd::new(fd)?,
+        })
+    }
+
+    /// Take over a listening vsock socket, such as one inherited across a
+    /// re-exec.
+    pub fn from_raw_fd(fd: RawFd) -> Result<VsockListener, Error> {
+        Ok(VsockListener {
+            fd: AsyncFd::new(unsafe { OwnedFd::from_raw_fd(fd) })?,
+        })
+    }
+
+    /// The file descriptor of the listening socket.
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.fd.get_ref().as_raw_fd()
+    }
+
+    /// Accept a connection, returning it along with the CID of the peer.
+    pub async fn accept(&self) -> Result<(UnixStream, u32), Error> {
+        loop {
+            let mut guard = self.fd.readable().await?;
+            match guard.try_io(|fd| {
+                accept4(
+                    fd.as_raw_fd(),
+                    SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC,
+                )
+                .map_err(std::io::Error::from)
+            }) {
+                Ok(res) => {
+                    let fd = unsafe { OwnedFd::from_raw_fd(res?) };
+                    let cid = getpeername::<VsockAddr>(fd.as_raw_fd())
+                        .map_err(|e| format!("unable to get vsock peer: {}", e))?
+                        .cid();
+                    let stream = UnixStream::from_std(StdUnixStream::from(fd))?;
+                    return Ok((stream, cid));
+                }
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Accept a connection if listening, and wait forever otherwise.
+    pub async fn accept_opt(listener: &Option<VsockListener>) -> Result<(UnixStream, u32), Error> {
+        match listener {
+            Some(listener) => listener.accept().await,
+            None => std::future::pending().await,
+        }
+    }
+}
@@ -10,7 +10,12 @@
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     let mut stream = UnixStream::connect(env::var("GREETD_SOCK")?)?;
-//!     Request::CreateSession { username: "john".to_string() }.write_to(&mut stream)?;
+//!     Request::CreateSession {
+//!         username: "john".to_string(),
+//!         concurrent_auth: false,
+//!         locale: None,
+//!     }
+//!     .write_to(&mut stream)?;
 //!     let resp = Response::read_from(&mut stream)?;
 //!     Ok(())
 //! }
@@ -11,7 +11,12 @@
 //! #[tokio::main(flavor = "current_thread")]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
 //!     let mut stream = UnixStream::connect(env::var("GREETD_SOCK")?).await?;
-//!     Request::CreateSession { username: "john".to_string() }.write_to(&mut stream).await?;
+//!     Request::CreateSession {
+//!         username: "john".to_string(),
+//!         concurrent_auth: false,
+//!         locale: None,
+//!     }
+//!     .write_to(&mut stream).await?;
 //!     let resp = Response::read_from(&mut stream).await?;
 //!     Ok(())
 //! }
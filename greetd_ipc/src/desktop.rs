@@ -0,0 +1,455 @@
+//! Discovery and parsing of session desktop entries.
+//!
+//! Session desktop entries, such as those found in
+//! `/usr/share/wayland-sessions`, describe the sessions that a greeter can
+//! offer. This module implements the subset of the [Desktop Entry
+//! Specification](https://specifications.freedesktop.org/desktop-entry-spec/latest/)
+//! that applies to them, including the quoting rules of the `Exec` key, so
+//! that greeters do not need to implement these themselves.
+//!
+//! ```no_run
+//! use greetd_ipc::desktop::{entries, DEFAULT_SESSION_DIRS};
+//!
+//! for entry in entries(DEFAULT_SESSION_DIRS) {
+//!     if !entry.no_display && entry.installed() {
+//!         println!("{}: {}", entry.name, entry.command_line());
+//!     }
+//! }
+//! ```
+use std::{collections::HashSet, env, fmt, fs, os::unix::fs::PermissionsExt, path::Path};
+
+/// The default locations to look for session desktop entries in.
+pub const DEFAULT_SESSION_DIRS: &str = "/usr/share/wayland-sessions:/usr/share/xsessions";
+
+/// An error encountered while parsing a desktop entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<&str> for ParseError {
+    fn from(error: &str) -> Self {
+        ParseError(error.to_string())
+    }
+}
+
+/// A session desktop entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// The desktop file ID, which is the file name without the `.desktop`
+    /// extension, such as "sway".
+    pub id: String,
+
+    /// The name of the session. Falls back to the ID if not set.
+    pub name: String,
+
+    /// A description of the session, if any.
+    pub comment: Option<String>,
+
+    /// The command to start the session with, split into arguments with
+    /// quoting and field codes removed.
+    pub exec: Vec<String>,
+
+    /// A program that must be installed for the session to be usable.
+    pub try_exec: Option<String>,
+
+    /// The names of the desktop, as used for `XDG_CURRENT_DESKTOP`.
+    pub desktop_names: Vec<String>,
+
+    /// Whether the session should be hidden from session menus.
+    pub no_display: bool,
+
+    /// The session type, as used for `XDG_SESSION_TYPE`, if it is known from
+    /// the directory the entry was found in.
+    pub session_type: Option<&'static str>,
+}
+
+// Unescape a value of the string type, as described in "Possible value
+// types".
+fn unescape_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some(c) => {
+                // Other escapes, such as those of Exec quoting, are left
+                // for later stages.
+                out.push('\\');
+                out.push(c);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// Remove field codes from an argument. Sessions are started without any files
+// or URLs, so an argument that is only a field code is dropped entirely.
+fn strip_field_codes(arg: &str) -> Option<String> {
+    let mut chars = arg.chars();
+    if let (Some('%'), Some(c), None) = (chars.next(), chars.next(), chars.next()) {
+        if c != '%' {
+            return None;
+        }
+    }
+    let mut out = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                if let Some('%') = chars.next() {
+                    out.push('%');
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+/// Split an unescaped Exec value into arguments, following the quoting rules
+/// of the specification, and remove field codes.
+pub fn split_exec(exec: &str) -> Result<Vec<String>, ParseError> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => {
+                if let Some(arg) = arg.take() {
+                    args.extend(strip_field_codes(&arg));
+                }
+            }
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '`' | '$' | '\\')) => arg.push(c),
+                            Some(c) => {
+                                arg.push('\\');
+                                arg.push(c);
+                            }
+                            None => return Err("unterminated escape in Exec".into()),
+                        },
+                        Some(c) => arg.push(c),
+                        None => return Err("unterminated quote in Exec".into()),
+                    }
+                }
+            }
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(arg) = arg {
+        args.extend(strip_field_codes(&arg));
+    }
+    Ok(args)
+}
+
+// Quote an argument for the shell, if needed.
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(m) => m.is_file() && m.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+// Check whether a program exists, either as an absolute path or in PATH.
+fn find_program(program: &str) -> bool {
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+    env::var("PATH")
+        .unwrap_or_else(|_| "/usr/local/bin:/usr/bin:/bin".to_string())
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .any(|d| is_executable(&Path::new(d).join(program)))
+}
+
+// Guess the session type from the directory an entry was found in.
+fn session_type(dir: &str) -> Option<&'static str> {
+    match Path::new(dir).file_name()?.to_str()? {
+        "wayland-sessions" => Some("wayland"),
+        "xsessions" => Some("x11"),
+        _ => None,
+    }
+}
+
+impl DesktopEntry {
+    /// Parse the contents of a desktop entry file. Returns None if the entry
+    /// is hidden, which means that it is to be treated as deleted.
+    pub fn parse(id: &str, contents: &str) -> Result<Option<DesktopEntry>, ParseError> {
+        let mut in_group = false;
+        let mut entry = DesktopEntry {
+            id: id.to_string(),
+            name: String::new(),
+            comment: None,
+            exec: Vec::new(),
+            try_exec: None,
+            desktop_names: Vec::new(),
+            no_display: false,
+            session_type: None,
+        };
+        let mut has_exec = false;
+        for line in contents.lines() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('[') {
+                in_group = line.trim_end() == "[Desktop Entry]";
+                continue;
+            }
+            if !in_group {
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), unescape_value(value.trim())),
+                None => return Err("expected key=value".into()),
+            };
+            match key {
+                "Name" => entry.name = value,
+                "Comment" => entry.comment = Some(value),
+                "Exec" => {
+                    entry.exec = split_exec(&value)?;
+                    has_exec = true;
+                }
+                "TryExec" if !value.is_empty() => entry.try_exec = Some(value),
+                "DesktopNames" => {
+                    entry.desktop_names = value
+                        .split(';')
+                        .filter(|s| !s.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                "NoDisplay" => entry.no_display = value == "true",
+                "Hidden" if value == "true" => return Ok(None),
+                _ => (),
+            }
+        }
+        if !has_exec {
+            return Err("no Exec key".into());
+        }
+        if entry.exec.is_empty() {
+            return Err("empty Exec key".into());
+        }
+        if entry.name.is_empty() {
+            entry.name = entry.id.clone();
+        }
+        Ok(Some(entry))
+    }
+
+    /// Whether the program named by TryExec, if any, is installed.
+    pub fn installed(&self) -> bool {
+        match &self.try_exec {
+            Some(program) => find_program(program),
+            None => true,
+        }
+    }
+
+    /// The command as a shell command line, with arguments quoted as needed.
+    /// This is suitable for Request::StartSession, as greetd runs session
+    /// commands with a shell.
+    pub fn command_line(&self) -> String {
+        self.exec
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    /// The environment describing this session to the session itself, such
+    /// as `XDG_SESSION_TYPE` and `XDG_CURRENT_DESKTOP`.
+    pub fn env(&self) -> Vec<String> {
+        let mut env = Vec::new();
+        if let Some(session_type) = self.session_type {
+            env.push(format!("XDG_SESSION_TYPE={}", session_type));
+        }
+        env.push(format!("XDG_SESSION_DESKTOP={}", self.id));
+        if !self.desktop_names.is_empty() {
+            env.push(format!(
+                "XDG_CURRENT_DESKTOP={}",
+                self.desktop_names.join(":")
+            ));
+        }
+        env
+    }
+}
+
+/// Enumerate the session desktop entries in the colon-separated list of
+/// directories. Entries in earlier directories take precedence over entries
+/// with the same ID in later directories. Entries that cannot be parsed are
+/// skipped.
+pub fn entries(dirs: &str) -> Vec<DesktopEntry> {
+    let mut seen = HashSet::new();
+    let mut res = Vec::new();
+    for dir in dirs.split(':').filter(|d| !d.is_empty()) {
+        let mut paths: Vec<_> = match fs::read_dir(dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+            Err(_) => continue,
+        };
+        paths.sort();
+        for path in paths {
+            let id = match (path.file_stem(), path.extension()) {
+                (Some(id), Some(ext)) if ext == "desktop" => id.to_string_lossy().to_string(),
+                _ => continue,
+            };
+            if seen.contains(&id) {
+                continue;
+            }
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(_) => continue,
+            };
+            match DesktopEntry::parse(&id, &contents) {
+                Ok(Some(mut entry)) => {
+                    entry.session_type = session_type(dir);
+                    seen.insert(id);
+                    res.push(entry);
+                }
+                // A hidden entry hides entries with the same ID in later
+                // directories.
+                Ok(None) => {
+                    seen.insert(id);
+                }
+                Err(_) => continue,
+            }
+        }
+    }
+    res
+}
+
+/// Find the session desktop entry with the given ID, such as "sway".
+pub fn find(dirs: &str, id: &str) -> Option<DesktopEntry> {
+    entries(dirs).into_iter().find(|e| e.id == id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exec() {
+        assert_eq!(split_exec("sway").unwrap(), vec!["sway"]);
+        assert_eq!(
+            split_exec("startplasma-wayland %U").unwrap(),
+            vec!["startplasma-wayland"]
+        );
+        assert_eq!(
+            split_exec("app --arg=%f --other").unwrap(),
+            vec!["app", "--arg=", "--other"]
+        );
+        assert_eq!(split_exec("printf 100%%").unwrap(), vec!["printf", "100%"]);
+        assert_eq!(
+            split_exec(r#""/opt/my app/run" "say \"hi\"" a\b"#).unwrap(),
+            vec!["/opt/my app/run", "say \"hi\"", "a\\b"]
+        );
+        assert_eq!(split_exec(r#"cmd """#).unwrap(), vec!["cmd", ""]);
+        assert!(split_exec(r#"cmd "open"#).is_err());
+    }
+
+    #[test]
+    fn quoting() {
+        let mut entry = DesktopEntry::parse("x", "[Desktop Entry]\nExec=sway")
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.command_line(), "sway");
+        entry.exec = vec![
+            "/opt/my app/run".to_string(),
+            "it's".to_string(),
+            "--x=1".to_string(),
+        ];
+        assert_eq!(entry.command_line(), r#"'/opt/my app/run' 'it'\''s' --x=1"#);
+    }
+
+    #[test]
+    fn parse() {
+        let entry = DesktopEntry::parse(
+            "plasma",
+            "# comment
+[Desktop Entry]
+Type=Application
+Name=Plasma (Wayland)
+Name[de]=Plasma
+Comment=Plasma by KDE
+Exec=/usr/lib/plasma-dbus-run-session-if-needed startplasma-wayland
+TryExec=startplasma-wayland
+DesktopNames=KDE
+
+[Desktop Action Other]
+Name=Other
+",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            entry,
+            DesktopEntry {
+                id: "plasma".to_string(),
+                name: "Plasma (Wayland)".to_string(),
+                comment: Some("Plasma by KDE".to_string()),
+                exec: vec![
+                    "/usr/lib/plasma-dbus-run-session-if-needed".to_string(),
+                    "startplasma-wayland".to_string()
+                ],
+                try_exec: Some("startplasma-wayland".to_string()),
+                desktop_names: vec!["KDE".to_string()],
+                no_display: false,
+                session_type: None,
+            }
+        );
+
+        let hidden = "[Desktop Entry]\nExec=sway\nHidden=true";
+        assert_eq!(DesktopEntry::parse("sway", hidden), Ok(None));
+        assert!(DesktopEntry::parse("sway", "[Desktop Entry]\nName=Sway").is_err());
+        let unnamed = DesktopEntry::parse("sway", "[Desktop Entry]\nExec=sway\\s--debug");
+        let unnamed = unnamed.unwrap().unwrap();
+        assert_eq!(unnamed.name, "sway");
+        assert_eq!(unnamed.exec, vec!["sway", "--debug"]);
+    }
+
+    #[test]
+    fn env() {
+        let mut entry = DesktopEntry::parse("plasma", "[Desktop Entry]\nExec=x\nDesktopNames=KDE")
+            .unwrap()
+            .unwrap();
+        entry.session_type = session_type("/usr/share/wayland-sessions");
+        assert_eq!(
+            entry.env(),
+            vec![
+                "XDG_SESSION_TYPE=wayland",
+                "XDG_SESSION_DESKTOP=plasma",
+                "XDG_CURRENT_DESKTOP=KDE"
+            ]
+        );
+        assert_eq!(session_type("/usr/share/xsessions/"), Some("x11"));
+        assert_eq!(session_type("/etc/greetd/sessions"), None);
+    }
+}
@@ -0,0 +1,320 @@
+//! Rendering of issue files and reading of the host identity.
+//!
+//! Issue files, such as `/etc/issue`, are shown before the login prompt and
+//! may contain the escapes documented in agetty(8), such as `\n` for the
+//! hostname or `\S` for the name of the operating system. This module renders
+//! them, and reads the os-release(5) fields that `\S` refers to.
+//!
+//! ```no_run
+//! use greetd_ipc::issue::{get_issue, DEFAULT_ISSUE_FILE};
+//!
+//! if let Ok(issue) = get_issue(DEFAULT_ISSUE_FILE, None) {
+//!     print!("{}", issue);
+//! }
+//! ```
+use std::{collections::HashMap, ffi::CString, fs, mem};
+
+use nix::{
+    ifaddrs::getifaddrs,
+    net::if_::InterfaceFlags,
+    sys::{socket::SockaddrLike, utsname::uname},
+};
+
+/// The default issue file.
+pub const DEFAULT_ISSUE_FILE: &str = "/etc/issue";
+
+// The locations of the os-release file, in order of precedence.
+const OS_RELEASE_FILES: [&str; 2] = ["/etc/os-release", "/usr/lib/os-release"];
+
+// Remove the quotes around an os-release value, along with the escapes that
+// are allowed within double quotes.
+fn unquote_value(value: &str) -> String {
+    let quote = match value.chars().next() {
+        Some(q @ '"') | Some(q @ '\'') if value.len() > 1 && value.ends_with(q) => q,
+        _ => return value.to_string(),
+    };
+    let inner = &value[1..value.len() - 1];
+    if quote == '\'' {
+        return inner.to_string();
+    }
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next @ ('$' | '"' | '\\' | '`')) => out.push(next),
+                Some(next) => {
+                    out.push('\\');
+                    out.push(next);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn parse_os_release(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), unquote_value(value.trim())))
+        .collect()
+}
+
+/// Read the fields of the os-release file, such as `PRETTY_NAME` and
+/// `VERSION_ID`. The map is empty if no os-release file could be read.
+pub fn os_release() -> HashMap<String, String> {
+    OS_RELEASE_FILES
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|contents| parse_os_release(&contents))
+        .unwrap_or_default()
+}
+
+fn get_os_release_field(key: &str) -> Option<String> {
+    os_release().remove(key)
+}
+
+fn strftime(format: &str) -> Option<String> {
+    let format = CString::new(format).ok()?;
+    let mut buf = [0u8; 128];
+    let len = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return None;
+        }
+        libc::strftime(
+            buf.as_mut_ptr() as *mut libc::c_char,
+            buf.len(),
+            format.as_ptr(),
+            &tm,
+        )
+    };
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+fn get_user_count() -> usize {
+    let mut count = 0;
+    unsafe {
+        libc::setutxent();
+        loop {
+            let entry = libc::getutxent();
+            if entry.is_null() {
+                break;
+            }
+            if (*entry).ut_type == libc::USER_PROCESS {
+                count += 1;
+            }
+        }
+        libc::endutxent();
+    }
+    count
+}
+
+// Find the first address of the given family, either on the named interface
+// or on the first interface that is up and not a loopback device.
+fn get_address(family: nix::sys::socket::AddressFamily, iface: Option<&str>) -> Option<String> {
+    for addr in getifaddrs().ok()? {
+        match iface {
+            Some(iface) if addr.interface_name != iface => continue,
+            None if addr.flags.contains(InterfaceFlags::IFF_LOOPBACK)
+                || !addr.flags.contains(InterfaceFlags::IFF_UP) =>
+            {
+                continue
+            }
+            _ => (),
+        }
+        let address = match addr.address {
+            Some(address) if address.family() == Some(family) => address,
+            _ => continue,
+        };
+        if let Some(v4) = address.as_sockaddr_in() {
+            return Some(std::net::Ipv4Addr::from(v4.ip()).to_string());
+        }
+        if let Some(v6) = address.as_sockaddr_in6() {
+            return Some(v6.ip().to_string());
+        }
+    }
+    None
+}
+
+fn get_color(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "black" => "\x1B[30m",
+        "red" => "\x1B[31m",
+        "green" => "\x1B[32m",
+        "brown" => "\x1B[33m",
+        "blue" => "\x1B[34m",
+        "magenta" => "\x1B[35m",
+        "cyan" => "\x1B[36m",
+        "lightgray" => "\x1B[37m",
+        "darkgray" => "\x1B[1;30m",
+        "lightred" => "\x1B[1;31m",
+        "lightgreen" => "\x1B[1;32m",
+        "yellow" => "\x1B[1;33m",
+        "lightblue" => "\x1B[1;34m",
+        "lightmagenta" => "\x1B[1;35m",
+        "lightcyan" => "\x1B[1;36m",
+        "white" => "\x1B[1;37m",
+        "bold" => "\x1B[1m",
+        "blink" => "\x1B[5m",
+        "reverse" => "\x1B[7m",
+        "halfbright" => "\x1B[2m",
+        "reset" => "\x1B[0m",
+        _ => return None,
+    })
+}
+
+/// Look up the value of an issue escape, with its optional argument, for a
+/// greeter running on the given VT.
+fn lookup(escape: char, arg: Option<&str>, vt: Option<usize>) -> Option<String> {
+    let uts = uname().ok();
+    let uts_field = |f: fn(&nix::sys::utsname::UtsName) -> &std::ffi::OsStr| {
+        uts.as_ref().map(|u| f(u).to_string_lossy().to_string())
+    };
+    match escape {
+        '4' => get_address(nix::sys::socket::AddressFamily::Inet, arg),
+        '6' => get_address(nix::sys::socket::AddressFamily::Inet6, arg),
+        'd' => strftime("%a %b %e %Y"),
+        't' => strftime("%H:%M:%S"),
+        'e' => match arg {
+            Some(name) => get_color(name).map(|c| c.to_string()),
+            None => Some("\x1B".to_string()),
+        },
+        'l' => Some(format!("tty{}", vt.unwrap_or(0))),
+        'm' => uts_field(|u| u.machine()),
+        'n' => uts_field(|u| u.nodename()),
+        'o' => uts_field(|u| u.domainname()),
+        'O' => {
+            let node = uts_field(|u| u.nodename())?;
+            Some(match node.split_once('.') {
+                Some((_, domain)) => domain.to_string(),
+                None => "unknown_domain".to_string(),
+            })
+        }
+        'r' => uts_field(|u| u.release()),
+        's' => uts_field(|u| u.sysname()),
+        'v' => uts_field(|u| u.version()),
+        'S' => match arg {
+            Some(key) => get_os_release_field(key),
+            None => {
+                Some(get_os_release_field("PRETTY_NAME").unwrap_or_else(|| "Linux".to_string()))
+            }
+        },
+        'u' => Some(get_user_count().to_string()),
+        'U' => Some(match get_user_count() {
+            1 => "1 user".to_string(),
+            n => format!("{} users", n),
+        }),
+        _ => None,
+    }
+}
+
+/// Render an issue template, replacing escapes using the provided lookup
+/// function. Escapes may take an argument in curly braces, such as
+/// `\S{VERSION}`. Unknown escapes are left untouched, and known escapes with
+/// no value (such as `\b`, which is not supported) are removed.
+fn render_with<F>(template: &str, lookup: F) -> String
+where
+    F: Fn(char, Option<&str>) -> Option<String>,
+{
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(idx) = rest.find('\\') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+        let escape = match rest.chars().next() {
+            Some(c) => c,
+            None => {
+                out.push('\\');
+                break;
+            }
+        };
+        rest = &rest[escape.len_utf8()..];
+        if escape == '\\' {
+            out.push('\\');
+            continue;
+        }
+
+        let mut arg = None;
+        if rest.starts_with('{') {
+            if let Some(end) = rest.find('}') {
+                arg = Some(&rest[1..end]);
+                rest = &rest[end + 1..];
+            }
+        }
+
+        match lookup(escape, arg) {
+            Some(value) => out.push_str(&value),
+            None if "46bdelmnoOrstSuUv".contains(escape) => (),
+            None => {
+                out.push('\\');
+                out.push(escape);
+                if let Some(arg) = arg {
+                    out.push('{');
+                    out.push_str(arg);
+                    out.push('}');
+                }
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Render an issue template for a greeter running on the given VT, which
+/// `\l` refers to.
+pub fn render(template: &str, vt: Option<usize>) -> String {
+    render_with(template, |escape, arg| lookup(escape, arg, vt))
+}
+
+/// Read and render the issue file at the given path for a greeter running on
+/// the given VT.
+pub fn get_issue(path: &str, vt: Option<usize>) -> Result<String, std::io::Error> {
+    Ok(render(&fs::read_to_string(path)?, vt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake_lookup(escape: char, arg: Option<&str>) -> Option<String> {
+        match (escape, arg) {
+            ('n', None) => Some("host".to_string()),
+            ('S', None) => Some("Distro".to_string()),
+            ('S', Some(key)) => Some(format!("<{}>", key)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(render_with("\\S on \\n", fake_lookup), "Distro on host");
+        assert_eq!(render_with("\\S{VERSION}", fake_lookup), "<VERSION>");
+        assert_eq!(render_with("a \\\\n b", fake_lookup), "a \\n b");
+        assert_eq!(render_with("ip: \\4\n", fake_lookup), "ip: \n");
+        assert_eq!(render_with("\\q \\x{y}", fake_lookup), "\\q \\x{y}");
+        assert_eq!(render_with("trailing \\", fake_lookup), "trailing \\");
+        assert_eq!(render_with("\\S{unclosed", fake_lookup), "Distro{unclosed");
+    }
+
+    #[test]
+    fn os_release_fields() {
+        let fields = parse_os_release(
+            "# comment\nNAME=Fedora\nPRETTY_NAME=\"Fedora \\\"Linux\\\" 40\"\n\nID='fedora'\nEMPTY=\n",
+        );
+        assert_eq!(fields.get("NAME").map(String::as_str), Some("Fedora"));
+        assert_eq!(
+            fields.get("PRETTY_NAME").map(String::as_str),
+            Some("Fedora \"Linux\" 40")
+        );
+        assert_eq!(fields.get("ID").map(String::as_str), Some("fedora"));
+        assert_eq!(fields.get("EMPTY").map(String::as_str), Some(""));
+        assert_eq!(fields.len(), 4);
+    }
+}
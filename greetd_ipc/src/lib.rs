@@ -8,6 +8,12 @@
 //! these to/from both sync and async readers/writers. The availability of
 //! these are controlled by feature flags.
 //!
+//! With the `desktop` feature, the [desktop](desktop/index.html) module
+//! provides discovery and parsing of session desktop entries for greeters.
+//!
+//! With the `issue` feature, the [issue](issue/index.html) module provides
+//! rendering of issue files and reading of os-release fields.
+//!
 //! Additional types are part of the different request and response values.
 //!
 //! See `agreety` for a simple example use of this library.
@@ -32,12 +38,22 @@
 //! serialization.
 //!
 #![cfg_attr(docsrs, feature(doc_cfg))]
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
 #[cfg(feature = "codec")]
 #[cfg_attr(docsrs, doc(cfg(feature = "codec")))]
 pub mod codec;
 
+#[cfg(feature = "desktop")]
+#[cfg_attr(docsrs, doc(cfg(feature = "desktop")))]
+pub mod desktop;
+
+#[cfg(feature = "issue")]
+#[cfg_attr(docsrs, doc(cfg(feature = "issue")))]
+pub mod issue;
+
 /// A request from a greeter to greetd. The request type is internally tagged
 /// with the"type" field, with the type written in snake_case.
 ///
@@ -64,7 +80,33 @@ pub enum Request {
     /// If a login flow needs to be aborted at any point, send
     /// Request::CancelSession. Note that the session is cancelled
     /// automatically on error.
-    CreateSession { username: String },
+    ///
+    /// If username is empty or absent, PAM determines the user, such as by
+    /// mapping a smartcard to a user or by asking for it. Authentication then
+    /// completes with Response::Authenticated rather than Response::Success,
+    /// telling the greeter who was authenticated.
+    ///
+    /// If concurrent_auth is set, authentication may proceed without waiting
+    /// for the greeter, such as when a fingerprint is accepted while a
+    /// password is asked for. The response is then followed by further
+    /// responses without a request: informational and error auth messages are
+    /// not answered, and an auth message that asks a question may be followed
+    /// by further auth messages, and by the final Response::Success or
+    /// Response::Error, before it is answered. Answers to questions that were
+    /// resolved this way are discarded, and answers get no response of their
+    /// own beyond the next response in this sequence.
+    ///
+    /// If locale is set, such as to "de_DE.UTF-8", PAM runs in that locale, so
+    /// that its messages are in the language of the user. It does not affect
+    /// the session.
+    CreateSession {
+        #[serde(default)]
+        username: String,
+        #[serde(default)]
+        concurrent_auth: bool,
+        #[serde(default)]
+        locale: Option<String>,
+    },
 
     /// PostAuthMessageResponse responds to the last auth message, and returns
     /// either a Response::AuthMessage, Response::Success or Response::Failure.
@@ -76,16 +118,301 @@ pub enum Request {
 
     /// Start a successfully logged in session. This will fail if the session
     /// has pending messages or has encountered an error.
+    ///
+    /// If cmd is empty, the default session of the user is started, as
+    /// returned by Request::GetDefaultSession.
+    ///
+    /// If the command is that of an installed session desktop entry, greetd
+    /// sets XDG_SESSION_TYPE, XDG_SESSION_DESKTOP and XDG_CURRENT_DESKTOP
+    /// accordingly, unless they are set in env. The session type can also be
+    /// given explicitly.
     StartSession {
         cmd: Vec<String>,
         #[serde(default)]
         env: Vec<String>,
+        #[serde(default)]
+        session_type: Option<SessionType>,
+    },
+
+    /// Start a successfully logged in session with a session desktop entry,
+    /// given by its ID such as "sway". greetd looks the entry up in its
+    /// configured session directories, and starts its command with the
+    /// session type and desktop set accordingly. Otherwise, this behaves like
+    /// Request::StartSession.
+    StartDesktopSession {
+        id: String,
+        #[serde(default)]
+        env: Vec<String>,
     },
 
     /// Cancel a session. This can only be done if the session has not been
     /// started. Cancel does not have to be called if an error has been
     /// encountered in its setup or login flow.
     CancelSession,
+
+    /// Perform a power action, such as powering off or rebooting the machine.
+    /// Returns Response::Success if the action was initiated.
+    PowerAction { action: PowerAction },
+
+    /// Retrieve login metrics. Returns Response::Metrics. Only available on
+    /// the admin socket.
+    GetMetrics,
+
+    /// Retrieve the most recent user sessions that have ended. Returns
+    /// Response::SessionHistory. Only available on the admin socket.
+    GetSessionHistory,
+
+    /// Retrieve the sessions that are running or about to start, such as for
+    /// a greeter to offer switching to the session of a user who is already
+    /// logged in. Returns Response::Sessions.
+    ///
+    /// The sessions of this instance of greetd are listed first, followed by
+    /// the user sessions of other instances, such as those on other VTs.
+    /// Sessions whose user has not authenticated yet are not included.
+    GetSessions,
+
+    /// Re-execute greetd from its binary, such as after it was upgraded,
+    /// keeping the running session. Returns Response::Success before greetd
+    /// re-executes, or an error if a session is being started. Only available
+    /// on the admin socket.
+    Upgrade,
+
+    /// Retrieve the banner, such as a legal notice, that must be displayed
+    /// before authentication. Returns Response::Banner.
+    ///
+    /// If greetd is configured to require the banner, it must be retrieved on
+    /// the same connection before Request::CreateSession is accepted.
+    GetBanner,
+
+    /// GetDefaultSession requests the default session chosen by the user of
+    /// the session under configuration, such as for a greeter to preselect
+    /// it. Returns Response::DefaultSession.
+    GetDefaultSession,
+
+    /// GetUserInfo requests the display metadata of a user, such as their
+    /// real name and avatar, as recorded by AccountsService. Returns
+    /// Response::UserInfo.
+    GetUserInfo { username: String },
+
+    /// GetHostInfo requests the identity of the machine, such as its hostname
+    /// and operating system, for greeters to display. Returns
+    /// Response::HostInfo.
+    GetHostInfo,
+
+    /// GetGreeterState retrieves a value that the greeter stored with
+    /// Request::SetGreeterState. Returns Response::GreeterState.
+    GetGreeterState { key: String },
+
+    /// SetGreeterState stores a value for the greeter under the given key, or
+    /// removes the key if the value is None. greetd keeps the values on disk,
+    /// so that a greeter can remember its state, such as the last selected
+    /// session, across restarts. Returns Response::Success.
+    SetGreeterState {
+        key: String,
+        #[serde(default)]
+        value: Option<String>,
+    },
+}
+
+/// A session type for Request::StartSession, as used for XDG_SESSION_TYPE.
+/// Serialized as snake_case.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionType {
+    /// A text session.
+    Tty,
+
+    /// An X11 session.
+    X11,
+
+    /// A Wayland session.
+    Wayland,
+}
+
+impl SessionType {
+    /// The value of XDG_SESSION_TYPE for this session type.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionType::Tty => "tty",
+            SessionType::X11 => "x11",
+            SessionType::Wayland => "wayland",
+        }
+    }
+}
+
+/// A power action for Request::PowerAction. Serialized as snake_case.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerAction {
+    /// Power off the machine.
+    Poweroff,
+
+    /// Reboot the machine.
+    Reboot,
+}
+
+/// The default session of a user for Response::DefaultSession.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct UserSession {
+    /// The command line of the session.
+    pub cmd: Vec<String>,
+
+    /// The ID of the session desktop entry, if the session is one.
+    pub desktop_id: Option<String>,
+
+    /// The name of the session desktop entry, if the session is one.
+    pub name: Option<String>,
+}
+
+/// The display metadata of a user for Response::UserInfo.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct UserInfo {
+    /// The name of the user.
+    pub username: String,
+
+    /// The real name of the user, if set.
+    pub real_name: Option<String>,
+
+    /// The path to the avatar of the user, if set.
+    pub icon: Option<String>,
+
+    /// The ID of the session desktop entry last chosen by the user, if any.
+    pub session: Option<String>,
+
+    /// The locale chosen by the user, such as "de_DE.UTF-8", if any.
+    pub language: Option<String>,
+}
+
+/// The identity of the machine for Response::HostInfo.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct HostInfo {
+    /// The hostname of the machine.
+    pub hostname: String,
+
+    /// The fields of os-release(5), such as "PRETTY_NAME" and "VERSION_ID".
+    pub os_release: HashMap<String, String>,
+
+    /// The release of the running kernel, such as "6.9.7-arch1-1".
+    pub kernel: String,
+
+    /// The issue file rendered for the greeter, if there is one. The issue may
+    /// contain terminal escape sequences.
+    pub issue: Option<String>,
+}
+
+/// A user session for Response::SessionHistory.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SessionRecord {
+    /// The name of the user.
+    pub username: String,
+
+    /// The seat the session ran on.
+    pub seat: String,
+
+    /// The VT the session ran on, if any.
+    pub vt: Option<usize>,
+
+    /// When the session started, in seconds since the Unix epoch.
+    pub start: u64,
+
+    /// When the session ended, in seconds since the Unix epoch, if it has.
+    pub end: Option<u64>,
+
+    /// The exit code of the session, or 128 plus the number of the signal
+    /// that killed it. None if it is unknown.
+    pub exit_code: Option<i32>,
+}
+
+/// The class of a session for Response::Sessions. Serialized as snake_case.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionClass {
+    /// The greeter.
+    Greeter,
+
+    /// A user session.
+    User,
+}
+
+/// The state of a session for Response::Sessions. Serialized as snake_case.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    /// The user has authenticated, and the session starts once the greeter
+    /// has exited.
+    Starting,
+
+    /// The session is running.
+    Active,
+
+    /// The session has been replaced, and is being terminated.
+    Closing,
+}
+
+/// A session for Response::Sessions.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SessionInfo {
+    /// The ID of the session, as used in the logs of greetd. None for
+    /// sessions of other instances of greetd.
+    pub id: Option<u64>,
+
+    /// The name of the user.
+    pub username: String,
+
+    /// The seat of the session.
+    pub seat: String,
+
+    /// The VT of the session, if any.
+    pub vt: Option<usize>,
+
+    /// Whether the session is the greeter or a user session.
+    pub class: SessionClass,
+
+    /// When the session was started, or for a session that is starting, when
+    /// it was created, in seconds since the Unix epoch.
+    pub start: u64,
+
+    /// The state of the session.
+    pub state: SessionState,
+}
+
+/// Login metrics for Response::Metrics, counted since greetd started.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Metrics {
+    /// The number of user sessions started after successful authentication.
+    pub logins: u64,
+
+    /// The number of failed authentication attempts.
+    pub auth_failures: u64,
+
+    /// The number of times the greeter has been started.
+    pub greeter_starts: u64,
+
+    /// The duration of user sessions that have ended, in seconds.
+    pub session_durations: Histogram,
+}
+
+/// A histogram with cumulative bucket counts.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Histogram {
+    /// The buckets, in increasing order of their upper bound.
+    pub buckets: Vec<Bucket>,
+
+    /// The sum of all observed values.
+    pub sum: f64,
+
+    /// The number of observed values.
+    pub count: u64,
+}
+
+/// A histogram bucket.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Bucket {
+    /// The inclusive upper bound of the bucket.
+    pub le: f64,
+
+    /// The number of observed values less than or equal to the upper bound.
+    pub count: u64,
 }
 
 /// An error type for Response::Error. Serialized as snake_case.
@@ -97,10 +424,46 @@ pub enum ErrorType {
 
     /// An error caused by failed authentication.
     AuthError,
+
+    /// Authentication succeeded, but the account has expired.
+    AccountExpired,
+
+    /// The account is locked, such as after too many failed login attempts,
+    /// or access to it is otherwise denied.
+    AccountLocked,
+
+    /// Authentication succeeded, but the password has expired and must be
+    /// changed.
+    PasswordExpired,
+
+    /// The session command is not allowed by the configured command policy.
+    CommandNotAllowed,
+
+    /// The user already has a session on another seat or VT, and greetd is
+    /// configured to allow only one. The description is the location of the
+    /// existing session, such as "seat0, VT 2".
+    AlreadyLoggedIn,
+
+    /// The power action is blocked by a logind inhibitor, such as one held
+    /// during a package upgrade. The description names who holds it and why.
+    Inhibited,
+
+    /// A PAM call failed for a reason other than the credentials or the state
+    /// of the account, such as a module that could not open the session. The
+    /// description names the call and the PAM return code.
+    PamError,
+
+    /// An I/O or other system error occurred in greetd, such as when starting
+    /// a session worker.
+    IoError,
+
+    /// A session worker did not follow the protocol. The session has been
+    /// discarded.
+    ProtocolError,
 }
 
 /// A message type for a Response::AuthMessage. Serialized as snake_case.
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum AuthMessageType {
     /// A question whose answer should be visible during input.
@@ -155,4 +518,46 @@ pub enum Response {
         auth_message_type: AuthMessageType,
         auth_message: String,
     },
+
+    /// The password of the user has expired and must be changed before the
+    /// session can be started. The authentication messages that follow ask
+    /// for the new password, rather than for the credentials to log in with.
+    ///
+    /// Needs no answer of its own, but is answered like an informational
+    /// message with Request::PostAuthMessageResponse to continue, or with
+    /// Request::CancelSession to give up on the login.
+    PasswordChangeRequired,
+
+    /// Authentication of a session created without a username succeeded, and
+    /// the session can be started with Request::StartSession. Sent in place of
+    /// Response::Success, with the user determined by PAM.
+    Authenticated { username: String },
+
+    /// The default session chosen by the user, in response to
+    /// Request::GetDefaultSession. None if the user has not chosen one.
+    DefaultSession { session: Option<UserSession> },
+
+    /// The display metadata of a user, in response to Request::GetUserInfo.
+    UserInfo { info: UserInfo },
+
+    /// The identity of the machine, in response to Request::GetHostInfo.
+    HostInfo { info: HostInfo },
+
+    /// Login metrics, in response to Request::GetMetrics.
+    Metrics { metrics: Metrics },
+
+    /// A value stored by the greeter, in response to Request::GetGreeterState.
+    /// None if nothing is stored under the key.
+    GreeterState { value: Option<String> },
+
+    /// The most recent user sessions that have ended, oldest first, in
+    /// response to Request::GetSessionHistory.
+    SessionHistory { sessions: Vec<SessionRecord> },
+
+    /// The sessions of the seat, in response to Request::GetSessions.
+    Sessions { sessions: Vec<SessionInfo> },
+
+    /// The banner to display, in response to Request::GetBanner. The banner is
+    /// None if none is configured.
+    Banner { banner: Option<String> },
 }
@@ -0,0 +1,216 @@
+//! A PAM module for testing greetd, which authenticates any user with the
+//! password "password" without touching the system. It must never be used in a
+//! real PAM configuration.
+//!
+//! The module takes the following arguments:
+//!
+//! - `code`: after the password, send an informational message and ask for
+//!   the visible code "1234".
+//! - `expired`: report the password as expired from pam_acct_mgmt, and accept
+//!   any non-empty new password in pam_chauthtok.
+//!
+//! The PAM functions used are resolved from libpam when the module is loaded,
+//! so the module does not link against it.
+
+use std::{
+    ffi::{CStr, CString},
+    ptr,
+};
+
+use libc::{c_char, c_int, c_void};
+
+const PAM_SUCCESS: c_int = 0;
+const PAM_AUTH_ERR: c_int = 7;
+const PAM_NEW_AUTHTOK_REQD: c_int = 12;
+const PAM_CONV_ERR: c_int = 19;
+const PAM_AUTHTOK_ERR: c_int = 20;
+
+const PAM_CONV: c_int = 5;
+
+const PAM_PROMPT_ECHO_OFF: c_int = 1;
+const PAM_PROMPT_ECHO_ON: c_int = 2;
+const PAM_TEXT_INFO: c_int = 4;
+
+const PAM_UPDATE_AUTHTOK: c_int = 0x2000;
+
+const PASSWORD: &str = "password";
+const CODE: &str = "1234";
+
+#[repr(C)]
+struct PamMessage {
+    msg_style: c_int,
+    msg: *const c_char,
+}
+
+#[repr(C)]
+struct PamResponse {
+    resp: *mut c_char,
+    #[allow(unused)]
+    resp_retcode: c_int,
+}
+
+#[repr(C)]
+struct PamConv {
+    conv: Option<
+        extern "C" fn(c_int, *mut *const PamMessage, *mut *mut PamResponse, *mut c_void) -> c_int,
+    >,
+    appdata_ptr: *mut c_void,
+}
+
+type PamHandle = c_void;
+
+extern "C" {
+    fn pam_get_item(pamh: *const PamHandle, item_type: c_int, item: *mut *const c_void) -> c_int;
+}
+
+unsafe fn args(argc: c_int, argv: *const *const c_char) -> Vec<String> {
+    (0..argc as isize)
+        .map(|i| {
+            CStr::from_ptr(*argv.offset(i))
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect()
+}
+
+// Send a single message through the conversation of the application,
+// returning the answer if there is one.
+unsafe fn converse(pamh: *mut PamHandle, style: c_int, msg: &str) -> Result<Option<String>, c_int> {
+    let mut conv: *const c_void = ptr::null();
+    if pam_get_item(pamh, PAM_CONV, &mut conv) != PAM_SUCCESS || conv.is_null() {
+        return Err(PAM_CONV_ERR);
+    }
+    let conv = &*(conv as *const PamConv);
+    let func = conv.conv.ok_or(PAM_CONV_ERR)?;
+
+    let msg = CString::new(msg).map_err(|_| PAM_CONV_ERR)?;
+    let message = PamMessage {
+        msg_style: style,
+        msg: msg.as_ptr(),
+    };
+    let mut messages = &message as *const PamMessage;
+    let mut resp: *mut PamResponse = ptr::null_mut();
+    let rc = func(1, &mut messages, &mut resp, conv.appdata_ptr);
+    if rc != PAM_SUCCESS {
+        return Err(rc);
+    }
+    if resp.is_null() {
+        return Ok(None);
+    }
+    let answer = match (*resp).resp {
+        answer if answer.is_null() => None,
+        answer => {
+            let s = CStr::from_ptr(answer).to_string_lossy().into_owned();
+            libc::free(answer as *mut c_void);
+            Some(s)
+        }
+    };
+    libc::free(resp as *mut c_void);
+    Ok(answer)
+}
+
+unsafe fn ask(pamh: *mut PamHandle, style: c_int, msg: &str, expected: &str) -> c_int {
+    match converse(pamh, style, msg) {
+        Ok(Some(answer)) if answer == expected => PAM_SUCCESS,
+        Ok(_) => PAM_AUTH_ERR,
+        Err(rc) => rc,
+    }
+}
+
+/// # Safety
+///
+/// Called by libpam.
+#[no_mangle]
+pub unsafe extern "C" fn pam_sm_authenticate(
+    pamh: *mut PamHandle,
+    _flags: c_int,
+    argc: c_int,
+    argv: *const *const c_char,
+) -> c_int {
+    let rc = ask(pamh, PAM_PROMPT_ECHO_OFF, "Password:", PASSWORD);
+    if rc != PAM_SUCCESS || !args(argc, argv).iter().any(|a| a == "code") {
+        return rc;
+    }
+    if let Err(rc) = converse(pamh, PAM_TEXT_INFO, "Check your authenticator") {
+        return rc;
+    }
+    ask(pamh, PAM_PROMPT_ECHO_ON, "Code:", CODE)
+}
+
+/// # Safety
+///
+/// Called by libpam.
+#[no_mangle]
+pub unsafe extern "C" fn pam_sm_acct_mgmt(
+    _pamh: *mut PamHandle,
+    _flags: c_int,
+    argc: c_int,
+    argv: *const *const c_char,
+) -> c_int {
+    if args(argc, argv).iter().any(|a| a == "expired") {
+        PAM_NEW_AUTHTOK_REQD
+    } else {
+        PAM_SUCCESS
+    }
+}
+
+/// # Safety
+///
+/// Called by libpam.
+#[no_mangle]
+pub unsafe extern "C" fn pam_sm_chauthtok(
+    pamh: *mut PamHandle,
+    flags: c_int,
+    _argc: c_int,
+    _argv: *const *const c_char,
+) -> c_int {
+    // Called once to check whether the password can be changed, and again
+    // to change it.
+    if flags & PAM_UPDATE_AUTHTOK == 0 {
+        return PAM_SUCCESS;
+    }
+    match converse(pamh, PAM_PROMPT_ECHO_OFF, "New password:") {
+        Ok(Some(password)) if !password.is_empty() => PAM_SUCCESS,
+        Ok(_) => PAM_AUTHTOK_ERR,
+        Err(rc) => rc,
+    }
+}
+
+/// # Safety
+///
+/// Called by libpam.
+#[no_mangle]
+pub unsafe extern "C" fn pam_sm_setcred(
+    _pamh: *mut PamHandle,
+    _flags: c_int,
+    _argc: c_int,
+    _argv: *const *const c_char,
+) -> c_int {
+    PAM_SUCCESS
+}
+
+/// # Safety
+///
+/// Called by libpam.
+#[no_mangle]
+pub unsafe extern "C" fn pam_sm_open_session(
+    _pamh: *mut PamHandle,
+    _flags: c_int,
+    _argc: c_int,
+    _argv: *const *const c_char,
+) -> c_int {
+    PAM_SUCCESS
+}
+
+/// # Safety
+///
+/// Called by libpam.
+#[no_mangle]
+pub unsafe extern "C" fn pam_sm_close_session(
+    _pamh: *mut PamHandle,
+    _flags: c_int,
+    _argc: c_int,
+    _argv: *const *const c_char,
+) -> c_int {
+    PAM_SUCCESS
+}
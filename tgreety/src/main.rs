@@ -0,0 +1,483 @@
+mod ui;
+mod users;
+
+use std::{env, os::unix::net::UnixStream};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use getopts::Options;
+use nix::sys::utsname::uname;
+
+use greetd_ipc::{
+    codec::SyncCodec,
+    desktop::{self, DEFAULT_SESSION_DIRS},
+    AuthMessageType, ErrorType, PowerAction, Request, Response,
+};
+
+use crate::{
+    ui::{draw, Line, Terminal},
+    users::get_users,
+};
+
+// The number of PAM and error messages to keep on screen.
+const MAX_MESSAGES: usize = 5;
+
+const POWER_MENU: [&str; 3] = ["Reboot", "Power off", "Cancel"];
+
+/// A list of choices, followed by an entry for free text input.
+struct Selector {
+    label: &'static str,
+    entries: Vec<(String, String)>,
+    idx: usize,
+    custom: String,
+}
+
+impl Selector {
+    fn new(label: &'static str, entries: Vec<(String, String)>) -> Selector {
+        Selector {
+            label,
+            entries,
+            idx: 0,
+            custom: String::new(),
+        }
+    }
+
+    fn is_custom(&self) -> bool {
+        self.idx == self.entries.len()
+    }
+
+    fn value(&self) -> String {
+        match self.entries.get(self.idx) {
+            Some((_, value)) => value.clone(),
+            None => self.custom.clone(),
+        }
+    }
+
+    fn next(&mut self) {
+        self.idx = (self.idx + 1) % (self.entries.len() + 1);
+    }
+
+    fn prev(&mut self) {
+        self.idx = (self.idx + self.entries.len()) % (self.entries.len() + 1);
+    }
+
+    fn line(&self, focused: bool) -> Line {
+        let value = match self.entries.get(self.idx) {
+            Some((name, _)) => format!("< {} >", name),
+            None if focused => format!("[{}_]", self.custom),
+            None => format!("[{}]", self.custom),
+        };
+        let text = format!("{:>8}: {}", self.label, value);
+        if focused {
+            Line::Highlighted(text)
+        } else {
+            Line::Text(text)
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    User,
+    Session,
+    Prompt,
+}
+
+/// A question from PAM waiting for an answer.
+struct Prompt {
+    message: String,
+    secret: bool,
+    input: String,
+}
+
+struct Greeter {
+    node: String,
+    banner: Option<String>,
+    user: Selector,
+    session: Selector,
+    focus: Focus,
+    prompt: Option<Prompt>,
+    messages: Vec<String>,
+    power_menu: Option<usize>,
+    stream: Option<UnixStream>,
+    starting: bool,
+}
+
+impl Greeter {
+    fn draw(&self) -> std::io::Result<()> {
+        let mut lines = vec![Line::Text(format!("Welcome to {}", self.node)), Line::Blank];
+        if let Some(banner) = &self.banner {
+            lines.extend(banner.trim_end().lines().map(|l| Line::Text(l.to_string())));
+            lines.push(Line::Blank);
+        }
+
+        if let Some(selected) = self.power_menu {
+            for (idx, entry) in POWER_MENU.iter().enumerate() {
+                lines.push(if idx == selected {
+                    Line::Highlighted(entry.to_string())
+                } else {
+                    Line::Text(entry.to_string())
+                });
+            }
+            return draw(&lines, "Up/Down: select, Enter: confirm, Esc: back");
+        }
+
+        lines.push(self.user.line(self.focus == Focus::User));
+        lines.push(self.session.line(self.focus == Focus::Session));
+        if let Some(prompt) = &self.prompt {
+            let input = if prompt.secret {
+                "*".repeat(prompt.input.chars().count())
+            } else {
+                prompt.input.clone()
+            };
+            lines.push(Line::Blank);
+            lines.push(Line::Highlighted(format!("{} {}_", prompt.message, input)));
+        }
+        if !self.messages.is_empty() {
+            lines.push(Line::Blank);
+            for message in &self.messages {
+                lines.push(Line::Text(message.clone()));
+            }
+        }
+
+        let help = if self.prompt.is_some() {
+            "Enter: submit, Esc: cancel, F12: power"
+        } else {
+            "Up/Down: select field, Left/Right: change, Enter: log in, F12: power"
+        };
+        draw(&lines, help)
+    }
+
+    fn message(&mut self, message: String) {
+        self.messages.push(message);
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    fn request(&mut self, req: Request) -> Result<Response, Box<dyn std::error::Error>> {
+        let stream = self.stream.as_mut().ok_or("not connected")?;
+        req.write_to(stream)?;
+        Ok(Response::read_from(stream)?)
+    }
+
+    /// Drop the current login attempt, if any.
+    fn reset(&mut self) {
+        self.stream = None;
+        self.prompt = None;
+        self.starting = false;
+        self.focus = Focus::User;
+    }
+
+    fn cancel(&mut self) {
+        if self.stream.is_some() {
+            let _ = self.request(Request::CancelSession);
+        }
+        self.reset();
+    }
+
+    fn login(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.cancel();
+        self.messages.clear();
+        self.stream = Some(UnixStream::connect(env::var("GREETD_SOCK")?)?);
+
+        // The banner is displayed from startup, but greetd may require it to be
+        // retrieved on every connection that creates a session. If it cannot
+        // be retrieved, greetd refuses the session if it required the banner.
+        match self.request(Request::GetBanner)? {
+            Response::Banner { banner } => self.banner = banner,
+            Response::Error { description, .. } => {
+                self.message(format!("unable to get banner: {}", description))
+            }
+            resp => return self.handle(resp),
+        }
+
+        let resp = self.request(Request::CreateSession {
+            username: self.user.value(),
+            concurrent_auth: false,
+            locale: None,
+        })?;
+        self.handle(resp)
+    }
+
+    // Process responses until the user needs to answer a question. Returns
+    // true once the session has been started.
+    fn handle(&mut self, mut resp: Response) -> Result<bool, Box<dyn std::error::Error>> {
+        loop {
+            resp = match resp {
+                Response::AuthMessage {
+                    auth_message_type,
+                    auth_message,
+                } => match auth_message_type {
+                    AuthMessageType::Visible | AuthMessageType::Secret => {
+                        self.prompt = Some(Prompt {
+                            message: auth_message,
+                            secret: matches!(auth_message_type, AuthMessageType::Secret),
+                            input: String::new(),
+                        });
+                        self.focus = Focus::Prompt;
+                        return Ok(false);
+                    }
+                    AuthMessageType::Info | AuthMessageType::Error => {
+                        self.message(auth_message);
+                        self.request(Request::PostAuthMessageResponse { response: None })?
+                    }
+                },
+                Response::PasswordChangeRequired => {
+                    self.message("Password expired, set a new one".to_string());
+                    self.request(Request::PostAuthMessageResponse { response: None })?
+                }
+                Response::Success if self.starting => return Ok(true),
+                Response::Success => {
+                    self.starting = true;
+                    self.prompt = None;
+                    self.request(Request::StartSession {
+                        cmd: vec![self.session.value()],
+                        env: vec![],
+                        session_type: None,
+                    })?
+                }
+                Response::Error {
+                    error_type,
+                    description,
+                } => {
+                    self.cancel();
+                    self.message(match error_type {
+                        ErrorType::AuthError => "Login incorrect".to_string(),
+                        ErrorType::AccountExpired => "Account expired".to_string(),
+                        ErrorType::AccountLocked => "Account locked".to_string(),
+                        ErrorType::PasswordExpired => "Password expired".to_string(),
+                        ErrorType::CommandNotAllowed => "Session not allowed".to_string(),
+                        ErrorType::AlreadyLoggedIn => {
+                            format!("Already logged in on {}", description)
+                        }
+                        ErrorType::Error
+                        | ErrorType::Inhibited
+                        | ErrorType::PamError
+                        | ErrorType::IoError
+                        | ErrorType::ProtocolError => {
+                            format!("error: {}", description)
+                        }
+                    });
+                    return Ok(false);
+                }
+                resp => return Err(format!("unexpected response: {:?}", resp).into()),
+            }
+        }
+    }
+
+    fn power(&mut self, action: PowerAction) -> Result<(), Box<dyn std::error::Error>> {
+        let mut stream = UnixStream::connect(env::var("GREETD_SOCK")?)?;
+        Request::PowerAction { action }.write_to(&mut stream)?;
+        match Response::read_from(&mut stream)? {
+            Response::Error {
+                error_type: ErrorType::Inhibited,
+                description,
+            } => self.message(format!("Blocked by {}", description)),
+            Response::Error { description, .. } => self.message(format!("error: {}", description)),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn power_menu_key(
+        &mut self,
+        selected: usize,
+        code: KeyCode,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match code {
+            KeyCode::Up => self.power_menu = Some(selected.saturating_sub(1)),
+            KeyCode::Down => self.power_menu = Some((selected + 1).min(POWER_MENU.len() - 1)),
+            KeyCode::Esc => self.power_menu = None,
+            KeyCode::Enter => {
+                self.power_menu = None;
+                match selected {
+                    0 => self.power(PowerAction::Reboot)?,
+                    1 => self.power(PowerAction::Poweroff)?,
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    // The text currently being edited, if any.
+    fn input(&mut self) -> Option<&mut String> {
+        match self.focus {
+            Focus::Prompt => self.prompt.as_mut().map(|p| &mut p.input),
+            Focus::User if self.user.is_custom() => Some(&mut self.user.custom),
+            Focus::Session if self.session.is_custom() => Some(&mut self.session.custom),
+            _ => None,
+        }
+    }
+
+    fn selector(&mut self) -> Option<&mut Selector> {
+        match self.focus {
+            Focus::User => Some(&mut self.user),
+            Focus::Session => Some(&mut self.session),
+            Focus::Prompt => None,
+        }
+    }
+
+    /// Handle a key press. Returns true once the session has been started.
+    fn key(
+        &mut self,
+        code: KeyCode,
+        modifiers: KeyModifiers,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if let Some(selected) = self.power_menu {
+            self.power_menu_key(selected, code)?;
+            return Ok(false);
+        }
+
+        match code {
+            KeyCode::F(12) => self.power_menu = Some(0),
+            KeyCode::Esc => self.cancel(),
+            KeyCode::Enter => match self.prompt.take() {
+                Some(prompt) => {
+                    let resp = self.request(Request::PostAuthMessageResponse {
+                        response: Some(prompt.input),
+                    })?;
+                    return self.handle(resp);
+                }
+                None => return self.login(),
+            },
+            KeyCode::Up | KeyCode::BackTab if self.prompt.is_none() => self.focus = Focus::User,
+            KeyCode::Down | KeyCode::Tab if self.prompt.is_none() => self.focus = Focus::Session,
+            KeyCode::Left => {
+                if let Some(selector) = self.selector() {
+                    selector.prev();
+                }
+            }
+            KeyCode::Right => {
+                if let Some(selector) = self.selector() {
+                    selector.next();
+                }
+            }
+            KeyCode::Backspace => {
+                if let Some(input) = self.input() {
+                    input.pop();
+                }
+            }
+            KeyCode::Char('u') if modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(input) = self.input() {
+                    input.clear();
+                }
+            }
+            KeyCode::Char(c) if !modifiers.contains(KeyModifiers::CONTROL) => {
+                if let Some(input) = self.input() {
+                    input.push(c);
+                }
+            }
+            _ => (),
+        }
+        Ok(false)
+    }
+}
+
+fn get_banner() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut stream = UnixStream::connect(env::var("GREETD_SOCK")?)?;
+    Request::GetBanner.write_to(&mut stream)?;
+    match Response::read_from(&mut stream)? {
+        Response::Banner { banner } => Ok(banner),
+        Response::Error { description, .. } => Err(description.into()),
+        resp => Err(format!("unexpected response: {:?}", resp).into()),
+    }
+}
+
+fn run(mut greeter: Greeter) -> Result<(), Box<dyn std::error::Error>> {
+    let _terminal = Terminal::enter()?;
+    loop {
+        greeter.draw()?;
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        match greeter.key(key.code, key.modifiers) {
+            Ok(true) => return Ok(()),
+            Ok(false) => (),
+            Err(e) => {
+                greeter.reset();
+                greeter.message(format!("error: {}", e));
+            }
+        }
+    }
+}
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options]", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optflag("h", "help", "print this help menu");
+    opts.optopt("c", "cmd", "default command to run", "COMMAND");
+    opts.optopt(
+        "s",
+        "session-dirs",
+        "colon-separated list of directories with session desktop entries",
+        "DIRS",
+    );
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => {
+            println!("{}", f);
+            print_usage(&program, opts);
+            std::process::exit(1);
+        }
+    };
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        std::process::exit(0);
+    }
+
+    let users = get_users()
+        .into_iter()
+        .map(|user| (user.display, user.name))
+        .collect();
+
+    let session_dirs = matches
+        .opt_str("session-dirs")
+        .unwrap_or_else(|| DEFAULT_SESSION_DIRS.to_string());
+    let mut sessions: Vec<(String, String)> = matches
+        .opt_str("cmd")
+        .map(|cmd| (cmd.clone(), cmd))
+        .into_iter()
+        .collect();
+    sessions.extend(
+        desktop::entries(&session_dirs)
+            .into_iter()
+            .filter(|entry| !entry.no_display && entry.installed())
+            .map(|entry| (entry.name.clone(), entry.command_line())),
+    );
+
+    let banner = match get_banner() {
+        Ok(banner) => banner,
+        Err(e) => {
+            eprintln!("unable to get banner: {}", e);
+            None
+        }
+    };
+
+    let uts = uname().unwrap();
+    let greeter = Greeter {
+        node: uts.nodename().to_string_lossy().to_string(),
+        banner,
+        user: Selector::new("User", users),
+        session: Selector::new("Session", sessions),
+        focus: Focus::User,
+        prompt: None,
+        messages: Vec::new(),
+        power_menu: None,
+        stream: None,
+        starting: false,
+    };
+
+    if let Err(e) = run(greeter) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,76 @@
+use std::io::{self, Write};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{
+        self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+
+/// Puts the terminal into raw mode on an alternate screen, restoring it when
+/// dropped.
+pub struct Terminal;
+
+impl Terminal {
+    pub fn enter() -> io::Result<Terminal> {
+        enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, Hide)?;
+        Ok(Terminal)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+/// A line of the screen.
+pub enum Line {
+    Blank,
+    Text(String),
+    Highlighted(String),
+}
+
+/// Draw the lines horizontally and vertically centered on the screen, with a
+/// help text on the bottom line.
+pub fn draw(lines: &[Line], help: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let (width, height) = terminal::size()?;
+    let text_width = |s: &str| s.chars().count() as u16;
+    let column = lines
+        .iter()
+        .map(|line| match line {
+            Line::Blank => 0,
+            Line::Text(s) | Line::Highlighted(s) => text_width(s),
+        })
+        .max()
+        .unwrap_or(0);
+    let column = width.saturating_sub(column) / 2;
+    let top = height.saturating_sub(lines.len() as u16) / 2;
+
+    queue!(stdout, Clear(ClearType::All))?;
+    for (idx, line) in lines.iter().enumerate() {
+        queue!(stdout, MoveTo(column, top + idx as u16))?;
+        match line {
+            Line::Blank => (),
+            Line::Text(s) => queue!(stdout, Print(s))?,
+            Line::Highlighted(s) => queue!(
+                stdout,
+                SetAttribute(Attribute::Reverse),
+                Print(s),
+                SetAttribute(Attribute::Reset)
+            )?,
+        }
+    }
+    queue!(
+        stdout,
+        MoveTo(width.saturating_sub(text_width(help)) / 2, height - 1),
+        Print(help)
+    )?;
+    stdout.flush()
+}
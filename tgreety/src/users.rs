@@ -0,0 +1,99 @@
+use std::fs;
+
+/// A user that can be picked from the user list.
+pub struct UserEntry {
+    pub name: String,
+    pub display: String,
+}
+
+// Read the range of regular user IDs from login.defs, falling back to the
+// common defaults.
+fn parse_uid_range(login_defs: &str) -> (u32, u32) {
+    let mut min = 1000;
+    let mut max = 60000;
+    for line in login_defs.lines() {
+        let mut fields = line.split_whitespace();
+        let (key, value) = match (fields.next(), fields.next()) {
+            (Some(key), Some(value)) => (key, value),
+            _ => continue,
+        };
+        match (key, value.parse()) {
+            ("UID_MIN", Ok(v)) => min = v,
+            ("UID_MAX", Ok(v)) => max = v,
+            _ => (),
+        }
+    }
+    (min, max)
+}
+
+fn parse_passwd_line(line: &str, min_uid: u32, max_uid: u32) -> Option<UserEntry> {
+    let fields: Vec<&str> = line.split(':').collect();
+    if fields.len() != 7 {
+        return None;
+    }
+    let uid: u32 = fields[2].parse().ok()?;
+    if uid < min_uid || uid > max_uid {
+        return None;
+    }
+    if fields[6].ends_with("/nologin") || fields[6].ends_with("/false") {
+        return None;
+    }
+    let name = fields[0].to_string();
+    let display = match fields[4].split(',').next() {
+        Some(full_name) if !full_name.is_empty() => full_name.to_string(),
+        _ => name.clone(),
+    };
+    Some(UserEntry { name, display })
+}
+
+/// Enumerate the regular users of the system that have a login shell.
+pub fn get_users() -> Vec<UserEntry> {
+    let (min_uid, max_uid) = match fs::read_to_string("/etc/login.defs") {
+        Ok(login_defs) => parse_uid_range(&login_defs),
+        Err(_) => parse_uid_range(""),
+    };
+    let passwd = match fs::read_to_string("/etc/passwd") {
+        Ok(passwd) => passwd,
+        Err(_) => return Vec::new(),
+    };
+    passwd
+        .lines()
+        .filter_map(|line| parse_passwd_line(line, min_uid, max_uid))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uid_range() {
+        assert_eq!(parse_uid_range(""), (1000, 60000));
+        assert_eq!(
+            parse_uid_range("# comment\nUID_MIN\t\t 500\nUID_MAX 2000\nGID_MIN 100\n"),
+            (500, 2000)
+        );
+    }
+
+    #[test]
+    fn passwd_line() {
+        let user = parse_passwd_line(
+            "john:x:1000:1000:John Doe,,,:/home/john:/bin/bash",
+            1000,
+            60000,
+        )
+        .unwrap();
+        assert_eq!(user.name, "john");
+        assert_eq!(user.display, "John Doe");
+
+        let user = parse_passwd_line("jane:x:1001:1001::/home/jane:/bin/sh", 1000, 60000).unwrap();
+        assert_eq!(user.display, "jane");
+
+        assert!(parse_passwd_line("root:x:0:0:root:/root:/bin/bash", 1000, 60000).is_none());
+        assert!(
+            parse_passwd_line("svc:x:1002:1002::/var/empty:/usr/sbin/nologin", 1000, 60000)
+                .is_none()
+        );
+        assert!(parse_passwd_line("broken:x:1003", 1000, 60000).is_none());
+    }
+}